@@ -0,0 +1,135 @@
+//! Traces a chip shaped like sp1-recursion-core's degree 3 `Poseidon2WideChip`.
+//!
+//! sp1-recursion-core is not a dependency of this crate, so the chip below reproduces the part of
+//! the recursion chip that matters for tracing: every round of the BabyBear width 16 Poseidon2
+//! permutation, with the round constants and internal diagonal the recursion chip uses. Each
+//! round adds its constants, stores the cube of every S-box input in a column and stores the
+//! state after the round's linear layer, which keeps every constraint at degree 3.
+
+use std::{array, borrow::Borrow};
+
+use air::{constant_stats, F};
+use criterion::{criterion_group, criterion_main, Criterion};
+use p3_air::{Air, AirBuilder, BaseAir};
+use p3_baby_bear::POSEIDON2_INTERNAL_MATRIX_DIAG_16_BABYBEAR_MONTY;
+use p3_field::{AbstractField, PrimeField32};
+use p3_matrix::{dense::RowMajorMatrix, Matrix};
+use sp1_core_executor::{ExecutionRecord, Program};
+use sp1_primitives::RC_16_30_U32;
+use sp1_stark::{air::MachineAir, Chip};
+
+const WIDTH: usize = 16;
+const NUM_EXTERNAL_ROUNDS: usize = 8;
+const NUM_INTERNAL_ROUNDS: usize = 13;
+const NUM_ROUNDS: usize = NUM_EXTERNAL_ROUNDS + NUM_INTERNAL_ROUNDS;
+
+/// The input, then for every external round `WIDTH` S-box cubes and `WIDTH` outputs, and for
+/// every internal round one S-box cube and `WIDTH` outputs.
+const NUM_COLS: usize = WIDTH + NUM_EXTERNAL_ROUNDS * 2 * WIDTH + NUM_INTERNAL_ROUNDS * (1 + WIDTH);
+
+fn is_external_round(round: usize) -> bool {
+    let internal = NUM_EXTERNAL_ROUNDS / 2..NUM_EXTERNAL_ROUNDS / 2 + NUM_INTERNAL_ROUNDS;
+    !internal.contains(&round)
+}
+
+/// Applies the circulant `[2, 3, 1, 1]` matrix to every block of four, then adds the sum of each
+/// lane across the blocks.
+fn external_linear_layer<E: AbstractField>(state: &mut [E; WIDTH]) {
+    for block in state.chunks_exact_mut(4) {
+        let [a, b, c, d] = array::from_fn(|i| block[i].clone());
+        let three = E::from_canonical_u32(3);
+        block[0] = a.clone().double() + b.clone() * three.clone() + c.clone() + d.clone();
+        block[1] = a.clone() + b.clone().double() + c.clone() * three.clone() + d.clone();
+        block[2] = a.clone() + b.clone() + c.clone().double() + d.clone() * three.clone();
+        block[3] = a * three + b + c + d.double();
+    }
+    let sums: [E; 4] =
+        array::from_fn(|lane| state.iter().skip(lane).step_by(4).cloned().sum::<E>());
+    for (i, x) in state.iter_mut().enumerate() {
+        *x = x.clone() + sums[i % 4].clone();
+    }
+}
+
+fn internal_linear_layer<E: AbstractField>(state: &mut [E; WIDTH]) {
+    let sum = state.iter().cloned().sum::<E>();
+    for (x, diag) in state.iter_mut().zip(POSEIDON2_INTERNAL_MATRIX_DIAG_16_BABYBEAR_MONTY) {
+        *x = x.clone() * E::from_canonical_u32(diag.as_canonical_u32()) + sum.clone();
+    }
+}
+
+#[derive(Default)]
+struct Poseidon2WideChip;
+
+impl MachineAir<F> for Poseidon2WideChip {
+    type Record = ExecutionRecord;
+
+    type Program = Program;
+
+    fn name(&self) -> String {
+        "Poseidon2Wide".to_string()
+    }
+
+    fn generate_trace(&self, _: &ExecutionRecord, _: &mut ExecutionRecord) -> RowMajorMatrix<F> {
+        unimplemented!()
+    }
+
+    fn included(&self, _: &Self::Record) -> bool {
+        true
+    }
+}
+
+impl BaseAir<F> for Poseidon2WideChip {
+    fn width(&self) -> usize {
+        NUM_COLS
+    }
+}
+
+impl<AB: AirBuilder<F = F>> Air<AB> for Poseidon2WideChip {
+    fn eval(&self, builder: &mut AB) {
+        let main = builder.main();
+        let local = main.row_slice(0);
+        let local: &[AB::Var] = (*local).borrow();
+        let mut cols = local.iter().copied();
+        let mut next = |n: usize| cols.by_ref().take(n).collect::<Vec<_>>();
+
+        let input = next(WIDTH);
+        let mut state: [AB::Expr; WIDTH] = array::from_fn(|i| input[i].into());
+        external_linear_layer(&mut state);
+
+        for round in 0..NUM_ROUNDS {
+            let constants = RC_16_30_U32[round];
+            let lanes = if is_external_round(round) { WIDTH } else { 1 };
+            let cubes = next(lanes);
+            for (i, &cube) in cubes.iter().enumerate() {
+                let x = state[i].clone() + AB::Expr::from_canonical_u32(constants[i]);
+                builder.assert_eq(cube, x.cube());
+                let cube: AB::Expr = cube.into();
+                state[i] = cube.clone() * cube * x;
+            }
+            if is_external_round(round) {
+                external_linear_layer(&mut state);
+            } else {
+                internal_linear_layer(&mut state);
+            }
+
+            let output = next(WIDTH);
+            for (x, &column) in state.iter_mut().zip(&output) {
+                builder.assert_eq(column, x.clone());
+                *x = column.into();
+            }
+        }
+    }
+}
+
+fn trace_poseidon2_wide(c: &mut Criterion) {
+    let chip = Chip::<F, _>::new(Poseidon2WideChip);
+    let stats = constant_stats(&chip).unwrap();
+    println!(
+        "Poseidon2Wide: {} F constants ({} hits), {} EF constants ({} hits)",
+        stats.f.distinct, stats.f.hits, stats.ef.distinct, stats.ef.hits
+    );
+    c.bench_function("trace Poseidon2Wide", |b| b.iter(|| constant_stats(&chip).unwrap()));
+}
+
+criterion_group!(benches, trace_poseidon2_wide);
+criterion_main!(benches);
@@ -0,0 +1,97 @@
+// Generated by sp1-symbolic for chip Program. Do not edit.
+// 4 constraints, 2 F registers, 5 EF registers.
+
+template <class Inputs, class Folder>
+__device__ __forceinline__ void eval_program(const Inputs& in, Folder& folder) {
+    bb31_t f1, f2;
+    bb31_4_t e1, e2, e3, e4, e5;
+
+    e1 = in.permutation_challenges[0];
+    e2 = bb31_4_t(bb31_t(2u));
+    e3 = e1 + e2;
+    f1 = in.preprocessed_local[0];
+    e1 = in.permutation_challenges[1];
+    e2 = e1 * f1;
+    e4 = e3 + e2;
+    e2 = e1 * e1;
+    f1 = in.preprocessed_local[1];
+    e3 = e2 * f1;
+    e5 = e4 + e3;
+    e3 = e2 * e1;
+    f1 = in.preprocessed_local[1];
+    e2 = e3 * f1;
+    e4 = e5 + e2;
+    e2 = e3 * e1;
+    f1 = in.preprocessed_local[2];
+    e3 = e2 * f1;
+    e5 = e4 + e3;
+    e3 = e2 * e1;
+    f1 = in.preprocessed_local[3];
+    e2 = e3 * f1;
+    e4 = e5 + e2;
+    e2 = e3 * e1;
+    f1 = in.preprocessed_local[4];
+    e3 = e2 * f1;
+    e5 = e4 + e3;
+    e3 = e2 * e1;
+    f1 = in.preprocessed_local[5];
+    e2 = e3 * f1;
+    e4 = e5 + e2;
+    e2 = e3 * e1;
+    f1 = in.preprocessed_local[6];
+    e3 = e2 * f1;
+    e5 = e4 + e3;
+    e3 = e2 * e1;
+    f1 = in.preprocessed_local[7];
+    e2 = e3 * f1;
+    e4 = e5 + e2;
+    e2 = e3 * e1;
+    f1 = in.preprocessed_local[8];
+    e3 = e2 * f1;
+    e5 = e4 + e3;
+    e3 = e2 * e1;
+    f1 = in.preprocessed_local[9];
+    e2 = e3 * f1;
+    e4 = e5 + e2;
+    e2 = e3 * e1;
+    f1 = in.preprocessed_local[10];
+    e3 = e2 * f1;
+    e5 = e4 + e3;
+    e3 = e2 * e1;
+    f1 = in.preprocessed_local[11];
+    e2 = e3 * f1;
+    e4 = e5 + e2;
+    e2 = e3 * e1;
+    f1 = in.preprocessed_local[12];
+    e3 = e2 * f1;
+    e5 = e4 + e3;
+    e3 = e2 * e1;
+    f1 = in.preprocessed_local[13];
+    e1 = e3 * f1;
+    e2 = e5 + e1;
+    f1 = in.main_local[0];
+    f2 = f1 * bb31_t(2013265920u);
+    e1 = bb31_4_t(f2);
+    e3 = in.permutation_local[0];
+    e1 = e2 * e3 - e1;
+    folder.assert_zero(e1);
+    e1 = in.permutation_local[0];
+    e2 = in.permutation_local[1];
+    e3 = e2 - e1;
+    f1 = in.is_first_row;
+    e1 = e3 * f1;
+    folder.assert_zero(e1);
+    e1 = in.permutation_next[1];
+    e3 = e1 - e2;
+    e1 = in.permutation_next[0];
+    e2 = e3 - e1;
+    f1 = in.is_transition;
+    e1 = e2 * f1;
+    folder.assert_zero(e1);
+    e1 = in.permutation_local[1];
+    e2 = in.local_cumulative_sum;
+    e3 = e1 - e2;
+    f1 = in.is_last_row;
+    e1 = e3 * f1;
+    folder.assert_zero(e1);
+}
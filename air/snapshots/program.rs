@@ -0,0 +1,112 @@
+// Generated by sp1-symbolic for chip Program. Do not edit.
+// 4 constraints.
+
+#[allow(clippy::too_many_arguments, unused_variables)]
+pub fn eval_program(
+    preprocessed_local: &[F],
+    preprocessed_next: &[F],
+    main_local: &[F],
+    main_next: &[F],
+    permutation_local: &[EF],
+    permutation_next: &[EF],
+    permutation_challenges: &[EF],
+    public_values: &[F],
+    local_cumulative_sum: EF,
+    global_cumulative_sum: &[F],
+    is_first_row: F,
+    is_last_row: F,
+    is_transition: F,
+    inv_zeroifier: F,
+    alpha: EF,
+) -> EF {
+    let mut acc = EF::zero();
+    let e1 = permutation_challenges[0];
+    let e2 = EF::from_base(F::from_canonical_u32(2));
+    let e3 = e1 + e2;
+    let f1 = preprocessed_local[0];
+    let e1 = permutation_challenges[1];
+    let e2 = e1 * f1;
+    let e4 = e3 + e2;
+    let e2 = e1 * e1;
+    let f1 = preprocessed_local[1];
+    let e3 = e2 * f1;
+    let e5 = e4 + e3;
+    let e3 = e2 * e1;
+    let f1 = preprocessed_local[1];
+    let e2 = e3 * f1;
+    let e4 = e5 + e2;
+    let e2 = e3 * e1;
+    let f1 = preprocessed_local[2];
+    let e3 = e2 * f1;
+    let e5 = e4 + e3;
+    let e3 = e2 * e1;
+    let f1 = preprocessed_local[3];
+    let e2 = e3 * f1;
+    let e4 = e5 + e2;
+    let e2 = e3 * e1;
+    let f1 = preprocessed_local[4];
+    let e3 = e2 * f1;
+    let e5 = e4 + e3;
+    let e3 = e2 * e1;
+    let f1 = preprocessed_local[5];
+    let e2 = e3 * f1;
+    let e4 = e5 + e2;
+    let e2 = e3 * e1;
+    let f1 = preprocessed_local[6];
+    let e3 = e2 * f1;
+    let e5 = e4 + e3;
+    let e3 = e2 * e1;
+    let f1 = preprocessed_local[7];
+    let e2 = e3 * f1;
+    let e4 = e5 + e2;
+    let e2 = e3 * e1;
+    let f1 = preprocessed_local[8];
+    let e3 = e2 * f1;
+    let e5 = e4 + e3;
+    let e3 = e2 * e1;
+    let f1 = preprocessed_local[9];
+    let e2 = e3 * f1;
+    let e4 = e5 + e2;
+    let e2 = e3 * e1;
+    let f1 = preprocessed_local[10];
+    let e3 = e2 * f1;
+    let e5 = e4 + e3;
+    let e3 = e2 * e1;
+    let f1 = preprocessed_local[11];
+    let e2 = e3 * f1;
+    let e4 = e5 + e2;
+    let e2 = e3 * e1;
+    let f1 = preprocessed_local[12];
+    let e3 = e2 * f1;
+    let e5 = e4 + e3;
+    let e3 = e2 * e1;
+    let f1 = preprocessed_local[13];
+    let e1 = e3 * f1;
+    let e2 = e5 + e1;
+    let f1 = main_local[0];
+    let f2 = f1 * F::from_canonical_u32(2013265920);
+    let e1 = EF::from_base(f2);
+    let e3 = permutation_local[0];
+    let e1 = e2 * e3 - e1;
+    acc = acc * alpha + e1;
+    let e1 = permutation_local[0];
+    let e2 = permutation_local[1];
+    let e3 = e2 - e1;
+    let f1 = is_first_row;
+    let e1 = e3 * f1;
+    acc = acc * alpha + e1;
+    let e1 = permutation_next[1];
+    let e3 = e1 - e2;
+    let e1 = permutation_next[0];
+    let e2 = e3 - e1;
+    let f1 = is_transition;
+    let e1 = e2 * f1;
+    acc = acc * alpha + e1;
+    let e1 = permutation_local[1];
+    let e2 = local_cumulative_sum;
+    let e3 = e1 - e2;
+    let f1 = is_last_row;
+    let e1 = e3 * f1;
+    acc = acc * alpha + e1;
+    acc
+}
@@ -0,0 +1,585 @@
+//! A textual syntax for constraint programs.
+//!
+//! Every instruction takes one line:
+//!
+//! ```text
+//! %f3 = main_local[12]           ; FAssignV
+//! %f4 = mul main_local[12], %f1  ; FMulVE
+//! %e2 = add %e1, %f4             ; EFAddEE
+//! %e2 *= %e5                     ; EMulAssignE
+//! %f5 = neg %f4                  ; FNegE
+//! %e6 = from_base %f5            ; EFFromE
+//! assert_zero %e7                ; EAssertZero
+//! f_spill[0] = spill %f3         ; FSpill
+//! %e1 = reload ef_spill[2]       ; EReload
+//! %f2 = muladd %f4, main_local[3], %f2  ; FMulAddEVE
+//! ```
+//!
+//! The fused `muladd` and `mulsub` compute `b * c + a` and `b * c - a` into `a`, so their last
+//! operand must be the destination.
+//!
+//! Constant-pool operands are written by value, as a canonical integer for F or as
+//! `[c0, c1, c2, c3]` for EF values outside the base field, and `SymbolicVarF::Constant` operands
+//! as `const(value)`. Everything after a `;` is a comment.
+
+use std::fmt::Write;
+
+use p3_field::{AbstractExtensionField, AbstractField, Field, PrimeField32};
+
+use crate::{
+    error::AsmError,
+    instruction::{Instruction32, Opcode, Operand},
+    symbolic_var_ef::SymbolicVarEF,
+    symbolic_var_f::SymbolicVarF,
+    trace::PoolIndex,
+    EF, F,
+};
+
+/// The `SymbolicVarF` variants, their names and whether they carry an index. `Constant` is
+/// written as `const(value)` instead.
+const F_VARIABLES: [(u8, &str, bool); 10] = [
+    (SymbolicVarF::PreprocessedLocal(0).variant(), "preprocessed_local", true),
+    (SymbolicVarF::PreprocessedNext(0).variant(), "preprocessed_next", true),
+    (SymbolicVarF::MainLocal(0).variant(), "main_local", true),
+    (SymbolicVarF::MainNext(0).variant(), "main_next", true),
+    (SymbolicVarF::IsFirstRow.variant(), "is_first_row", false),
+    (SymbolicVarF::IsLastRow.variant(), "is_last_row", false),
+    (SymbolicVarF::IsTransition.variant(), "is_transition", false),
+    (SymbolicVarF::PublicValue(0).variant(), "public_value", true),
+    (SymbolicVarF::GlobalCumulativeSum(0).variant(), "global_cumulative_sum", true),
+    (SymbolicVarF::InvZeroifier.variant(), "inv_zeroifier", false),
+];
+
+/// The `SymbolicVarEF` variants, their names and whether they carry an index.
+const EF_VARIABLES: [(u8, &str, bool); 5] = [
+    (SymbolicVarEF::PermutationLocal(0).variant(), "permutation_local", true),
+    (SymbolicVarEF::PermutationNext(0).variant(), "permutation_next", true),
+    (SymbolicVarEF::PermutationChallenge(0).variant(), "permutation_challenge", true),
+    (SymbolicVarEF::CumulativeSum(0).variant(), "cumulative_sum", true),
+    (SymbolicVarEF::Alpha.variant(), "alpha", false),
+];
+
+pub(crate) fn mnemonic(opcode: Opcode) -> &'static str {
+    match opcode {
+        Opcode::Empty => "nop",
+        Opcode::FAssignC
+        | Opcode::FAssignV
+        | Opcode::FAssignE
+        | Opcode::EAssignC
+        | Opcode::EAssignV
+        | Opcode::EAssignE => "",
+        Opcode::FAddVC
+        | Opcode::FAddVV
+        | Opcode::FAddVE
+        | Opcode::FAddEC
+        | Opcode::FAddEV
+        | Opcode::FAddEE
+        | Opcode::EAddVC
+        | Opcode::EAddVV
+        | Opcode::EAddVE
+        | Opcode::EAddEC
+        | Opcode::EAddEV
+        | Opcode::EAddEE
+        | Opcode::EFAddEE => "add",
+        Opcode::FSubVC
+        | Opcode::FSubVV
+        | Opcode::FSubVE
+        | Opcode::FSubEC
+        | Opcode::FSubEV
+        | Opcode::FSubEE
+        | Opcode::ESubVC
+        | Opcode::ESubVV
+        | Opcode::ESubVE
+        | Opcode::ESubEC
+        | Opcode::ESubEV
+        | Opcode::ESubEE
+        | Opcode::EFSubEE => "sub",
+        Opcode::FMulVC
+        | Opcode::FMulVV
+        | Opcode::FMulVE
+        | Opcode::FMulEC
+        | Opcode::FMulEV
+        | Opcode::FMulEE
+        | Opcode::EMulVC
+        | Opcode::EMulVV
+        | Opcode::EMulVE
+        | Opcode::EMulEC
+        | Opcode::EMulEV
+        | Opcode::EMulEE
+        | Opcode::EFMulEE => "mul",
+        Opcode::FAddAssignE | Opcode::EAddAssignE | Opcode::EFAddAssignE => "+=",
+        Opcode::FSubAssignE | Opcode::ESubAssignE | Opcode::EFSubAssignE => "-=",
+        Opcode::FMulAssignE | Opcode::EMulAssignE | Opcode::EFMulAssignE => "*=",
+        Opcode::FNegE | Opcode::ENegE => "neg",
+        Opcode::EFFromE => "from_base",
+        Opcode::EFAsBaseSlice => "as_base_slice",
+        Opcode::FAssertZero | Opcode::EAssertZero => "assert_zero",
+        Opcode::FSpill | Opcode::ESpill => "spill",
+        Opcode::FReload | Opcode::EReload => "reload",
+        Opcode::FMulAddVVE
+        | Opcode::FMulAddVEE
+        | Opcode::FMulAddEVE
+        | Opcode::FMulAddEEE
+        | Opcode::EMulAddVVE
+        | Opcode::EMulAddVEE
+        | Opcode::EMulAddEVE
+        | Opcode::EMulAddEEE => "muladd",
+        Opcode::FMulSubVVE
+        | Opcode::FMulSubVEE
+        | Opcode::FMulSubEVE
+        | Opcode::FMulSubEEE
+        | Opcode::EMulSubVVE
+        | Opcode::EMulSubVEE
+        | Opcode::EMulSubEVE
+        | Opcode::EMulSubEEE => "mulsub",
+    }
+}
+
+/// How a target spells operands. Registers, constants and spill slots are rendered the same way
+/// for every target from these pieces, so each target only supplies its own syntax.
+pub(crate) trait Syntax {
+    /// The names of the F and EF registers, which are followed by the register number.
+    const REGISTERS: (&'static str, &'static str);
+
+    fn f(value: F) -> String;
+
+    /// An EF value in the base field, from its rendered base coefficient.
+    fn ef_from_base(coeff: String) -> String;
+
+    /// An EF value from its four rendered coefficients.
+    fn ef_from_coeffs(coeffs: &[String]) -> String;
+
+    /// A variable operand, reading `SymbolicVarF::Constant` values from `f_constants`.
+    fn variable(operand: Operand, variant: u8, data: u32, f_constants: &[F]) -> String;
+}
+
+fn render_ef<S: Syntax>(value: EF) -> String {
+    let coeffs = AbstractExtensionField::<F>::as_base_slice(&value);
+    if coeffs[1..].iter().all(|c| c.is_zero()) {
+        S::ef_from_base(S::f(coeffs[0]))
+    } else {
+        let coeffs = coeffs.iter().map(|&c| S::f(c)).collect::<Vec<_>>();
+        S::ef_from_coeffs(&coeffs)
+    }
+}
+
+/// Renders an operand in the syntax `S`, reading constant operands from the pools.
+pub(crate) fn render_operand<S: Syntax>(
+    operand: Operand,
+    variant: u8,
+    data: u32,
+    f_constants: &[F],
+    ef_constants: &[EF],
+) -> String {
+    match operand {
+        Operand::None => String::new(),
+        Operand::FRegister => format!("{}{}", S::REGISTERS.0, data),
+        Operand::EFRegister => format!("{}{}", S::REGISTERS.1, data),
+        Operand::FConstant => S::f(f_constants[data as usize]),
+        Operand::EFConstant => render_ef::<S>(ef_constants[data as usize]),
+        Operand::FVariable | Operand::EFVariable => {
+            S::variable(operand, variant, data, f_constants)
+        }
+        Operand::FSpillSlot => format!("f_spill[{}]", data),
+        Operand::EFSpillSlot => format!("ef_spill[{}]", data),
+    }
+}
+
+fn render_variable(table: &[(u8, &str, bool)], variant: u8, data: u32) -> String {
+    match table.iter().find(|(v, _, _)| *v == variant) {
+        Some((_, name, true)) => format!("{}[{}]", name, data),
+        Some((_, name, false)) => name.to_string(),
+        None => format!("<invalid variant {:#04x}>", variant),
+    }
+}
+
+/// The syntax of this module.
+struct Asm;
+
+impl Syntax for Asm {
+    const REGISTERS: (&'static str, &'static str) = ("%f", "%e");
+
+    fn f(value: F) -> String {
+        value.to_string()
+    }
+
+    fn ef_from_base(coeff: String) -> String {
+        coeff
+    }
+
+    fn ef_from_coeffs(coeffs: &[String]) -> String {
+        format!("[{}]", coeffs.join(", "))
+    }
+
+    fn variable(operand: Operand, variant: u8, data: u32, f_constants: &[F]) -> String {
+        match operand {
+            Operand::FVariable => match SymbolicVarF::decode(variant, data) {
+                Some(SymbolicVarF::Constant(i)) => format!("const({})", f_constants[i as usize]),
+                _ => render_variable(&F_VARIABLES, variant, data),
+            },
+            _ => render_variable(&EF_VARIABLES, variant, data),
+        }
+    }
+}
+
+/// Renders a single instruction, reading constant operands from the pools.
+pub fn disassemble_instruction(
+    instr: &Instruction32,
+    f_constants: &[F],
+    ef_constants: &[EF],
+) -> String {
+    let Ok(opcode) = Opcode::try_from(instr.opcode) else {
+        return format!("<invalid opcode {}>", instr.opcode);
+    };
+    let (a, b, c) = opcode.operands();
+    let a = render_operand::<Asm>(a, 0, instr.a, f_constants, ef_constants);
+    let b = render_operand::<Asm>(b, instr.b_variant, instr.b, f_constants, ef_constants);
+    let c = render_operand::<Asm>(c, instr.c_variant, instr.c, f_constants, ef_constants);
+    match (opcode, mnemonic(opcode)) {
+        (Opcode::Empty, m) => m.to_string(),
+        (Opcode::EFAsBaseSlice, m) => format!("{} {}, {}, {}", m, instr.a, instr.b, instr.c),
+        (Opcode::FAssertZero | Opcode::EAssertZero, m) => format!("{} {}", m, a),
+        (_, "") => format!("{} = {}", a, b),
+        (_, m @ ("+=" | "-=" | "*=")) => format!("{} {} {}", a, m, b),
+        (_, m @ ("muladd" | "mulsub")) => format!("{} = {} {}, {}, {}", a, m, b, c, a),
+        (_, m) if c.is_empty() => format!("{} = {} {}", a, m, b),
+        (_, m) => format!("{} = {} {}, {}", a, m, b, c),
+    }
+}
+
+/// Renders `code` one instruction per line.
+pub fn disassemble<I: Copy + Into<Instruction32>>(
+    code: &[I],
+    f_constants: &[F],
+    ef_constants: &[EF],
+) -> String {
+    let mut text = String::new();
+    for &instr in code {
+        let line = disassemble_instruction(&instr.into(), f_constants, ef_constants);
+        writeln!(text, "{}", line).unwrap();
+    }
+    text
+}
+
+/// A parsed operand, before it is matched against an opcode's operand kinds.
+#[derive(Debug, Clone, Copy)]
+enum Token {
+    FRegister(u32),
+    EFRegister(u32),
+    Number(F),
+    Ext(EF),
+    ConstVariable(F),
+    FVariable(u8, u32),
+    EFVariable(u8, u32),
+    FSpillSlot(u32),
+    EFSpillSlot(u32),
+}
+
+impl Token {
+    fn fits(&self, operand: Operand) -> bool {
+        matches!(
+            (self, operand),
+            (Token::FRegister(_), Operand::FRegister)
+                | (Token::EFRegister(_), Operand::EFRegister)
+                | (Token::Number(_), Operand::FConstant | Operand::EFConstant)
+                | (Token::Ext(_), Operand::EFConstant)
+                | (Token::ConstVariable(_), Operand::FVariable)
+                | (Token::FVariable(_, _), Operand::FVariable)
+                | (Token::EFVariable(_, _), Operand::EFVariable)
+                | (Token::FSpillSlot(_), Operand::FSpillSlot)
+                | (Token::EFSpillSlot(_), Operand::EFSpillSlot)
+        )
+    }
+}
+
+fn parse_number(text: &str) -> Result<F, String> {
+    let text = text.trim();
+    let value = text.parse::<u32>().map_err(|_| format!("invalid number `{}`", text))?;
+    if value >= F::ORDER_U32 {
+        return Err(format!("{} is not a canonical field element", value));
+    }
+    Ok(F::from_canonical_u32(value))
+}
+
+fn parse_token(text: &str) -> Result<Token, String> {
+    let text = text.trim();
+    if let Some(reg) = text.strip_prefix("%f") {
+        return reg
+            .parse()
+            .map(Token::FRegister)
+            .map_err(|_| format!("invalid register `{}`", text));
+    }
+    if let Some(reg) = text.strip_prefix("%e") {
+        return reg
+            .parse()
+            .map(Token::EFRegister)
+            .map_err(|_| format!("invalid register `{}`", text));
+    }
+    if let Some(value) = text.strip_prefix("const(").and_then(|t| t.strip_suffix(')')) {
+        return parse_number(value).map(Token::ConstVariable);
+    }
+    if let Some(coeffs) = text.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+        let coeffs = coeffs.split(',').map(parse_number).collect::<Result<Vec<_>, _>>()?;
+        if coeffs.len() != 4 {
+            return Err(format!("`{}` must have 4 coefficients", text));
+        }
+        return Ok(Token::Ext(EF::from_base_slice(&coeffs)));
+    }
+    if text.starts_with(|c: char| c.is_ascii_digit()) {
+        return parse_number(text).map(Token::Number);
+    }
+
+    let (name, index) = match text.split_once('[') {
+        Some((name, rest)) => {
+            let index =
+                rest.strip_suffix(']').ok_or_else(|| format!("missing `]` in `{}`", text))?;
+            let index = index.parse().map_err(|_| format!("invalid index in `{}`", text))?;
+            (name, Some(index))
+        }
+        None => (text, None),
+    };
+    match (name, index) {
+        ("f_spill", Some(slot)) => return Ok(Token::FSpillSlot(slot)),
+        ("ef_spill", Some(slot)) => return Ok(Token::EFSpillSlot(slot)),
+        ("f_spill" | "ef_spill", None) => return Err(format!("`{}` needs an index", name)),
+        _ => {}
+    }
+    let lookup = |table: &[(u8, &str, bool)]| {
+        table.iter().find(|(_, n, _)| *n == name).map(|&(variant, _, indexed)| (variant, indexed))
+    };
+    let (variant, indexed, is_ef) = match (lookup(&F_VARIABLES), lookup(&EF_VARIABLES)) {
+        (Some((variant, indexed)), _) => (variant, indexed, false),
+        (None, Some((variant, indexed))) => (variant, indexed, true),
+        (None, None) => return Err(format!("unknown operand `{}`", text)),
+    };
+    let data = match (indexed, index) {
+        (true, Some(index)) => index,
+        (false, None) => 0,
+        (true, None) => return Err(format!("`{}` needs an index", name)),
+        (false, Some(_)) => return Err(format!("`{}` does not take an index", name)),
+    };
+    Ok(if is_ef { Token::EFVariable(variant, data) } else { Token::FVariable(variant, data) })
+}
+
+/// The constant pools of an [`Assembly`] being built, with their indices.
+#[derive(Default)]
+struct Pools {
+    f_constants: Vec<F>,
+    ef_constants: Vec<EF>,
+    f_index: PoolIndex<F>,
+    ef_index: PoolIndex<EF>,
+}
+
+impl Pools {
+    fn f_constant(&mut self, c: F) -> u32 {
+        self.f_index.intern(&mut self.f_constants, c)
+    }
+
+    fn ef_constant(&mut self, c: EF) -> u32 {
+        self.ef_index.intern(&mut self.ef_constants, c)
+    }
+}
+
+/// Encodes `token` as an operand of kind `operand`, adding constants to the pools.
+fn encode(token: Option<Token>, operand: Operand, pools: &mut Pools) -> (u8, u32) {
+    match (token, operand) {
+        (None, _) => (0, 0),
+        (
+            Some(
+                Token::FRegister(x)
+                | Token::EFRegister(x)
+                | Token::FSpillSlot(x)
+                | Token::EFSpillSlot(x),
+            ),
+            _,
+        ) => (0, x),
+        (Some(Token::Number(x)), Operand::FConstant) => (0, pools.f_constant(x)),
+        (Some(Token::Number(x)), _) => (0, pools.ef_constant(EF::from_base(x))),
+        (Some(Token::Ext(x)), _) => (0, pools.ef_constant(x)),
+        (Some(Token::ConstVariable(x)), _) => {
+            let var = SymbolicVarF::Constant(pools.f_constant(x));
+            (var.variant(), var.data())
+        }
+        (Some(Token::FVariable(variant, x) | Token::EFVariable(variant, x)), _) => (variant, x),
+    }
+}
+
+fn assemble_line(line: &str, pools: &mut Pools) -> Result<Instruction32, String> {
+    let (mnemonic_name, a, b, c) = if line == "nop" {
+        ("nop", None, None, None)
+    } else if let Some(a) = line.strip_prefix("assert_zero ") {
+        ("assert_zero", Some(parse_token(a)?), None, None)
+    } else if let Some((m, (a, b))) = ["+=", "-=", "*="]
+        .into_iter()
+        .find_map(|m| line.split_once(&format!(" {} ", m)).map(|operands| (m, operands)))
+    {
+        (m, Some(parse_token(a)?), Some(parse_token(b)?), None)
+    } else if let Some((destination, rhs)) = line.split_once(" = ") {
+        let a = Some(parse_token(destination)?);
+        let rhs = rhs.trim();
+        match rhs.split_once(' ') {
+            Some((m @ ("add" | "sub" | "mul"), operands)) => {
+                let (b, c) = operands
+                    .split_once(',')
+                    .ok_or_else(|| format!("`{}` takes two operands", m))?;
+                (m, a, Some(parse_token(b)?), Some(parse_token(c)?))
+            }
+            Some((m @ ("muladd" | "mulsub"), operands)) => {
+                let [b, c, addend] = operands.split(',').collect::<Vec<_>>()[..] else {
+                    return Err(format!("`{}` takes three operands", m));
+                };
+                if addend.trim() != destination.trim() {
+                    return Err(format!("`{}` must accumulate into its destination", m));
+                }
+                (m, a, Some(parse_token(b)?), Some(parse_token(c)?))
+            }
+            Some((m @ ("neg" | "from_base" | "spill" | "reload"), b)) => {
+                (m, a, Some(parse_token(b)?), None)
+            }
+            _ => ("", a, Some(parse_token(rhs)?), None),
+        }
+    } else {
+        return Err(format!("cannot parse `{}`", line));
+    };
+
+    let fits = |token: Option<Token>, operand: Operand| match token {
+        Some(token) => token.fits(operand),
+        None => operand == Operand::None,
+    };
+    let opcode = Opcode::ALL
+        .into_iter()
+        .find(|&opcode| {
+            let (oa, ob, oc) = opcode.operands();
+            opcode != Opcode::EFAsBaseSlice
+                && mnemonic(opcode) == mnemonic_name
+                && fits(a, oa)
+                && fits(b, ob)
+                && fits(c, oc)
+        })
+        .ok_or_else(|| format!("no instruction matches `{}`", line))?;
+
+    let (oa, ob, oc) = opcode.operands();
+    let (_, a) = encode(a, oa, pools);
+    let (b_variant, b) = encode(b, ob, pools);
+    let (c_variant, c) = encode(c, oc, pools);
+    Ok(Instruction32 { opcode: opcode as u8, b_variant, c_variant, a, b, c })
+}
+
+/// Instructions parsed by [`assemble`], with the constant pools they index into.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Assembly {
+    pub code: Vec<Instruction32>,
+    pub f_constants: Vec<F>,
+    pub ef_constants: Vec<EF>,
+}
+
+/// Parses text in the syntax produced by [`disassemble`]. Use
+/// [`crate::optimizer::lower_to_instruction16`] to get [`crate::instruction::Instruction16`]s.
+pub fn assemble(text: &str) -> Result<Assembly, AsmError> {
+    let mut code = Vec::new();
+    let mut pools = Pools::default();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.split(';').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let instr =
+            assemble_line(line, &mut pools).map_err(|message| AsmError { line: i + 1, message })?;
+        code.push(instr);
+    }
+    Ok(Assembly { code, f_constants: pools.f_constants, ef_constants: pools.ef_constants })
+}
+
+#[cfg(test)]
+mod tests {
+    use p3_field::{AbstractExtensionField, AbstractField};
+    use sp1_core_machine::riscv::RiscvAir;
+    use sp1_stark::baby_bear_poseidon2::BabyBearPoseidon2;
+
+    use crate::{
+        codegen_cuda_eval, codegen_cuda_eval_with_budget,
+        instruction::Opcode,
+        interpreter::{interpret, ConstraintValue, InterpreterInputs},
+        optimizer::{lower_to_instruction16, RegisterBudget},
+        EvalMode, EF, F,
+    };
+
+    use super::{assemble, disassemble};
+
+    #[test]
+    fn test_assemble_hand_written_program() {
+        let text = "
+            %f1 = main_local[0]
+            %f2 = mul %f1, const(3)       ; FMulEV with a constant variable
+            %f3 = sub %f2, 6
+            %f3 = muladd %f1, main_local[0], %f3
+            %f3 = mulsub %f1, const(2), %f3
+            assert_zero %f3
+            %e1 = permutation_challenge[1]
+            %e2 = add %e1, %f3
+            %e2 *= %e1
+            %e3 = from_base %f1
+            %e4 = sub %e2, [1, 2, 3, 4]
+            assert_zero %e4
+            assert_zero %e3
+        ";
+        let assembly = assemble(text).unwrap();
+        let opcodes = assembly.code.iter().map(|instr| instr.decode_opcode()).collect::<Vec<_>>();
+        assert_eq!(
+            opcodes,
+            vec![
+                Opcode::FAssignV,
+                Opcode::FMulEV,
+                Opcode::FSubEC,
+                Opcode::FMulAddEVE,
+                Opcode::FMulSubEVE,
+                Opcode::FAssertZero,
+                Opcode::EAssignV,
+                Opcode::EFAddEE,
+                Opcode::EMulAssignE,
+                Opcode::EFFromE,
+                Opcode::ESubEC,
+                Opcode::EAssertZero,
+                Opcode::EAssertZero,
+            ]
+        );
+
+        let code = lower_to_instruction16(&assembly.code).unwrap();
+        let challenge = EF::from_base_slice(&[5, 6, 7, 8].map(F::from_canonical_u32));
+        let inputs = InterpreterInputs {
+            main_local: vec![F::from_canonical_u32(2)],
+            permutation_challenges: vec![EF::zero(), challenge],
+            ..Default::default()
+        };
+        let results = interpret(&code, &assembly.f_constants, &assembly.ef_constants, &inputs);
+        let ext = EF::from_base_slice(&[1, 2, 3, 4].map(F::from_canonical_u32));
+        assert_eq!(
+            results,
+            vec![
+                ConstraintValue::F(F::zero()),
+                ConstraintValue::EF(challenge * challenge - ext),
+                ConstraintValue::EF(EF::two()),
+            ]
+        );
+
+        let err = assemble("%f1 = muladd %f2, %f3, %f4").unwrap_err();
+        assert_eq!(err.message, "`muladd` must accumulate into its destination");
+    }
+
+    #[test]
+    fn test_round_trip_riscv_chips() {
+        let machine = RiscvAir::machine(BabyBearPoseidon2::default());
+        let budget = RegisterBudget { f: 8, ef: 4 };
+        for chip in machine.chips() {
+            for program in [
+                codegen_cuda_eval(chip).unwrap(),
+                codegen_cuda_eval_with_budget(chip, EvalMode::Constraints, budget).unwrap(),
+            ] {
+                let text = program.to_string();
+                let assembly = assemble(&text).unwrap();
+                let code = lower_to_instruction16(&assembly.code).unwrap();
+                assert_eq!(disassemble(&code, &assembly.f_constants, &assembly.ef_constants), text);
+            }
+        }
+    }
+}
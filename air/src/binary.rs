@@ -0,0 +1,366 @@
+//! The on-disk format for compiled constraint programs.
+//!
+//! All integers are little-endian. A file is laid out as:
+//!
+//! ```text
+//! magic            [u8; 4] = "SP1C"
+//! version          u32
+//! chip name        u32 length, then UTF-8 bytes
+//! instruction set  u64 hash of the opcode table and variable tags
+//! layout           u64 hash of the column widths
+//! column widths    u32 preprocessed, u32 main, u32 permutation
+//! register counts  u32 F, u32 EF
+//! code             u32 length, then 9 bytes per instruction:
+//!                  u8 opcode, u8 b_variant, u8 c_variant, u16 a, u16 b, u16 c
+//! F constants      u32 length, then one canonical u32 per element
+//! EF constants     u32 length, then four canonical u32s per element
+//! checksum         u64 FNV-1a of every preceding byte
+//! ```
+
+use std::io::{Read, Write};
+
+use p3_field::{AbstractExtensionField, AbstractField, PrimeField32};
+use sp1_stark::{air::MachineAir, Chip};
+
+use crate::{
+    error::FormatError,
+    instruction::{Instruction16, Opcode},
+    program::{ColumnWidths, ConstraintProgram},
+    symbolic_var_ef::SymbolicVarEF,
+    symbolic_var_f::SymbolicVarF,
+    EF, F,
+};
+
+pub const MAGIC: [u8; 4] = *b"SP1C";
+
+/// Bumped whenever the layout above or the meaning of the instruction set changes, in the same
+/// change that makes it.
+///
+/// 4 covers every instruction set change up to the fused multiply-add and multiply-sub opcodes,
+/// and 5 added the layout hash.
+pub const FORMAT_VERSION: u32 = 5;
+
+/// The 64-bit FNV-1a hash of `bytes`.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .fold(0xcbf29ce484222325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+/// A hash of every opcode with its operand kinds and every variable tag, used to detect programs
+/// written by a build with a different instruction set under the same [`FORMAT_VERSION`].
+pub fn instruction_set_hash() -> u64 {
+    let mut table = String::new();
+    for opcode in Opcode::ALL {
+        table += &format!("{} {:?} {:?}\n", opcode as u8, opcode, opcode.operands());
+    }
+    for variant in 0..=u8::MAX {
+        if let Some(var) = SymbolicVarF::decode(variant, 0) {
+            table += &format!("F {} {:?}\n", variant, var);
+        }
+        if let Some(var) = SymbolicVarEF::decode(variant, 0) {
+            table += &format!("EF {} {:?}\n", variant, var);
+        }
+    }
+    fnv1a(table.as_bytes())
+}
+
+/// A hash of the column widths a program was compiled for.
+pub fn layout_hash(widths: ColumnWidths) -> u64 {
+    let mut bytes = Vec::with_capacity(24);
+    for width in [widths.preprocessed, widths.main, widths.permutation] {
+        bytes.extend_from_slice(&(width as u64).to_le_bytes());
+    }
+    fnv1a(&bytes)
+}
+
+fn put_u32(buf: &mut Vec<u8>, value: usize) -> Result<(), FormatError> {
+    let value = u32::try_from(value).map_err(|_| FormatError::Malformed("length exceeds u32"))?;
+    buf.extend_from_slice(&value.to_le_bytes());
+    Ok(())
+}
+
+/// Writes `program`, compiled for the chip named `chip`, to `writer`.
+pub fn write_to(
+    writer: &mut impl Write,
+    chip: &str,
+    program: &ConstraintProgram,
+) -> Result<(), FormatError> {
+    let mut buf = Vec::new();
+    buf.extend_from_slice(&MAGIC);
+    buf.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+
+    put_u32(&mut buf, chip.len())?;
+    buf.extend_from_slice(chip.as_bytes());
+    buf.extend_from_slice(&instruction_set_hash().to_le_bytes());
+    buf.extend_from_slice(&layout_hash(program.widths()).to_le_bytes());
+    put_u32(&mut buf, program.preprocessed_width())?;
+    put_u32(&mut buf, program.main_width())?;
+    put_u32(&mut buf, program.permutation_width())?;
+    put_u32(&mut buf, program.num_f_registers())?;
+    put_u32(&mut buf, program.num_ef_registers())?;
+
+    put_u32(&mut buf, program.code().len())?;
+    for instr in program.code() {
+        buf.extend_from_slice(&[instr.opcode, instr.b_variant, instr.c_variant]);
+        buf.extend_from_slice(&instr.a.to_le_bytes());
+        buf.extend_from_slice(&instr.b.to_le_bytes());
+        buf.extend_from_slice(&instr.c.to_le_bytes());
+    }
+
+    put_u32(&mut buf, program.f_constants().len())?;
+    for f in program.f_constants() {
+        buf.extend_from_slice(&f.as_canonical_u32().to_le_bytes());
+    }
+    put_u32(&mut buf, program.ef_constants().len())?;
+    for ef in program.ef_constants() {
+        for f in AbstractExtensionField::<F>::as_base_slice(ef) {
+            buf.extend_from_slice(&f.as_canonical_u32().to_le_bytes());
+        }
+    }
+
+    let checksum = fnv1a(&buf);
+    buf.extend_from_slice(&checksum.to_le_bytes());
+    writer.write_all(&buf)?;
+    Ok(())
+}
+
+/// A cursor over the bytes of a serialized program.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], FormatError> {
+        if self.bytes.len() < len {
+            return Err(FormatError::Truncated);
+        }
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8, FormatError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, FormatError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, FormatError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, FormatError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn len(&mut self) -> Result<usize, FormatError> {
+        Ok(self.u32()? as usize)
+    }
+
+    fn f(&mut self) -> Result<F, FormatError> {
+        let value = self.u32()?;
+        if value >= F::ORDER_U32 {
+            return Err(FormatError::Malformed("field element is not canonical"));
+        }
+        Ok(F::from_canonical_u32(value))
+    }
+}
+
+/// Reads a program written by [`write_to`], returning the chip name it was compiled for.
+///
+/// Fails if the data was written by a different format version or instruction set, was
+/// corrupted, its column widths do not match their layout hash, or it does not describe a valid
+/// program.
+pub fn read_from(reader: &mut impl Read) -> Result<(String, ConstraintProgram), FormatError> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+
+    if bytes.len() < MAGIC.len() || bytes[..MAGIC.len()] != MAGIC {
+        return Err(FormatError::BadMagic);
+    }
+    let mut reader = Reader { bytes: &bytes[MAGIC.len()..] };
+    let version = reader.u32()?;
+    if version != FORMAT_VERSION {
+        return Err(FormatError::UnsupportedVersion(version));
+    }
+
+    if bytes.len() < MAGIC.len() + 4 + 8 {
+        return Err(FormatError::Truncated);
+    }
+    let (body, checksum) = bytes.split_at(bytes.len() - 8);
+    let expected = u64::from_le_bytes(checksum.try_into().unwrap());
+    let actual = fnv1a(body);
+    if expected != actual {
+        return Err(FormatError::ChecksumMismatch { expected, actual });
+    }
+    let mut reader = Reader { bytes: &body[MAGIC.len() + 4..] };
+
+    let name_len = reader.len()?;
+    let chip = String::from_utf8(reader.take(name_len)?.to_vec())
+        .map_err(|_| FormatError::Malformed("chip name is not UTF-8"))?;
+    let stored_hash = reader.u64()?;
+    if stored_hash != instruction_set_hash() {
+        return Err(FormatError::InstructionSetMismatch {
+            expected: instruction_set_hash(),
+            actual: stored_hash,
+        });
+    }
+    let stored_layout_hash = reader.u64()?;
+    let widths = ColumnWidths {
+        preprocessed: reader.len()?,
+        main: reader.len()?,
+        permutation: reader.len()?,
+    };
+    if stored_layout_hash != layout_hash(widths) {
+        return Err(FormatError::LayoutMismatch {
+            expected: layout_hash(widths),
+            actual: stored_layout_hash,
+        });
+    }
+    let num_f_registers = reader.len()?;
+    let num_ef_registers = reader.len()?;
+
+    let code = (0..reader.len()?)
+        .map(|_| {
+            Ok(Instruction16 {
+                opcode: reader.u8()?,
+                b_variant: reader.u8()?,
+                c_variant: reader.u8()?,
+                a: reader.u16()?,
+                b: reader.u16()?,
+                c: reader.u16()?,
+            })
+        })
+        .collect::<Result<Vec<_>, FormatError>>()?;
+    let f_constants = (0..reader.len()?).map(|_| reader.f()).collect::<Result<Vec<_>, _>>()?;
+    let ef_constants = (0..reader.len()?)
+        .map(|_| {
+            let base = [reader.f()?, reader.f()?, reader.f()?, reader.f()?];
+            Ok(EF::from_base_slice(&base))
+        })
+        .collect::<Result<Vec<_>, FormatError>>()?;
+    if !reader.bytes.is_empty() {
+        return Err(FormatError::Malformed("trailing bytes after the constant pools"));
+    }
+
+    let program = ConstraintProgram::new(
+        code,
+        num_f_registers,
+        num_ef_registers,
+        f_constants,
+        ef_constants,
+        widths,
+    );
+    program.validate()?;
+    Ok((chip, program))
+}
+
+/// Reads a program written by [`write_to`] and checks that it was compiled for `chip` with its
+/// current column layout.
+pub fn read_for_chip<A: MachineAir<F>>(
+    reader: &mut impl Read,
+    chip: &Chip<F, A>,
+) -> Result<ConstraintProgram, FormatError> {
+    let (name, program) = read_from(reader)?;
+    if name != chip.name() {
+        return Err(FormatError::ChipMismatch { expected: chip.name(), actual: name });
+    }
+    let expected = layout_hash(ColumnWidths::of(chip));
+    let actual = layout_hash(program.widths());
+    if expected != actual {
+        return Err(FormatError::LayoutMismatch { expected, actual });
+    }
+    Ok(program)
+}
+
+#[cfg(test)]
+mod tests {
+    use sp1_core_machine::riscv::RiscvAir;
+    use sp1_stark::{air::MachineAir, baby_bear_poseidon2::BabyBearPoseidon2};
+
+    use crate::{codegen_cuda_eval, error::FormatError};
+
+    use crate::program::ColumnWidths;
+
+    use super::{
+        fnv1a, instruction_set_hash, layout_hash, read_for_chip, read_from, write_to,
+        FORMAT_VERSION,
+    };
+
+    /// Rewrites the checksum of `bytes` after a header field was changed.
+    fn reseal(bytes: &[u8]) -> Vec<u8> {
+        let mut body = bytes[..bytes.len() - 8].to_vec();
+        let checksum = fnv1a(&body);
+        body.extend_from_slice(&checksum.to_le_bytes());
+        body
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let machine = RiscvAir::machine(BabyBearPoseidon2::default());
+        let chip = &machine.chips()[0];
+        let program = codegen_cuda_eval(chip).unwrap();
+
+        let mut bytes = Vec::new();
+        write_to(&mut bytes, &chip.name(), &program).unwrap();
+        let (name, decoded) = read_from(&mut bytes.as_slice()).unwrap();
+        assert_eq!(name, chip.name());
+        assert_eq!(decoded, program);
+        assert_eq!(read_for_chip(&mut bytes.as_slice(), chip).unwrap(), program);
+
+        let other = &machine.chips()[1];
+        assert!(matches!(
+            read_for_chip(&mut bytes.as_slice(), other),
+            Err(FormatError::ChipMismatch { .. })
+        ));
+
+        let mut corrupted = bytes.clone();
+        corrupted[20] ^= 1;
+        assert!(matches!(
+            read_from(&mut corrupted.as_slice()),
+            Err(FormatError::ChecksumMismatch { .. })
+        ));
+
+        let mut future = bytes.clone();
+        future[4..8].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            read_from(&mut future.as_slice()),
+            Err(FormatError::UnsupportedVersion(v)) if v == FORMAT_VERSION + 1
+        ));
+
+        // A program from a build with a different instruction set, with a valid checksum.
+        let hash_offset = 12 + chip.name().len();
+        let mut other_set = bytes.clone();
+        other_set[hash_offset..hash_offset + 8].copy_from_slice(&1u64.to_le_bytes());
+        assert!(matches!(
+            read_from(&mut reseal(&other_set).as_slice()),
+            Err(FormatError::InstructionSetMismatch { expected, actual: 1 })
+                if expected == instruction_set_hash()
+        ));
+
+        // Column widths that do not match the layout hash they were written with.
+        let widths = decoded.widths();
+        let main_offset = hash_offset + 20;
+        let mut wider = bytes.clone();
+        wider[main_offset..main_offset + 4]
+            .copy_from_slice(&(widths.main as u32 + 1).to_le_bytes());
+        let expected = layout_hash(ColumnWidths { main: widths.main + 1, ..widths });
+        assert!(matches!(
+            read_from(&mut reseal(&wider).as_slice()),
+            Err(FormatError::LayoutMismatch { expected: e, actual })
+                if e == expected && actual == layout_hash(widths)
+        ));
+
+        assert!(matches!(read_from(&mut &bytes[1..]), Err(FormatError::BadMagic)));
+    }
+
+    /// Changing the instruction set changes the meaning of stored programs, so it must bump
+    /// [`FORMAT_VERSION`] in the same change. Update both values here together.
+    #[test]
+    fn test_instruction_set_is_versioned() {
+        assert_eq!((FORMAT_VERSION, instruction_set_hash()), (5, 0x3ee0e7bf3a6ce40c));
+    }
+}
@@ -0,0 +1,270 @@
+//! Emits a constraint program as a Rust function for CPU evaluation.
+//!
+//! The emitted function takes every input of the program as a slice or value, named after the
+//! fields of [`crate::interpreter::InterpreterInputs`], and folds the constraints with `alpha`
+//! the same way sp1-stark's folders do:
+//!
+//! ```text
+//! #[allow(clippy::too_many_arguments, unused_variables)]
+//! pub fn eval_add_sub(
+//!     preprocessed_local: &[F],
+//!     ...
+//!     alpha: EF,
+//! ) -> EF {
+//!     let mut acc = EF::zero();
+//!     let f1 = main_local[3];
+//!     let f2 = f1 * F::from_canonical_u32(7);
+//!     acc = acc * alpha + f2;
+//!     ...
+//!     acc
+//! }
+//! ```
+//!
+//! Every register write is a new `let` binding, so the function is straight-line code without
+//! mutable locals other than the accumulator and, for programs that spill, the `f_spill` and
+//! `ef_spill` scratch arrays. The including module must have `F`, `EF`,
+//! `AbstractField` and `AbstractExtensionField` in scope, e.g. from a `build.rs` output:
+//!
+//! ```text
+//! use p3_field::{AbstractExtensionField, AbstractField};
+//! use air::{EF, F};
+//! include!(concat!(env!("OUT_DIR"), "/add_sub.rs"));
+//! ```
+
+use std::fmt::Write;
+
+use p3_field::PrimeField32;
+
+use crate::{
+    asm::{mnemonic, render_operand, Syntax},
+    cuda::{function_name, render_variable},
+    instruction::{Instruction32, Opcode, Operand},
+    program::ConstraintProgram,
+    EF, F,
+};
+
+/// The parameters of the emitted function, in order.
+const PARAMETERS: [(&str, &str); 15] = [
+    ("preprocessed_local", "&[F]"),
+    ("preprocessed_next", "&[F]"),
+    ("main_local", "&[F]"),
+    ("main_next", "&[F]"),
+    ("permutation_local", "&[EF]"),
+    ("permutation_next", "&[EF]"),
+    ("permutation_challenges", "&[EF]"),
+    ("public_values", "&[F]"),
+    ("local_cumulative_sum", "EF"),
+    ("global_cumulative_sum", "&[F]"),
+    ("is_first_row", "F"),
+    ("is_last_row", "F"),
+    ("is_transition", "F"),
+    ("inv_zeroifier", "F"),
+    ("alpha", "EF"),
+];
+
+/// The syntax of Rust.
+struct Rust;
+
+impl Syntax for Rust {
+    const REGISTERS: (&'static str, &'static str) = ("f", "e");
+
+    fn f(value: F) -> String {
+        format!("F::from_canonical_u32({})", value.as_canonical_u32())
+    }
+
+    fn ef_from_base(coeff: String) -> String {
+        format!("EF::from_base({})", coeff)
+    }
+
+    fn ef_from_coeffs(coeffs: &[String]) -> String {
+        format!("EF::from_base_slice(&[{}])", coeffs.join(", "))
+    }
+
+    fn variable(operand: Operand, variant: u8, data: u32, f_constants: &[F]) -> String {
+        render_variable(operand, variant, data, "", |i| Self::f(f_constants[i as usize]))
+    }
+}
+
+/// Renders a single instruction as a Rust statement, or `None` for `Empty`.
+fn emit_instruction(
+    instr: &Instruction32,
+    f_constants: &[F],
+    ef_constants: &[EF],
+) -> Option<String> {
+    let opcode = instr.decode_opcode();
+    let (a, b, c) = opcode.operands();
+    let a = render_operand::<Rust>(a, 0, instr.a, f_constants, ef_constants);
+    let b = render_operand::<Rust>(b, instr.b_variant, instr.b, f_constants, ef_constants);
+    let c = render_operand::<Rust>(c, instr.c_variant, instr.c, f_constants, ef_constants);
+    let statement = match (opcode, mnemonic(opcode)) {
+        (Opcode::Empty, _) => return None,
+        (Opcode::EFAsBaseSlice, _) => unreachable!("EFAsBaseSlice is rejected by validate"),
+        (Opcode::FAssertZero | Opcode::EAssertZero, _) => format!("acc = acc * alpha + {};", a),
+        (Opcode::EFFromE, _) => format!("let {} = EF::from_base({});", a, b),
+        (_, "spill") => format!("{} = {};", a, b),
+        (_, "" | "reload") => format!("let {} = {};", a, b),
+        (_, "neg") => format!("let {} = -{};", a, b),
+        (_, "add") => format!("let {} = {} + {};", a, b, c),
+        (_, "sub") => format!("let {} = {} - {};", a, b, c),
+        (_, "mul") => format!("let {} = {} * {};", a, b, c),
+        (_, "muladd") => format!("let {} = {} * {} + {};", a, b, c, a),
+        (_, "mulsub") => format!("let {} = {} * {} - {};", a, b, c, a),
+        (_, op) => format!("let {} = {} {} {};", a, a, &op[..1], b),
+    };
+    Some(statement)
+}
+
+/// Emits `program`, compiled for the chip named `chip`, as a Rust function named
+/// [`function_name`]`(chip)` that returns the constraints folded with `alpha`. `program` must
+/// pass [`ConstraintProgram::validate`].
+pub fn emit_rust<I: Copy + Into<Instruction32>>(
+    chip: &str,
+    program: &ConstraintProgram<I>,
+) -> String {
+    let mut source = String::new();
+    writeln!(source, "// Generated by sp1-symbolic for chip {}. Do not edit.", chip).unwrap();
+    writeln!(source, "// {} constraints.", program.num_constraints()).unwrap();
+    writeln!(source).unwrap();
+    writeln!(source, "#[allow(clippy::too_many_arguments, unused_variables)]").unwrap();
+    writeln!(source, "pub fn {}(", function_name(chip)).unwrap();
+    for (name, ty) in PARAMETERS {
+        writeln!(source, "    {}: {},", name, ty).unwrap();
+    }
+    writeln!(source, ") -> EF {{").unwrap();
+    writeln!(source, "    let mut acc = EF::zero();").unwrap();
+    if program.num_f_spill_slots() > 0 {
+        let slots = program.num_f_spill_slots();
+        writeln!(source, "    let mut f_spill = [F::zero(); {}];", slots).unwrap();
+    }
+    if program.num_ef_spill_slots() > 0 {
+        let slots = program.num_ef_spill_slots();
+        writeln!(source, "    let mut ef_spill = [EF::zero(); {}];", slots).unwrap();
+    }
+    for &instr in program.code() {
+        let statement =
+            emit_instruction(&instr.into(), program.f_constants(), program.ef_constants());
+        if let Some(statement) = statement {
+            writeln!(source, "    {}", statement).unwrap();
+        }
+    }
+    writeln!(source, "    acc").unwrap();
+    writeln!(source, "}}").unwrap();
+    source
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use sp1_core_machine::riscv::RiscvAir;
+    use sp1_stark::{air::MachineAir, baby_bear_poseidon2::BabyBearPoseidon2};
+
+    use crate::{
+        asm::assemble,
+        codegen_cuda_eval,
+        harness::{eval_folded_constraints, random_inputs},
+        program::{ColumnWidths, ConstraintProgram},
+        EF,
+    };
+
+    use super::emit_rust;
+
+    #[test]
+    fn test_emit_in_place_constants_spills_and_fused_ops() {
+        let assembly = assemble(
+            "
+            %f1 = main_next[2]
+            %e1 = from_base %f1
+            %e2 = add %e1, [1, 2, 3, 4]
+            %e2 *= %e1
+            %e2 -= %f1
+            %e2 = mulsub %e1, %e1, %e2
+            f_spill[0] = spill %f1
+            %f1 = reload f_spill[0]
+            assert_zero %e2
+            ",
+        )
+        .unwrap();
+        let program = ConstraintProgram::new(
+            assembly.code,
+            2,
+            3,
+            assembly.f_constants,
+            assembly.ef_constants,
+            ColumnWidths { preprocessed: 0, main: 3, permutation: 0 },
+        );
+        let source = emit_rust("Example", &program);
+        let body =
+            source.lines().skip_while(|line| !line.starts_with(") -> EF")).collect::<Vec<_>>();
+        assert_eq!(
+            body,
+            vec![
+                ") -> EF {",
+                "    let mut acc = EF::zero();",
+                "    let mut f_spill = [F::zero(); 1];",
+                "    let f1 = main_next[2];",
+                "    let e1 = EF::from_base(f1);",
+                "    let e2 = e1 + EF::from_base_slice(&[F::from_canonical_u32(1), \
+                 F::from_canonical_u32(2), F::from_canonical_u32(3), F::from_canonical_u32(4)]);",
+                "    let e2 = e2 * e1;",
+                "    let e2 = e2 - f1;",
+                "    let e2 = e1 * e1 - e2;",
+                "    f_spill[0] = f1;",
+                "    let f1 = f_spill[0];",
+                "    acc = acc * alpha + e2;",
+                "    acc",
+                "}",
+            ]
+        );
+    }
+
+    /// The checked-in output of `emit_rust` for the `Program` chip.
+    mod generated {
+        use p3_field::{AbstractExtensionField, AbstractField};
+
+        use crate::{EF, F};
+
+        include!("../snapshots/program.rs");
+    }
+
+    /// Compares the source emitted for the `Program` chip against the checked-in snapshot, and
+    /// the snapshot against the chip's `eval`. Run with `UPDATE_SNAPSHOTS=1` to rewrite the
+    /// snapshot after an intended change.
+    #[test]
+    fn test_emit_program_chip() {
+        let machine = RiscvAir::machine(BabyBearPoseidon2::default());
+        let chip = machine.chips().iter().find(|chip| chip.name() == "Program").unwrap();
+        let source = emit_rust(&chip.name(), &codegen_cuda_eval(chip).unwrap());
+
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("snapshots/program.rs");
+        if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+            fs::write(&path, &source).unwrap();
+        }
+        assert_eq!(source, fs::read_to_string(&path).unwrap());
+
+        let mut rng = StdRng::seed_from_u64(0xc0ffee);
+        for _ in 0..4 {
+            let inputs = random_inputs(chip, &mut rng);
+            let alpha = rng.gen::<EF>();
+            let actual = generated::eval_program(
+                &inputs.preprocessed_local,
+                &inputs.preprocessed_next,
+                &inputs.main_local,
+                &inputs.main_next,
+                &inputs.permutation_local,
+                &inputs.permutation_next,
+                &inputs.permutation_challenges,
+                &inputs.public_values,
+                inputs.local_cumulative_sum,
+                &inputs.global_cumulative_sum,
+                inputs.is_first_row,
+                inputs.is_last_row,
+                inputs.is_transition,
+                inputs.inv_zeroifier,
+                alpha,
+            );
+            assert_eq!(actual, eval_folded_constraints(chip, &inputs, alpha));
+        }
+    }
+}
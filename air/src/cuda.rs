@@ -0,0 +1,387 @@
+//! Emits a constraint program as straight-line CUDA C++.
+//!
+//! The interpreter kernel decodes one instruction at a time. For hot chips it is faster to compile
+//! a kernel specialized to the chip, with every register as a local and every column read as a
+//! direct index. The emitted function looks like:
+//!
+//! ```text
+//! template <class Inputs, class Folder>
+//! __device__ __forceinline__ void eval_add_sub(const Inputs& in, Folder& folder) {
+//!     bb31_t f1, f2;
+//!     bb31_4_t e1;
+//!
+//!     f1 = in.main_local[3];
+//!     f2 = f1 * bb31_t(7u);
+//!     folder.assert_zero(f2);
+//!     ...
+//! }
+//! ```
+//!
+//! Programs that spill also declare `bb31_t f_spill[N]` and `bb31_4_t ef_spill[N]` scratch arrays,
+//! which the compiler places in local memory.
+//!
+//! `Inputs` must have the members of [`crate::interpreter::InterpreterInputs`], under the same
+//! names, and `Folder` an `assert_zero` overload for `bb31_t` and `bb31_4_t` which is called once
+//! per constraint in program order. `bb31_t(x)` must build an element from its canonical value,
+//! `bb31_4_t(x)` must embed a base element and `bb31_4_t(x0, x1, x2, x3)` build an element from
+//! its coefficients.
+
+use std::{collections::BTreeSet, fmt::Write};
+
+use p3_field::PrimeField32;
+
+use crate::{
+    asm::{mnemonic, render_operand, Syntax},
+    instruction::{Instruction32, Opcode, Operand},
+    program::ConstraintProgram,
+    symbolic_var_ef::SymbolicVarEF,
+    symbolic_var_f::SymbolicVarF,
+    EF, F,
+};
+
+/// The number of registers declared per line.
+const DECLARATIONS_PER_LINE: usize = 8;
+
+/// The name of the function emitted for the chip named `chip`, e.g. `eval_add_sub` for `AddSub`.
+pub fn function_name(chip: &str) -> String {
+    let mut name = String::from("eval");
+    let mut prev_lower = false;
+    for ch in chip.chars() {
+        if ch.is_ascii_uppercase() && prev_lower || name == "eval" {
+            name.push('_');
+        }
+        prev_lower = ch.is_ascii_lowercase() || ch.is_ascii_digit();
+        if ch.is_ascii_alphanumeric() {
+            name.push(ch.to_ascii_lowercase());
+        } else {
+            name.push('_');
+        }
+    }
+    name
+}
+
+/// Renders a variable operand as the input it reads, e.g. `main_local[3]`, with `prefix` in front
+/// of the input name. Constant variables are rendered by `constant` from their pool index.
+///
+/// Both emitters share this so their input names stay in sync with
+/// [`crate::interpreter::InterpreterInputs`].
+pub(crate) fn render_variable(
+    operand: Operand,
+    variant: u8,
+    data: u32,
+    prefix: &str,
+    constant: impl Fn(u32) -> String,
+) -> String {
+    match operand {
+        Operand::FVariable => match SymbolicVarF::decode(variant, data) {
+            Some(SymbolicVarF::Constant(i)) => constant(i),
+            Some(SymbolicVarF::PreprocessedLocal(i)) => {
+                format!("{}preprocessed_local[{}]", prefix, i)
+            }
+            Some(SymbolicVarF::PreprocessedNext(i)) => {
+                format!("{}preprocessed_next[{}]", prefix, i)
+            }
+            Some(SymbolicVarF::MainLocal(i)) => format!("{}main_local[{}]", prefix, i),
+            Some(SymbolicVarF::MainNext(i)) => format!("{}main_next[{}]", prefix, i),
+            Some(SymbolicVarF::IsFirstRow) => format!("{}is_first_row", prefix),
+            Some(SymbolicVarF::IsLastRow) => format!("{}is_last_row", prefix),
+            Some(SymbolicVarF::IsTransition) => format!("{}is_transition", prefix),
+            Some(SymbolicVarF::PublicValue(i)) => format!("{}public_values[{}]", prefix, i),
+            Some(SymbolicVarF::GlobalCumulativeSum(i)) => {
+                format!("{}global_cumulative_sum[{}]", prefix, i)
+            }
+            Some(SymbolicVarF::InvZeroifier) => format!("{}inv_zeroifier", prefix),
+            Some(SymbolicVarF::Empty) | None => {
+                panic!("invalid SymbolicVarF variant {:#04x}", variant)
+            }
+        },
+        Operand::EFVariable => match SymbolicVarEF::decode(variant, data) {
+            Some(SymbolicVarEF::PermutationLocal(i)) => {
+                format!("{}permutation_local[{}]", prefix, i)
+            }
+            Some(SymbolicVarEF::PermutationNext(i)) => format!("{}permutation_next[{}]", prefix, i),
+            Some(SymbolicVarEF::PermutationChallenge(i)) => {
+                format!("{}permutation_challenges[{}]", prefix, i)
+            }
+            Some(SymbolicVarEF::CumulativeSum(_)) => format!("{}local_cumulative_sum", prefix),
+            Some(SymbolicVarEF::Alpha) => format!("{}alpha", prefix),
+            Some(SymbolicVarEF::Empty) | None => {
+                panic!("invalid SymbolicVarEF variant {:#04x}", variant)
+            }
+        },
+        _ => unreachable!("{:?} is not a variable operand", operand),
+    }
+}
+
+/// The syntax of CUDA C++.
+struct Cuda;
+
+impl Syntax for Cuda {
+    const REGISTERS: (&'static str, &'static str) = ("f", "e");
+
+    fn f(value: F) -> String {
+        format!("bb31_t({}u)", value.as_canonical_u32())
+    }
+
+    fn ef_from_base(coeff: String) -> String {
+        format!("bb31_4_t({})", coeff)
+    }
+
+    fn ef_from_coeffs(coeffs: &[String]) -> String {
+        format!("bb31_4_t({})", coeffs.join(", "))
+    }
+
+    fn variable(operand: Operand, variant: u8, data: u32, f_constants: &[F]) -> String {
+        render_variable(operand, variant, data, "in.", |i| Self::f(f_constants[i as usize]))
+    }
+}
+
+/// Renders a single instruction as a C++ statement, or `None` for `Empty`.
+fn emit_instruction(
+    instr: &Instruction32,
+    f_constants: &[F],
+    ef_constants: &[EF],
+) -> Option<String> {
+    let opcode = instr.decode_opcode();
+    let (a, b, c) = opcode.operands();
+    let a = render_operand::<Cuda>(a, 0, instr.a, f_constants, ef_constants);
+    let b = render_operand::<Cuda>(b, instr.b_variant, instr.b, f_constants, ef_constants);
+    let c = render_operand::<Cuda>(c, instr.c_variant, instr.c, f_constants, ef_constants);
+    let statement = match (opcode, mnemonic(opcode)) {
+        (Opcode::Empty, _) => return None,
+        (Opcode::EFAsBaseSlice, _) => unreachable!("EFAsBaseSlice is rejected by validate"),
+        (Opcode::FAssertZero | Opcode::EAssertZero, _) => format!("folder.assert_zero({});", a),
+        (Opcode::EFFromE, _) => format!("{} = bb31_4_t({});", a, b),
+        (_, "" | "spill" | "reload") => format!("{} = {};", a, b),
+        (_, "neg") => format!("{} = -{};", a, b),
+        (_, "add") => format!("{} = {} + {};", a, b, c),
+        (_, "sub") => format!("{} = {} - {};", a, b, c),
+        (_, "mul") => format!("{} = {} * {};", a, b, c),
+        (_, "muladd") => format!("{} = {} * {} + {};", a, b, c, a),
+        (_, "mulsub") => format!("{} = {} * {} - {};", a, b, c, a),
+        (_, op) => format!("{} {} {};", a, op, b),
+    };
+    Some(statement)
+}
+
+/// Declares `registers` as locals of type `ty`, a few per line.
+fn declare(source: &mut String, ty: &str, prefix: char, registers: &BTreeSet<u32>) {
+    let names = registers.iter().map(|r| format!("{}{}", prefix, r)).collect::<Vec<_>>();
+    for chunk in names.chunks(DECLARATIONS_PER_LINE) {
+        writeln!(source, "    {} {};", ty, chunk.join(", ")).unwrap();
+    }
+}
+
+/// Emits `program`, compiled for the chip named `chip`, as a CUDA C++ device function named
+/// [`function_name`]`(chip)`. `program` must pass [`ConstraintProgram::validate`].
+pub fn emit_cuda<I: Copy + Into<Instruction32>>(
+    chip: &str,
+    program: &ConstraintProgram<I>,
+) -> String {
+    let code = program.code().iter().map(|&instr| instr.into()).collect::<Vec<Instruction32>>();
+
+    let mut f_registers = BTreeSet::new();
+    let mut ef_registers = BTreeSet::new();
+    for instr in &code {
+        let (a, b, c) = instr.decode_opcode().operands();
+        for (operand, data) in [(a, instr.a), (b, instr.b), (c, instr.c)] {
+            match operand {
+                Operand::FRegister => f_registers.insert(data),
+                Operand::EFRegister => ef_registers.insert(data),
+                _ => false,
+            };
+        }
+    }
+
+    let mut source = String::new();
+    writeln!(source, "// Generated by sp1-symbolic for chip {}. Do not edit.", chip).unwrap();
+    writeln!(
+        source,
+        "// {} constraints, {} F registers, {} EF registers.",
+        program.num_constraints(),
+        f_registers.len(),
+        ef_registers.len()
+    )
+    .unwrap();
+    writeln!(source).unwrap();
+    writeln!(source, "template <class Inputs, class Folder>").unwrap();
+    writeln!(
+        source,
+        "__device__ __forceinline__ void {}(const Inputs& in, Folder& folder) {{",
+        function_name(chip)
+    )
+    .unwrap();
+    declare(&mut source, "bb31_t", 'f', &f_registers);
+    declare(&mut source, "bb31_4_t", 'e', &ef_registers);
+    if program.num_f_spill_slots() > 0 {
+        writeln!(source, "    bb31_t f_spill[{}];", program.num_f_spill_slots()).unwrap();
+    }
+    if program.num_ef_spill_slots() > 0 {
+        writeln!(source, "    bb31_4_t ef_spill[{}];", program.num_ef_spill_slots()).unwrap();
+    }
+    if !f_registers.is_empty() || !ef_registers.is_empty() {
+        writeln!(source).unwrap();
+    }
+    for instr in &code {
+        if let Some(statement) =
+            emit_instruction(instr, program.f_constants(), program.ef_constants())
+        {
+            writeln!(source, "    {}", statement).unwrap();
+        }
+    }
+    writeln!(source, "}}").unwrap();
+    source
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use sp1_core_machine::riscv::RiscvAir;
+    use sp1_stark::{air::MachineAir, baby_bear_poseidon2::BabyBearPoseidon2};
+
+    use crate::{
+        asm::assemble,
+        codegen_cuda_eval,
+        program::{ColumnWidths, ConstraintProgram},
+    };
+
+    use super::{emit_cuda, function_name};
+
+    #[test]
+    fn test_function_name() {
+        assert_eq!(function_name("AddSub"), "eval_add_sub");
+        assert_eq!(function_name("Secp256k1AddAssign"), "eval_secp256k1_add_assign");
+        assert_eq!(function_name("CPU"), "eval_cpu");
+        assert_eq!(function_name("MemoryLocal"), "eval_memory_local");
+    }
+
+    #[test]
+    fn test_emit_hand_written_program() {
+        let assembly = assemble(
+            "
+            %f1 = main_local[0]
+            %f2 = mul %f1, const(3)
+            %f3 = sub %f2, main_next[1]
+            assert_zero %f3
+            %e1 = permutation_challenge[1]
+            %e2 = add %e1, %f3
+            %e2 *= %e1
+            %e3 = from_base %f1
+            %e4 = sub %e2, [1, 2, 3, 4]
+            %e4 -= %e3
+            %e5 = mul %e4, cumulative_sum[0]
+            %e5 = muladd %e4, permutation_next[0], %e5
+            assert_zero %e5
+            %f4 = neg %f1
+            %f4 += %f2
+            %f5 = add %f4, is_transition
+            assert_zero %f5
+            ",
+        )
+        .unwrap();
+        let program = ConstraintProgram::new(
+            assembly.code,
+            6,
+            6,
+            assembly.f_constants,
+            assembly.ef_constants,
+            ColumnWidths { preprocessed: 0, main: 2, permutation: 0 },
+        );
+        let expected = "\
+// Generated by sp1-symbolic for chip Example. Do not edit.
+// 3 constraints, 5 F registers, 5 EF registers.
+
+template <class Inputs, class Folder>
+__device__ __forceinline__ void eval_example(const Inputs& in, Folder& folder) {
+    bb31_t f1, f2, f3, f4, f5;
+    bb31_4_t e1, e2, e3, e4, e5;
+
+    f1 = in.main_local[0];
+    f2 = f1 * bb31_t(3u);
+    f3 = f2 - in.main_next[1];
+    folder.assert_zero(f3);
+    e1 = in.permutation_challenges[1];
+    e2 = e1 + f3;
+    e2 *= e1;
+    e3 = bb31_4_t(f1);
+    e4 = e2 - bb31_4_t(bb31_t(1u), bb31_t(2u), bb31_t(3u), bb31_t(4u));
+    e4 -= e3;
+    e5 = e4 * in.local_cumulative_sum;
+    e5 = e4 * in.permutation_next[0] + e5;
+    folder.assert_zero(e5);
+    f4 = -f1;
+    f4 += f2;
+    f5 = f4 + in.is_transition;
+    folder.assert_zero(f5);
+}
+";
+        assert_eq!(emit_cuda("Example", &program), expected);
+    }
+
+    /// Compares the source emitted for a real chip against the checked-in snapshot. Run with
+    /// `UPDATE_SNAPSHOTS=1` to rewrite it after an intended change.
+    #[test]
+    fn test_emit_program_chip_snapshot() {
+        let machine = RiscvAir::machine(BabyBearPoseidon2::default());
+        let chip = machine.chips().iter().find(|chip| chip.name() == "Program").unwrap();
+        let source = emit_cuda(&chip.name(), &codegen_cuda_eval(chip).unwrap());
+
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("snapshots/program.cu");
+        if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+            fs::write(&path, &source).unwrap();
+        }
+        assert_eq!(source, fs::read_to_string(&path).unwrap());
+    }
+
+    #[test]
+    fn test_emit_spills() {
+        let assembly = assemble(
+            "
+            %f1 = main_local[0]
+            f_spill[0] = spill %f1
+            %f1 = main_local[1]
+            %f2 = reload f_spill[0]
+            %f1 *= %f2
+            assert_zero %f1
+            %e1 = permutation_local[0]
+            ef_spill[1] = spill %e1
+            %e1 = reload ef_spill[1]
+            assert_zero %e1
+            ",
+        )
+        .unwrap();
+        let program = ConstraintProgram::new(
+            assembly.code,
+            3,
+            2,
+            assembly.f_constants,
+            assembly.ef_constants,
+            ColumnWidths { preprocessed: 0, main: 2, permutation: 1 },
+        );
+        let source = emit_cuda("Example", &program);
+        let body = source.lines().skip(4).collect::<Vec<_>>();
+        assert_eq!(
+            body,
+            vec![
+                "__device__ __forceinline__ void eval_example(const Inputs& in, Folder& folder) {",
+                "    bb31_t f1, f2;",
+                "    bb31_4_t e1;",
+                "    bb31_t f_spill[1];",
+                "    bb31_4_t ef_spill[2];",
+                "",
+                "    f1 = in.main_local[0];",
+                "    f_spill[0] = f1;",
+                "    f1 = in.main_local[1];",
+                "    f2 = f_spill[0];",
+                "    f1 *= f2;",
+                "    folder.assert_zero(f1);",
+                "    e1 = in.permutation_local[0];",
+                "    ef_spill[1] = e1;",
+                "    e1 = ef_spill[1];",
+                "    folder.assert_zero(e1);",
+                "}",
+            ]
+        );
+    }
+}
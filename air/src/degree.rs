@@ -0,0 +1,206 @@
+//! Degree analysis of traced code.
+//!
+//! Degrees are multiples of the trace length, as in p3-uni-stark's symbolic expressions. Column
+//! reads (including the permutation columns) and the `IsFirstRow` and `IsLastRow` selectors have
+//! degree 1. `IsTransition` is `x - g^-1`, whose degree is not a multiple of the trace length, so
+//! like constants, public values, challenges and the other per-proof inputs it has degree 0.
+
+use crate::{
+    asm::mnemonic,
+    instruction::{Instruction32, Opcode, Operand},
+    symbolic_var_ef::SymbolicVarEF,
+    symbolic_var_f::SymbolicVarF,
+};
+
+/// The degree of a `SymbolicVarF` with `variant`.
+fn f_variable_degree(variant: u8) -> usize {
+    match SymbolicVarF::decode(variant, 0) {
+        Some(
+            SymbolicVarF::PreprocessedLocal(_)
+            | SymbolicVarF::PreprocessedNext(_)
+            | SymbolicVarF::MainLocal(_)
+            | SymbolicVarF::MainNext(_)
+            | SymbolicVarF::IsFirstRow
+            | SymbolicVarF::IsLastRow,
+        ) => 1,
+        _ => 0,
+    }
+}
+
+/// The degree of a `SymbolicVarEF` with `variant`.
+fn ef_variable_degree(variant: u8) -> usize {
+    match SymbolicVarEF::decode(variant, 0) {
+        Some(SymbolicVarEF::PermutationLocal(_) | SymbolicVarEF::PermutationNext(_)) => 1,
+        _ => 0,
+    }
+}
+
+/// The highest constraint degree a chip with `log_quotient_degree` can prove, e.g. 3 for a
+/// `log_quotient_degree` of 1.
+pub fn degree_limit(log_quotient_degree: usize) -> usize {
+    (1 << log_quotient_degree) + 1
+}
+
+/// The degree of every register and spill slot written by a piece of code.
+#[derive(Debug, Clone, Default)]
+pub struct Degrees {
+    f: Vec<usize>,
+    ef: Vec<usize>,
+    f_spill: Vec<usize>,
+    ef_spill: Vec<usize>,
+}
+
+impl Degrees {
+    /// Computes the degrees of the registers written by `code`, in a single forward pass.
+    pub fn of<I: Copy + Into<Instruction32>>(code: &[I]) -> Self {
+        let mut degrees = Self::default();
+        for &instr in code {
+            degrees.step(&instr.into());
+        }
+        degrees
+    }
+
+    /// The degree of F register `reg`, or 0 if it was never written.
+    pub fn f(&self, reg: u32) -> usize {
+        self.f.get(reg as usize).copied().unwrap_or(0)
+    }
+
+    /// The degree of EF register `reg`, or 0 if it was never written.
+    pub fn ef(&self, reg: u32) -> usize {
+        self.ef.get(reg as usize).copied().unwrap_or(0)
+    }
+
+    /// The degree of the `operand` of an instruction, or 0 for constants and missing operands.
+    pub fn operand(&self, operand: Operand, variant: u8, data: u32) -> usize {
+        let slot = |file: &[usize]| file.get(data as usize).copied().unwrap_or(0);
+        match operand {
+            Operand::FRegister => self.f(data),
+            Operand::EFRegister => self.ef(data),
+            Operand::FSpillSlot => slot(&self.f_spill),
+            Operand::EFSpillSlot => slot(&self.ef_spill),
+            Operand::FVariable => f_variable_degree(variant),
+            Operand::EFVariable => ef_variable_degree(variant),
+            Operand::None | Operand::FConstant | Operand::EFConstant => 0,
+        }
+    }
+
+    fn step(&mut self, instr: &Instruction32) {
+        let opcode = instr.decode_opcode();
+        let (a, b, c) = opcode.operands();
+        let a_degree = self.operand(a, 0, instr.a);
+        let b_degree = self.operand(b, instr.b_variant, instr.b);
+        let c_degree = self.operand(c, instr.c_variant, instr.c);
+        let degree = match (opcode, mnemonic(opcode)) {
+            (
+                Opcode::Empty | Opcode::EFAsBaseSlice | Opcode::FAssertZero | Opcode::EAssertZero,
+                _,
+            ) => return,
+            (_, "add" | "sub") => b_degree.max(c_degree),
+            (_, "mul") => b_degree + c_degree,
+            (_, "+=" | "-=") => a_degree.max(b_degree),
+            (_, "*=") => a_degree + b_degree,
+            (_, "muladd" | "mulsub") => a_degree.max(b_degree + c_degree),
+            _ => b_degree,
+        };
+        let file = match a {
+            Operand::FRegister => &mut self.f,
+            Operand::FSpillSlot => &mut self.f_spill,
+            Operand::EFSpillSlot => &mut self.ef_spill,
+            _ => &mut self.ef,
+        };
+        let reg = instr.a as usize;
+        if file.len() <= reg {
+            file.resize(reg + 1, 0);
+        }
+        file[reg] = degree;
+    }
+}
+
+/// The degree of every constraint of a program, checked against a limit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DegreeReport {
+    /// The degree of each assertion, in program order.
+    pub constraint_degrees: Vec<usize>,
+    /// The highest constraint degree, or 0 if there are no constraints.
+    pub max_degree: usize,
+    pub limit: usize,
+    /// The constraints whose degree exceeds `limit`, as indices into `constraint_degrees`.
+    pub over_limit: Vec<usize>,
+}
+
+impl DegreeReport {
+    pub fn is_within_limit(&self) -> bool {
+        self.over_limit.is_empty()
+    }
+}
+
+/// Computes the degree of every assertion in `code` and flags those above `limit`.
+pub fn analyze<I: Copy + Into<Instruction32>>(code: &[I], limit: usize) -> DegreeReport {
+    let mut degrees = Degrees::default();
+    let mut constraint_degrees = Vec::new();
+    for &instr in code {
+        let instr = instr.into();
+        match instr.decode_opcode() {
+            Opcode::FAssertZero => constraint_degrees.push(degrees.f(instr.a)),
+            Opcode::EAssertZero => constraint_degrees.push(degrees.ef(instr.a)),
+            _ => degrees.step(&instr),
+        }
+    }
+    let max_degree = constraint_degrees.iter().copied().max().unwrap_or(0);
+    let over_limit =
+        (0..constraint_degrees.len()).filter(|&i| constraint_degrees[i] > limit).collect();
+    DegreeReport { constraint_degrees, max_degree, limit, over_limit }
+}
+
+#[cfg(test)]
+mod tests {
+    use p3_field::AbstractField;
+
+    use crate::{
+        instruction::Instruction32, symbolic_expr_ef::SymbolicExprEF,
+        symbolic_var_ef::SymbolicVarEF, symbolic_var_f::SymbolicVarF, trace::TraceContext, F,
+    };
+
+    use super::{analyze, degree_limit, Degrees};
+
+    #[test]
+    fn test_degrees() {
+        TraceContext::reset();
+        let x = SymbolicVarF::main_local(0) * SymbolicVarF::main_next(1);
+        let y = x * SymbolicVarF::is_first_row() + F::two();
+        let z = SymbolicVarF::public_value(0) * F::two() + x;
+        let w = SymbolicExprEF::from(SymbolicVarEF::permutation_challenge(0)) * y;
+        let v = SymbolicExprEF::from(SymbolicVarEF::permutation_local(0)) * w;
+        let t = SymbolicVarF::public_value(1) + F::one();
+        TraceContext::push(Instruction32::f_mul_add_eee(t, x, y));
+        let TraceContext { code, .. } = TraceContext::take();
+
+        let degrees = Degrees::of(&code);
+        assert_eq!(degrees.f(x.0), 2);
+        assert_eq!(degrees.f(y.0), 3);
+        assert_eq!(degrees.f(z.0), 2);
+        assert_eq!(degrees.ef(w.0), 3);
+        assert_eq!(degrees.ef(v.0), 4);
+        assert_eq!(degrees.f(t.0), 5);
+    }
+
+    #[test]
+    fn test_analyze_flags_constraints_over_the_limit() {
+        TraceContext::reset();
+        let x = SymbolicVarF::main_local(0) * SymbolicVarF::main_local(1);
+        TraceContext::push(Instruction32::f_assert_zero(x));
+        let y = x * x;
+        TraceContext::push(Instruction32::f_assert_zero(y));
+        let z = SymbolicExprEF::from(SymbolicVarEF::permutation_next(0)) * x;
+        TraceContext::push(Instruction32::e_assert_zero(z));
+        let TraceContext { code, .. } = TraceContext::take();
+
+        let report = analyze(&code, degree_limit(1));
+        assert_eq!(report.constraint_degrees, vec![2, 4, 3]);
+        assert_eq!(report.max_degree, 4);
+        assert_eq!(report.limit, 3);
+        assert_eq!(report.over_limit, vec![1]);
+        assert!(!report.is_within_limit());
+        assert!(analyze(&code, degree_limit(2)).is_within_limit());
+    }
+}
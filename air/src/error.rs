@@ -0,0 +1,233 @@
+use std::fmt;
+
+use crate::instruction::{Opcode, Operand};
+
+/// One of the two register files of a compiled program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RegisterFile {
+    F,
+    EF,
+}
+
+impl fmt::Display for RegisterFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegisterFile::F => write!(f, "F"),
+            RegisterFile::EF => write!(f, "EF"),
+        }
+    }
+}
+
+/// The reason code generation failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CodegenErrorKind {
+    /// A single instruction uses more `file` registers than the register budget provides.
+    RegistersExhausted { file: RegisterFile, capacity: usize },
+    /// The chip asked for a transition window other than 2.
+    UnsupportedWindowSize(usize),
+    /// The chip used an operation the symbolic tracer cannot express.
+    UnsupportedOperation(&'static str),
+    /// Operand `operand` of instruction `index` does not fit in a 16-bit instruction.
+    OperandOutOfRange { index: usize, operand: char, value: u32 },
+    /// The chip's permutation trace does not have the width its local interactions need.
+    PermutationWidthMismatch { expected: usize, actual: usize },
+}
+
+impl fmt::Display for CodegenErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CodegenErrorKind::RegistersExhausted { file, capacity } => {
+                write!(f, "{} {} registers are too few for a single instruction", capacity, file)
+            }
+            CodegenErrorKind::UnsupportedWindowSize(size) => {
+                write!(f, "transition window of size {} is not supported", size)
+            }
+            CodegenErrorKind::UnsupportedOperation(operation) => {
+                write!(f, "{} is not supported", operation)
+            }
+            CodegenErrorKind::OperandOutOfRange { index, operand, value } => write!(
+                f,
+                "operand {} of instruction {} is {}, which does not fit in 16 bits",
+                operand, index, value
+            ),
+            CodegenErrorKind::PermutationWidthMismatch { expected, actual } => write!(
+                f,
+                "permutation trace has {} columns, but its interactions need {}",
+                actual, expected
+            ),
+        }
+    }
+}
+
+/// A code generation failure, tagged with the chip it happened in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodegenError {
+    pub chip: String,
+    pub kind: CodegenErrorKind,
+}
+
+impl fmt::Display for CodegenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "codegen failed for chip {}: {}", self.chip, self.kind)
+    }
+}
+
+impl std::error::Error for CodegenError {}
+
+/// A byte that does not encode any [`crate::instruction::Opcode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidOpcode(pub u8);
+
+impl fmt::Display for InvalidOpcode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid opcode {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidOpcode {}
+
+/// A reason a [`crate::program::ConstraintProgram`] is inconsistent with its own metadata.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProgramError {
+    /// Instruction `index` has an opcode byte that does not encode any opcode.
+    InvalidOpcode { index: usize, opcode: u8 },
+    /// Instruction `index` has an opcode that no backend can run.
+    UnsupportedOpcode { index: usize, opcode: Opcode },
+    /// Instruction `index` refers to entry `value` of something with only `len` entries.
+    OperandOutOfRange { index: usize, operand: Operand, value: u32, len: usize },
+    /// Instruction `index` encodes a variable with an unknown variant.
+    InvalidVariant { index: usize, operand: Operand, variant: u8 },
+    /// Instruction `index` reloads spill slot `slot` before anything is spilled to it.
+    ReloadBeforeSpill { index: usize, operand: Operand, slot: u32 },
+}
+
+impl fmt::Display for ProgramError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProgramError::InvalidOpcode { index, opcode } => {
+                write!(f, "instruction {} has invalid opcode {}", index, opcode)
+            }
+            ProgramError::UnsupportedOpcode { index, opcode } => {
+                write!(f, "instruction {} has unsupported opcode {:?}", index, opcode)
+            }
+            ProgramError::OperandOutOfRange { index, operand, value, len } => write!(
+                f,
+                "instruction {} reads {:?} {}, but only {} are available",
+                index, operand, value, len
+            ),
+            ProgramError::InvalidVariant { index, operand, variant } => {
+                write!(
+                    f,
+                    "instruction {} has invalid {:?} variant {:#04x}",
+                    index, operand, variant
+                )
+            }
+            ProgramError::ReloadBeforeSpill { index, operand, slot } => {
+                write!(
+                    f,
+                    "instruction {} reloads {:?} {} before it is spilled",
+                    index, operand, slot
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ProgramError {}
+
+/// A reason a serialized constraint program could not be read.
+#[derive(Debug)]
+pub enum FormatError {
+    Io(std::io::Error),
+    /// The data does not start with the program magic bytes.
+    BadMagic,
+    /// The data was written in a format version this build cannot read.
+    UnsupportedVersion(u32),
+    /// The data was corrupted after it was written.
+    ChecksumMismatch {
+        expected: u64,
+        actual: u64,
+    },
+    /// The data ends in the middle of a field.
+    Truncated,
+    /// The data contains something that cannot appear in a valid program.
+    Malformed(&'static str),
+    /// The data was written by a build with a different instruction set.
+    InstructionSetMismatch {
+        expected: u64,
+        actual: u64,
+    },
+    /// The column widths do not match the layout hash, e.g. because the program was compiled for
+    /// a different layout of the chip being loaded.
+    LayoutMismatch {
+        expected: u64,
+        actual: u64,
+    },
+    /// The program was compiled for a different chip.
+    ChipMismatch {
+        expected: String,
+        actual: String,
+    },
+    InvalidProgram(ProgramError),
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FormatError::Io(err) => write!(f, "i/o error: {}", err),
+            FormatError::BadMagic => write!(f, "not a compiled constraint program"),
+            FormatError::UnsupportedVersion(version) => {
+                write!(f, "unsupported format version {}", version)
+            }
+            FormatError::ChecksumMismatch { expected, actual } => {
+                write!(f, "checksum mismatch: expected {:#018x}, got {:#018x}", expected, actual)
+            }
+            FormatError::Truncated => write!(f, "unexpected end of data"),
+            FormatError::Malformed(what) => write!(f, "malformed program: {}", what),
+            FormatError::InstructionSetMismatch { expected, actual } => write!(
+                f,
+                "instruction set hash mismatch: expected {:#018x}, got {:#018x}",
+                expected, actual
+            ),
+            FormatError::LayoutMismatch { expected, actual } => write!(
+                f,
+                "column layout hash mismatch: expected {:#018x}, got {:#018x}",
+                expected, actual
+            ),
+            FormatError::ChipMismatch { expected, actual } => {
+                write!(f, "program was compiled for chip {}, not {}", actual, expected)
+            }
+            FormatError::InvalidProgram(err) => write!(f, "invalid program: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for FormatError {}
+
+impl From<std::io::Error> for FormatError {
+    fn from(err: std::io::Error) -> Self {
+        FormatError::Io(err)
+    }
+}
+
+impl From<ProgramError> for FormatError {
+    fn from(err: ProgramError) -> Self {
+        FormatError::InvalidProgram(err)
+    }
+}
+
+/// A line of assembly that could not be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    /// The 1-based line number.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for AsmError {}
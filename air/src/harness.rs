@@ -0,0 +1,301 @@
+use std::marker::PhantomData;
+
+use p3_air::{
+    Air, AirBuilder, AirBuilderWithPublicValues, BaseAir, ExtensionBuilder, PairBuilder,
+    PermutationAirBuilder,
+};
+use p3_field::{AbstractExtensionField, AbstractField};
+use p3_matrix::{dense::RowMajorMatrixView, stack::VerticalPair};
+use rand::Rng;
+use sp1_stark::{
+    air::{EmptyMessageBuilder, MachineAir, MultiTableAirBuilder},
+    baby_bear_poseidon2::BabyBearPoseidon2,
+    septic_curve::SepticCurve,
+    septic_digest::SepticDigest,
+    septic_extension::SepticExtension,
+    AirOpenedValues, Chip, VerifierConstraintFolder, PROOF_MAX_NUM_PVS,
+};
+
+use crate::{
+    codegen_cuda_eval,
+    interpreter::{interpret, ConstraintValue, InterpreterInputs},
+    program::NUM_PERMUTATION_CHALLENGES,
+    SymbolicProverFolder, EF, F,
+};
+
+/// A folder that evaluates a chip's constraints on concrete values and records every assertion.
+pub struct RecordingConstraintFolder<'a> {
+    pub preprocessed: VerticalPair<RowMajorMatrixView<'a, F>, RowMajorMatrixView<'a, F>>,
+    pub main: VerticalPair<RowMajorMatrixView<'a, F>, RowMajorMatrixView<'a, F>>,
+    pub perm: VerticalPair<RowMajorMatrixView<'a, EF>, RowMajorMatrixView<'a, EF>>,
+    pub perm_challenges: &'a [EF],
+    pub local_cumulative_sum: &'a EF,
+    pub global_cumulative_sum: &'a SepticDigest<F>,
+    pub is_first_row: F,
+    pub is_last_row: F,
+    pub is_transition: F,
+    pub public_values: &'a [F],
+    pub constraints: Vec<ConstraintValue>,
+}
+
+impl<'a> AirBuilder for RecordingConstraintFolder<'a> {
+    type F = F;
+    type Var = F;
+    type Expr = F;
+    type M = VerticalPair<RowMajorMatrixView<'a, F>, RowMajorMatrixView<'a, F>>;
+
+    fn main(&self) -> Self::M {
+        self.main
+    }
+
+    fn is_first_row(&self) -> Self::Expr {
+        self.is_first_row
+    }
+
+    fn is_last_row(&self) -> Self::Expr {
+        self.is_last_row
+    }
+
+    fn is_transition_window(&self, size: usize) -> Self::Expr {
+        if size == 2 {
+            self.is_transition
+        } else {
+            panic!("uni-stark only supports a window size of 2")
+        }
+    }
+
+    fn assert_zero<I: Into<Self::Expr>>(&mut self, x: I) {
+        self.constraints.push(ConstraintValue::F(x.into()));
+    }
+}
+
+impl<'a> ExtensionBuilder for RecordingConstraintFolder<'a> {
+    type EF = EF;
+    type ExprEF = EF;
+    type VarEF = EF;
+
+    fn assert_zero_ext<I>(&mut self, x: I)
+    where
+        I: Into<Self::ExprEF>,
+    {
+        self.constraints.push(ConstraintValue::EF(x.into()));
+    }
+}
+
+impl<'a> PermutationAirBuilder for RecordingConstraintFolder<'a> {
+    type MP = VerticalPair<RowMajorMatrixView<'a, EF>, RowMajorMatrixView<'a, EF>>;
+    type RandomVar = EF;
+
+    fn permutation(&self) -> Self::MP {
+        self.perm
+    }
+
+    fn permutation_randomness(&self) -> &[Self::RandomVar] {
+        self.perm_challenges
+    }
+}
+
+impl<'a> MultiTableAirBuilder<'a> for RecordingConstraintFolder<'a> {
+    type LocalSum = EF;
+    type GlobalSum = F;
+
+    fn local_cumulative_sum(&self) -> &'a Self::LocalSum {
+        self.local_cumulative_sum
+    }
+
+    fn global_cumulative_sum(&self) -> &'a SepticDigest<Self::GlobalSum> {
+        self.global_cumulative_sum
+    }
+}
+
+impl<'a> PairBuilder for RecordingConstraintFolder<'a> {
+    fn preprocessed(&self) -> Self::M {
+        self.preprocessed
+    }
+}
+
+impl<'a> AirBuilderWithPublicValues for RecordingConstraintFolder<'a> {
+    type PublicVar = F;
+
+    fn public_values(&self) -> &[Self::PublicVar] {
+        self.public_values
+    }
+}
+
+impl<'a> EmptyMessageBuilder for RecordingConstraintFolder<'a> {}
+
+/// Samples random values for every input of `chip`'s constraint program.
+pub fn random_inputs<A: MachineAir<F>>(chip: &Chip<F, A>, rng: &mut impl Rng) -> InterpreterInputs {
+    let mut f_vec = |len: usize| (0..len).map(|_| rng.gen::<F>()).collect::<Vec<_>>();
+    let preprocessed_local = f_vec(chip.preprocessed_width());
+    let preprocessed_next = f_vec(chip.preprocessed_width());
+    let main_local = f_vec(chip.width());
+    let main_next = f_vec(chip.width());
+    let public_values = f_vec(PROOF_MAX_NUM_PVS);
+    let global_cumulative_sum = f_vec(14);
+
+    let mut ef_vec = |len: usize| (0..len).map(|_| rng.gen::<EF>()).collect::<Vec<_>>();
+    let permutation_local = ef_vec(chip.permutation_width());
+    let permutation_next = ef_vec(chip.permutation_width());
+    let permutation_challenges = ef_vec(NUM_PERMUTATION_CHALLENGES);
+
+    InterpreterInputs {
+        preprocessed_local,
+        preprocessed_next,
+        main_local,
+        main_next,
+        permutation_local,
+        permutation_next,
+        permutation_challenges,
+        public_values,
+        local_cumulative_sum: rng.gen(),
+        global_cumulative_sum,
+        is_first_row: rng.gen(),
+        is_last_row: rng.gen(),
+        is_transition: rng.gen(),
+        inv_zeroifier: rng.gen(),
+        alpha: rng.gen(),
+    }
+}
+
+pub(crate) fn global_cumulative_sum<T: Copy>(values: &[T]) -> SepticDigest<T> {
+    SepticDigest(SepticCurve {
+        x: SepticExtension(core::array::from_fn(|i| values[i])),
+        y: SepticExtension(core::array::from_fn(|i| values[i + 7])),
+    })
+}
+
+/// Evaluates `chip`'s constraints on `inputs` and returns every assertion in evaluation order.
+pub fn eval_constraints<A>(chip: &Chip<F, A>, inputs: &InterpreterInputs) -> Vec<ConstraintValue>
+where
+    A: for<'a> Air<RecordingConstraintFolder<'a>> + MachineAir<F>,
+{
+    let preprocessed = AirOpenedValues {
+        local: inputs.preprocessed_local.clone(),
+        next: inputs.preprocessed_next.clone(),
+    };
+    let main = AirOpenedValues { local: inputs.main_local.clone(), next: inputs.main_next.clone() };
+    let perm = AirOpenedValues {
+        local: inputs.permutation_local.clone(),
+        next: inputs.permutation_next.clone(),
+    };
+    let global_cumulative_sum = global_cumulative_sum(&inputs.global_cumulative_sum);
+
+    let mut folder = RecordingConstraintFolder {
+        preprocessed: preprocessed.view(),
+        main: main.view(),
+        perm: perm.view(),
+        perm_challenges: &inputs.permutation_challenges,
+        local_cumulative_sum: &inputs.local_cumulative_sum,
+        global_cumulative_sum: &global_cumulative_sum,
+        is_first_row: inputs.is_first_row,
+        is_last_row: inputs.is_last_row,
+        is_transition: inputs.is_transition,
+        public_values: &inputs.public_values,
+        constraints: Vec::new(),
+    };
+    chip.eval(&mut folder);
+    folder.constraints
+}
+
+/// Folds `chip`'s constraints on `inputs` with sp1-stark's `VerifierConstraintFolder`.
+pub fn eval_folded_constraints<A>(chip: &Chip<F, A>, inputs: &InterpreterInputs, alpha: EF) -> EF
+where
+    A: for<'a> Air<VerifierConstraintFolder<'a, BabyBearPoseidon2>> + MachineAir<F>,
+{
+    let embed = |values: &[F]| values.iter().map(|&x| EF::from_base(x)).collect::<Vec<_>>();
+    let preprocessed = AirOpenedValues {
+        local: embed(&inputs.preprocessed_local),
+        next: embed(&inputs.preprocessed_next),
+    };
+    let main = AirOpenedValues { local: embed(&inputs.main_local), next: embed(&inputs.main_next) };
+    let perm = AirOpenedValues {
+        local: inputs.permutation_local.clone(),
+        next: inputs.permutation_next.clone(),
+    };
+    let global_cumulative_sum = global_cumulative_sum(&inputs.global_cumulative_sum);
+
+    let mut folder = VerifierConstraintFolder::<BabyBearPoseidon2> {
+        preprocessed: preprocessed.view(),
+        main: main.view(),
+        perm: perm.view(),
+        perm_challenges: &inputs.permutation_challenges,
+        local_cumulative_sum: &inputs.local_cumulative_sum,
+        global_cumulative_sum: &global_cumulative_sum,
+        is_first_row: EF::from_base(inputs.is_first_row),
+        is_last_row: EF::from_base(inputs.is_last_row),
+        is_transition: EF::from_base(inputs.is_transition),
+        alpha,
+        accumulator: EF::zero(),
+        public_values: &inputs.public_values,
+        _marker: PhantomData,
+    };
+    chip.eval(&mut folder);
+    folder.accumulator
+}
+
+/// Folds a list of assertions with `alpha` in the same order as sp1-stark's folders.
+pub fn fold_constraints(constraints: &[ConstraintValue], alpha: EF) -> EF {
+    constraints.iter().fold(EF::zero(), |acc, value| match *value {
+        ConstraintValue::F(f) => acc * alpha + f,
+        ConstraintValue::EF(ef) => acc * alpha + ef,
+    })
+}
+
+/// Checks that the compiled program of `chip` computes the same constraints as its `eval`.
+///
+/// The chip is evaluated on random rows with both a concrete folder and the compiled program
+/// running on the CPU interpreter, and the two must agree assertion for assertion. The folded
+/// result must also match sp1-stark's `VerifierConstraintFolder`.
+pub fn check_chip<A>(chip: &Chip<F, A>, rng: &mut impl Rng)
+where
+    A: for<'a> Air<SymbolicProverFolder<'a>>
+        + for<'a> Air<RecordingConstraintFolder<'a>>
+        + for<'a> Air<VerifierConstraintFolder<'a, BabyBearPoseidon2>>
+        + MachineAir<F>,
+{
+    let program = codegen_cuda_eval(chip).unwrap_or_else(|err| panic!("{}", err));
+    let inputs = random_inputs(chip, rng);
+
+    let expected = eval_constraints(chip, &inputs);
+    let actual = interpret(program.code(), program.f_constants(), program.ef_constants(), &inputs);
+    assert_eq!(
+        expected.len(),
+        actual.len(),
+        "chip {} recorded {} constraints but its program asserts {}",
+        chip.name(),
+        expected.len(),
+        actual.len()
+    );
+    for (i, (expected, actual)) in expected.iter().zip(actual.iter()).enumerate() {
+        assert_eq!(expected, actual, "chip {} differs at constraint {}", chip.name(), i);
+    }
+
+    let alpha = rng.gen::<EF>();
+    assert_eq!(
+        fold_constraints(&actual, alpha),
+        eval_folded_constraints(chip, &inputs, alpha),
+        "chip {} differs from the verifier folder",
+        chip.name()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+    use sp1_core_machine::{riscv::RiscvAir, utils::setup_logger};
+    use sp1_stark::baby_bear_poseidon2::BabyBearPoseidon2;
+
+    use super::check_chip;
+
+    #[test]
+    fn test_riscv_chips_match_eval() {
+        setup_logger();
+
+        let mut rng = StdRng::seed_from_u64(0xdeadbeef);
+        let machine = RiscvAir::machine(BabyBearPoseidon2::default());
+        for chip in machine.chips() {
+            check_chip(chip, &mut rng);
+        }
+    }
+}
@@ -2,15 +2,14 @@ use std::fmt::Debug;
 use std::mem::size_of;
 
 use crate::{
-    symbolic_expr_ef::SymbolicExprEF, symbolic_expr_f::SymbolicExprF,
-    symbolic_var_ef::SymbolicVarEF, symbolic_var_f::SymbolicVarF, CUDA_P3_EVAL_EF_CONSTANTS,
-    CUDA_P3_EVAL_F_CONSTANTS, EF, F,
+    error::InvalidOpcode, symbolic_expr_ef::SymbolicExprEF, symbolic_expr_f::SymbolicExprF,
+    symbolic_var_ef::SymbolicVarEF, symbolic_var_f::SymbolicVarF, trace::TraceContext, EF, F,
 };
 
 pub const INSTRUCTION_32_SIZE: usize = size_of::<Instruction32>();
 pub const INSTRUCTION_16_SIZE: usize = size_of::<Instruction16>();
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub struct Instruction32 {
     pub opcode: u8,
     pub b_variant: u8,
@@ -20,7 +19,7 @@ pub struct Instruction32 {
     pub c: u32,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(C)]
 pub struct Instruction16 {
     pub opcode: u8,
@@ -31,7 +30,7 @@ pub struct Instruction16 {
     pub c: u16,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
 pub enum Opcode {
     Empty = 0,
@@ -113,17 +112,165 @@ pub enum Opcode {
 
     FAssertZero = 59,
     EAssertZero = 60,
+
+    FSpill = 61,
+    FReload = 62,
+    ESpill = 63,
+    EReload = 64,
+
+    /// `a = b * c + a` and `a = b * c - a`, with the kinds of `b`, `c` and the accumulator `a`
+    /// in the suffix.
+    FMulAddVVE = 65,
+    FMulAddVEE = 66,
+    FMulAddEVE = 67,
+    FMulAddEEE = 68,
+
+    FMulSubVVE = 69,
+    FMulSubVEE = 70,
+    FMulSubEVE = 71,
+    FMulSubEEE = 72,
+
+    EMulAddVVE = 73,
+    EMulAddVEE = 74,
+    EMulAddEVE = 75,
+    EMulAddEEE = 76,
+
+    EMulSubVVE = 77,
+    EMulSubVEE = 78,
+    EMulSubEVE = 79,
+    EMulSubEEE = 80,
 }
 
 impl Opcode {
+    /// Every opcode, indexed by its value.
+    pub const ALL: [Opcode; 81] = [
+        Opcode::Empty,
+        Opcode::FAssignC,
+        Opcode::FAssignV,
+        Opcode::FAssignE,
+        Opcode::FAddVC,
+        Opcode::FAddVV,
+        Opcode::FAddVE,
+        Opcode::FAddEC,
+        Opcode::FAddEV,
+        Opcode::FAddEE,
+        Opcode::FAddAssignE,
+        Opcode::FSubVC,
+        Opcode::FSubVV,
+        Opcode::FSubVE,
+        Opcode::FSubEC,
+        Opcode::FSubEV,
+        Opcode::FSubEE,
+        Opcode::FSubAssignE,
+        Opcode::FMulVC,
+        Opcode::FMulVV,
+        Opcode::FMulVE,
+        Opcode::FMulEC,
+        Opcode::FMulEV,
+        Opcode::FMulEE,
+        Opcode::FMulAssignE,
+        Opcode::FNegE,
+        Opcode::EAssignC,
+        Opcode::EAssignV,
+        Opcode::EAssignE,
+        Opcode::EAddVC,
+        Opcode::EAddVV,
+        Opcode::EAddVE,
+        Opcode::EAddEC,
+        Opcode::EAddEV,
+        Opcode::EAddEE,
+        Opcode::EAddAssignE,
+        Opcode::ESubVC,
+        Opcode::ESubVV,
+        Opcode::ESubVE,
+        Opcode::ESubEC,
+        Opcode::ESubEV,
+        Opcode::ESubEE,
+        Opcode::ESubAssignE,
+        Opcode::EMulVC,
+        Opcode::EMulVV,
+        Opcode::EMulVE,
+        Opcode::EMulEC,
+        Opcode::EMulEV,
+        Opcode::EMulEE,
+        Opcode::EMulAssignE,
+        Opcode::ENegE,
+        Opcode::EFFromE,
+        Opcode::EFAddEE,
+        Opcode::EFAddAssignE,
+        Opcode::EFSubEE,
+        Opcode::EFSubAssignE,
+        Opcode::EFMulEE,
+        Opcode::EFMulAssignE,
+        Opcode::EFAsBaseSlice,
+        Opcode::FAssertZero,
+        Opcode::EAssertZero,
+        Opcode::FSpill,
+        Opcode::FReload,
+        Opcode::ESpill,
+        Opcode::EReload,
+        Opcode::FMulAddVVE,
+        Opcode::FMulAddVEE,
+        Opcode::FMulAddEVE,
+        Opcode::FMulAddEEE,
+        Opcode::FMulSubVVE,
+        Opcode::FMulSubVEE,
+        Opcode::FMulSubEVE,
+        Opcode::FMulSubEEE,
+        Opcode::EMulAddVVE,
+        Opcode::EMulAddVEE,
+        Opcode::EMulAddEVE,
+        Opcode::EMulAddEEE,
+        Opcode::EMulSubVVE,
+        Opcode::EMulSubVEE,
+        Opcode::EMulSubEVE,
+        Opcode::EMulSubEEE,
+    ];
+
     pub fn is_f_assign(&self) -> bool {
         let value = *self as u8;
-        (1..26).contains(&value) || value == 59
+        (1..26).contains(&value) || value == 59 || value == 62 || (65..73).contains(&value)
     }
 
     pub fn is_e_assign(&self) -> bool {
         let value = *self as u8;
-        (26..59).contains(&value) || value == 60
+        (26..59).contains(&value) || value == 60 || value == 64 || (73..81).contains(&value)
+    }
+
+    /// Whether the instruction reads and writes its destination `a` in place.
+    pub fn is_in_place(&self) -> bool {
+        matches!(
+            self,
+            Opcode::FAddAssignE
+                | Opcode::FSubAssignE
+                | Opcode::FMulAssignE
+                | Opcode::EAddAssignE
+                | Opcode::ESubAssignE
+                | Opcode::EMulAssignE
+                | Opcode::EFAddAssignE
+                | Opcode::EFSubAssignE
+                | Opcode::EFMulAssignE
+                | Opcode::FMulAddVVE
+                | Opcode::FMulAddVEE
+                | Opcode::FMulAddEVE
+                | Opcode::FMulAddEEE
+                | Opcode::FMulSubVVE
+                | Opcode::FMulSubVEE
+                | Opcode::FMulSubEVE
+                | Opcode::FMulSubEEE
+                | Opcode::EMulAddVVE
+                | Opcode::EMulAddVEE
+                | Opcode::EMulAddEVE
+                | Opcode::EMulAddEEE
+                | Opcode::EMulSubVVE
+                | Opcode::EMulSubVEE
+                | Opcode::EMulSubEVE
+                | Opcode::EMulSubEEE
+        )
+    }
+
+    pub fn is_assert(&self) -> bool {
+        matches!(self, Opcode::FAssertZero | Opcode::EAssertZero)
     }
 
     pub fn is_f_arg1(&self) -> bool {
@@ -147,6 +294,11 @@ impl Opcode {
                 | Opcode::EFAddAssignE
                 | Opcode::EFSubAssignE
                 | Opcode::EFMulAssignE
+                | Opcode::FSpill
+                | Opcode::FMulAddEVE
+                | Opcode::FMulAddEEE
+                | Opcode::FMulSubEVE
+                | Opcode::FMulSubEEE
         )
     }
 
@@ -162,6 +314,10 @@ impl Opcode {
                 | Opcode::EFAddEE
                 | Opcode::EFSubEE
                 | Opcode::EFMulEE
+                | Opcode::FMulAddVEE
+                | Opcode::FMulAddEEE
+                | Opcode::FMulSubVEE
+                | Opcode::FMulSubEEE
         )
     }
 
@@ -185,6 +341,11 @@ impl Opcode {
                 | Opcode::EFAddEE
                 | Opcode::EFSubEE
                 | Opcode::EFMulEE
+                | Opcode::ESpill
+                | Opcode::EMulAddEVE
+                | Opcode::EMulAddEEE
+                | Opcode::EMulSubEVE
+                | Opcode::EMulSubEEE
         )
     }
 
@@ -197,17 +358,125 @@ impl Opcode {
                 | Opcode::ESubEE
                 | Opcode::EMulVE
                 | Opcode::EMulEE
+                | Opcode::EMulAddVEE
+                | Opcode::EMulAddEEE
+                | Opcode::EMulSubVEE
+                | Opcode::EMulSubEEE
         )
     }
+
+    /// What the `a`, `b` and `c` operands of the instruction refer to.
+    pub fn operands(&self) -> (Operand, Operand, Operand) {
+        use Operand::*;
+        match self {
+            Opcode::Empty | Opcode::EFAsBaseSlice => (None, None, None),
+
+            Opcode::FAssignC => (FRegister, FConstant, None),
+            Opcode::FAssignV => (FRegister, FVariable, None),
+            Opcode::FAssignE
+            | Opcode::FAddAssignE
+            | Opcode::FSubAssignE
+            | Opcode::FMulAssignE
+            | Opcode::FNegE => (FRegister, FRegister, None),
+            Opcode::FAddVC | Opcode::FSubVC | Opcode::FMulVC => (FRegister, FVariable, FConstant),
+            Opcode::FAddVV | Opcode::FSubVV | Opcode::FMulVV => (FRegister, FVariable, FVariable),
+            Opcode::FAddVE | Opcode::FSubVE | Opcode::FMulVE => (FRegister, FVariable, FRegister),
+            Opcode::FAddEC | Opcode::FSubEC | Opcode::FMulEC => (FRegister, FRegister, FConstant),
+            Opcode::FAddEV | Opcode::FSubEV | Opcode::FMulEV => (FRegister, FRegister, FVariable),
+            Opcode::FAddEE | Opcode::FSubEE | Opcode::FMulEE => (FRegister, FRegister, FRegister),
+
+            Opcode::EAssignC => (EFRegister, EFConstant, None),
+            Opcode::EAssignV => (EFRegister, EFVariable, None),
+            Opcode::EAssignE
+            | Opcode::EAddAssignE
+            | Opcode::ESubAssignE
+            | Opcode::EMulAssignE
+            | Opcode::ENegE => (EFRegister, EFRegister, None),
+            Opcode::EAddVC | Opcode::ESubVC | Opcode::EMulVC => {
+                (EFRegister, EFVariable, EFConstant)
+            }
+            Opcode::EAddVV | Opcode::ESubVV | Opcode::EMulVV => {
+                (EFRegister, EFVariable, EFVariable)
+            }
+            Opcode::EAddVE | Opcode::ESubVE | Opcode::EMulVE => {
+                (EFRegister, EFVariable, EFRegister)
+            }
+            Opcode::EAddEC | Opcode::ESubEC | Opcode::EMulEC => {
+                (EFRegister, EFRegister, EFConstant)
+            }
+            Opcode::EAddEV | Opcode::ESubEV | Opcode::EMulEV => {
+                (EFRegister, EFRegister, EFVariable)
+            }
+            Opcode::EAddEE | Opcode::ESubEE | Opcode::EMulEE => {
+                (EFRegister, EFRegister, EFRegister)
+            }
+
+            Opcode::EFFromE
+            | Opcode::EFAddAssignE
+            | Opcode::EFSubAssignE
+            | Opcode::EFMulAssignE => (EFRegister, FRegister, None),
+            Opcode::EFAddEE | Opcode::EFSubEE | Opcode::EFMulEE => {
+                (EFRegister, EFRegister, FRegister)
+            }
+
+            Opcode::FAssertZero => (FRegister, None, None),
+            Opcode::EAssertZero => (EFRegister, None, None),
+
+            Opcode::FSpill => (FSpillSlot, FRegister, None),
+            Opcode::FReload => (FRegister, FSpillSlot, None),
+            Opcode::ESpill => (EFSpillSlot, EFRegister, None),
+            Opcode::EReload => (EFRegister, EFSpillSlot, None),
+
+            Opcode::FMulAddVVE | Opcode::FMulSubVVE => (FRegister, FVariable, FVariable),
+            Opcode::FMulAddVEE | Opcode::FMulSubVEE => (FRegister, FVariable, FRegister),
+            Opcode::FMulAddEVE | Opcode::FMulSubEVE => (FRegister, FRegister, FVariable),
+            Opcode::FMulAddEEE | Opcode::FMulSubEEE => (FRegister, FRegister, FRegister),
+            Opcode::EMulAddVVE | Opcode::EMulSubVVE => (EFRegister, EFVariable, EFVariable),
+            Opcode::EMulAddVEE | Opcode::EMulSubVEE => (EFRegister, EFVariable, EFRegister),
+            Opcode::EMulAddEVE | Opcode::EMulSubEVE => (EFRegister, EFRegister, EFVariable),
+            Opcode::EMulAddEEE | Opcode::EMulSubEEE => (EFRegister, EFRegister, EFRegister),
+        }
+    }
+}
+
+/// What an instruction operand refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    /// The operand is unused.
+    None,
+    FRegister,
+    EFRegister,
+    /// An index into the F constant pool.
+    FConstant,
+    /// An index into the EF constant pool.
+    EFConstant,
+    /// A `SymbolicVarF`, encoded by the variant byte and its data.
+    FVariable,
+    /// A `SymbolicVarEF`, encoded by the variant byte and its data.
+    EFVariable,
+    /// An index into the F spill scratch array.
+    FSpillSlot,
+    /// An index into the EF spill scratch array.
+    EFSpillSlot,
 }
 
-impl From<u8> for Opcode {
-    fn from(value: u8) -> Self {
-        unsafe { std::mem::transmute(value) }
+impl TryFrom<u8> for Opcode {
+    type Error = InvalidOpcode;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Opcode::ALL.get(value as usize).copied().ok_or(InvalidOpcode(value))
     }
 }
 
 impl Instruction32 {
+    /// Decodes the opcode byte.
+    ///
+    /// Panics on an unknown opcode, so code from outside the tracer should be checked with
+    /// [`crate::program::ConstraintProgram::validate`] first.
+    pub fn decode_opcode(&self) -> Opcode {
+        Opcode::try_from(self.opcode).unwrap_or_else(|err| panic!("{}", err))
+    }
+
     pub fn f_assign_c(a: SymbolicExprF, b: F) -> Self {
         let b = f_constant(b);
         Self { opcode: Opcode::FAssignC as u8, a: a.data(), b_variant: 0, b, c_variant: 0, c: 0 }
@@ -856,6 +1125,217 @@ impl Instruction32 {
             c: 0,
         }
     }
+
+    /// Stores physical F register `reg` to spill slot `slot`.
+    pub fn f_spill(slot: u32, reg: u32) -> Self {
+        Self { opcode: Opcode::FSpill as u8, a: slot, b_variant: 0, b: reg, c_variant: 0, c: 0 }
+    }
+
+    /// Loads spill slot `slot` into physical F register `reg`.
+    pub fn f_reload(reg: u32, slot: u32) -> Self {
+        Self { opcode: Opcode::FReload as u8, a: reg, b_variant: 0, b: slot, c_variant: 0, c: 0 }
+    }
+
+    /// Stores physical EF register `reg` to spill slot `slot`.
+    pub fn e_spill(slot: u32, reg: u32) -> Self {
+        Self { opcode: Opcode::ESpill as u8, a: slot, b_variant: 0, b: reg, c_variant: 0, c: 0 }
+    }
+
+    /// Loads spill slot `slot` into physical EF register `reg`.
+    pub fn e_reload(reg: u32, slot: u32) -> Self {
+        Self { opcode: Opcode::EReload as u8, a: reg, b_variant: 0, b: slot, c_variant: 0, c: 0 }
+    }
+
+    /// Accumulates `b * c` into `a`.
+    pub fn f_mul_add_vve(a: SymbolicExprF, b: SymbolicVarF, c: SymbolicVarF) -> Self {
+        Self {
+            opcode: Opcode::FMulAddVVE as u8,
+            a: a.data(),
+            b_variant: b.variant(),
+            b: b.data(),
+            c_variant: c.variant(),
+            c: c.data(),
+        }
+    }
+
+    pub fn f_mul_add_vee(a: SymbolicExprF, b: SymbolicVarF, c: SymbolicExprF) -> Self {
+        Self {
+            opcode: Opcode::FMulAddVEE as u8,
+            a: a.data(),
+            b_variant: b.variant(),
+            b: b.data(),
+            c_variant: c.variant(),
+            c: c.data(),
+        }
+    }
+
+    pub fn f_mul_add_eve(a: SymbolicExprF, b: SymbolicExprF, c: SymbolicVarF) -> Self {
+        Self {
+            opcode: Opcode::FMulAddEVE as u8,
+            a: a.data(),
+            b_variant: b.variant(),
+            b: b.data(),
+            c_variant: c.variant(),
+            c: c.data(),
+        }
+    }
+
+    pub fn f_mul_add_eee(a: SymbolicExprF, b: SymbolicExprF, c: SymbolicExprF) -> Self {
+        Self {
+            opcode: Opcode::FMulAddEEE as u8,
+            a: a.data(),
+            b_variant: b.variant(),
+            b: b.data(),
+            c_variant: c.variant(),
+            c: c.data(),
+        }
+    }
+
+    /// Replaces `a` with `b * c - a`.
+    pub fn f_mul_sub_vve(a: SymbolicExprF, b: SymbolicVarF, c: SymbolicVarF) -> Self {
+        Self {
+            opcode: Opcode::FMulSubVVE as u8,
+            a: a.data(),
+            b_variant: b.variant(),
+            b: b.data(),
+            c_variant: c.variant(),
+            c: c.data(),
+        }
+    }
+
+    pub fn f_mul_sub_vee(a: SymbolicExprF, b: SymbolicVarF, c: SymbolicExprF) -> Self {
+        Self {
+            opcode: Opcode::FMulSubVEE as u8,
+            a: a.data(),
+            b_variant: b.variant(),
+            b: b.data(),
+            c_variant: c.variant(),
+            c: c.data(),
+        }
+    }
+
+    pub fn f_mul_sub_eve(a: SymbolicExprF, b: SymbolicExprF, c: SymbolicVarF) -> Self {
+        Self {
+            opcode: Opcode::FMulSubEVE as u8,
+            a: a.data(),
+            b_variant: b.variant(),
+            b: b.data(),
+            c_variant: c.variant(),
+            c: c.data(),
+        }
+    }
+
+    pub fn f_mul_sub_eee(a: SymbolicExprF, b: SymbolicExprF, c: SymbolicExprF) -> Self {
+        Self {
+            opcode: Opcode::FMulSubEEE as u8,
+            a: a.data(),
+            b_variant: b.variant(),
+            b: b.data(),
+            c_variant: c.variant(),
+            c: c.data(),
+        }
+    }
+
+    pub fn e_mul_add_vve(a: SymbolicExprEF, b: SymbolicVarEF, c: SymbolicVarEF) -> Self {
+        Self {
+            opcode: Opcode::EMulAddVVE as u8,
+            a: a.data(),
+            b_variant: b.variant(),
+            b: b.data(),
+            c_variant: c.variant(),
+            c: c.data(),
+        }
+    }
+
+    pub fn e_mul_add_vee(a: SymbolicExprEF, b: SymbolicVarEF, c: SymbolicExprEF) -> Self {
+        Self {
+            opcode: Opcode::EMulAddVEE as u8,
+            a: a.data(),
+            b_variant: b.variant(),
+            b: b.data(),
+            c_variant: c.variant(),
+            c: c.data(),
+        }
+    }
+
+    pub fn e_mul_add_eve(a: SymbolicExprEF, b: SymbolicExprEF, c: SymbolicVarEF) -> Self {
+        Self {
+            opcode: Opcode::EMulAddEVE as u8,
+            a: a.data(),
+            b_variant: b.variant(),
+            b: b.data(),
+            c_variant: c.variant(),
+            c: c.data(),
+        }
+    }
+
+    pub fn e_mul_add_eee(a: SymbolicExprEF, b: SymbolicExprEF, c: SymbolicExprEF) -> Self {
+        Self {
+            opcode: Opcode::EMulAddEEE as u8,
+            a: a.data(),
+            b_variant: b.variant(),
+            b: b.data(),
+            c_variant: c.variant(),
+            c: c.data(),
+        }
+    }
+
+    pub fn e_mul_sub_vve(a: SymbolicExprEF, b: SymbolicVarEF, c: SymbolicVarEF) -> Self {
+        Self {
+            opcode: Opcode::EMulSubVVE as u8,
+            a: a.data(),
+            b_variant: b.variant(),
+            b: b.data(),
+            c_variant: c.variant(),
+            c: c.data(),
+        }
+    }
+
+    pub fn e_mul_sub_vee(a: SymbolicExprEF, b: SymbolicVarEF, c: SymbolicExprEF) -> Self {
+        Self {
+            opcode: Opcode::EMulSubVEE as u8,
+            a: a.data(),
+            b_variant: b.variant(),
+            b: b.data(),
+            c_variant: c.variant(),
+            c: c.data(),
+        }
+    }
+
+    pub fn e_mul_sub_eve(a: SymbolicExprEF, b: SymbolicExprEF, c: SymbolicVarEF) -> Self {
+        Self {
+            opcode: Opcode::EMulSubEVE as u8,
+            a: a.data(),
+            b_variant: b.variant(),
+            b: b.data(),
+            c_variant: c.variant(),
+            c: c.data(),
+        }
+    }
+
+    pub fn e_mul_sub_eee(a: SymbolicExprEF, b: SymbolicExprEF, c: SymbolicExprEF) -> Self {
+        Self {
+            opcode: Opcode::EMulSubEEE as u8,
+            a: a.data(),
+            b_variant: b.variant(),
+            b: b.data(),
+            c_variant: c.variant(),
+            c: c.data(),
+        }
+    }
+}
+
+impl From<Instruction16> for Instruction32 {
+    fn from(instr: Instruction16) -> Self {
+        Self {
+            opcode: instr.opcode,
+            b_variant: instr.b_variant,
+            c_variant: instr.c_variant,
+            a: instr.a.into(),
+            b: instr.b.into(),
+            c: instr.c.into(),
+        }
+    }
 }
 
 impl Default for Instruction32 {
@@ -864,33 +1344,69 @@ impl Default for Instruction32 {
     }
 }
 
+impl Instruction16 {
+    /// Decodes the opcode byte. Panics on an unknown opcode, like
+    /// [`Instruction32::decode_opcode`].
+    pub fn decode_opcode(&self) -> Opcode {
+        Opcode::try_from(self.opcode).unwrap_or_else(|err| panic!("{}", err))
+    }
+}
+
 impl Debug for Instruction32 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let opcode = Opcode::from(self.opcode);
+        let opcode = match Opcode::try_from(self.opcode) {
+            Ok(opcode) => format!("{:?}", opcode),
+            Err(_) => format!("<invalid {}>", self.opcode),
+        };
         write!(
             f,
-            "Instruction {{ opcode: {:?}, a: {}, b_variant: {}, b: {}, c_variant: {}, c: {} }}",
+            "Instruction {{ opcode: {}, a: {}, b_variant: {}, b: {}, c_variant: {}, c: {} }}",
             opcode, self.a, self.b_variant, self.b, self.c_variant, self.c
         )
     }
 }
 
 pub fn f_constant(c: F) -> u32 {
-    let mut tmp = CUDA_P3_EVAL_F_CONSTANTS.lock().unwrap();
-    if let Some(pos) = tmp.iter().position(|&x| x == c) {
-        pos as u32
-    } else {
-        tmp.push(c);
-        (tmp.len() - 1) as u32
-    }
+    TraceContext::with(|ctx| ctx.f_constant(c))
 }
 
 pub fn ef_constant(c: EF) -> u32 {
-    let mut tmp = CUDA_P3_EVAL_EF_CONSTANTS.lock().unwrap();
-    if let Some(pos) = tmp.iter().position(|&x| x == c) {
-        pos as u32
-    } else {
-        tmp.push(c);
-        (tmp.len() - 1) as u32
+    TraceContext::with(|ctx| ctx.ef_constant(c))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        error::InvalidOpcode, symbolic_var_ef::SymbolicVarEF, symbolic_var_f::SymbolicVarF,
+    };
+
+    use super::Opcode;
+
+    #[test]
+    fn test_opcode_try_from() {
+        for (i, opcode) in Opcode::ALL.into_iter().enumerate() {
+            assert_eq!(opcode as usize, i);
+            assert_eq!(Opcode::try_from(i as u8), Ok(opcode));
+        }
+        assert_eq!(Opcode::try_from(Opcode::ALL.len() as u8), Err(InvalidOpcode(81)));
+        assert_eq!(Opcode::try_from(u8::MAX), Err(InvalidOpcode(u8::MAX)));
+    }
+
+    #[test]
+    fn test_variable_decode() {
+        for variant in 0..=u8::MAX {
+            if let Some(var) = SymbolicVarF::decode(variant, 7) {
+                assert_eq!(var.variant(), variant);
+                assert!(var.data() == 7 || var.data() == 0);
+            }
+            if let Some(var) = SymbolicVarEF::decode(variant, 7) {
+                assert_eq!(var.variant(), variant);
+                assert!(var.data() == 7 || var.data() == 0);
+            }
+        }
+        assert_eq!(SymbolicVarF::decode(0x0B, 0), Some(SymbolicVarF::InvZeroifier));
+        assert_eq!(SymbolicVarF::decode(0x0C, 0), None);
+        assert_eq!(SymbolicVarEF::decode(0x05, 0), Some(SymbolicVarEF::Alpha));
+        assert_eq!(SymbolicVarEF::decode(0x06, 0), None);
     }
 }
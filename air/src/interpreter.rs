@@ -0,0 +1,380 @@
+use p3_field::{AbstractExtensionField, AbstractField, Field};
+
+use crate::{
+    instruction::{Instruction16, Opcode},
+    symbolic_var_ef::SymbolicVarEF,
+    symbolic_var_f::SymbolicVarF,
+    EF, F,
+};
+
+/// The concrete values of every input a constraint program can read for a single row.
+#[derive(Debug, Clone, Default)]
+pub struct InterpreterInputs {
+    pub preprocessed_local: Vec<F>,
+    pub preprocessed_next: Vec<F>,
+    pub main_local: Vec<F>,
+    pub main_next: Vec<F>,
+    pub permutation_local: Vec<EF>,
+    pub permutation_next: Vec<EF>,
+    pub permutation_challenges: Vec<EF>,
+    pub public_values: Vec<F>,
+    pub local_cumulative_sum: EF,
+    pub global_cumulative_sum: Vec<F>,
+    pub is_first_row: F,
+    pub is_last_row: F,
+    pub is_transition: F,
+    /// The inverse of the vanishing polynomial, read by quotient programs.
+    pub inv_zeroifier: F,
+    /// The challenge folded programs combine their constraints with.
+    pub alpha: EF,
+}
+
+/// The value of a single `FAssertZero` or `EAssertZero` instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConstraintValue {
+    F(F),
+    EF(EF),
+}
+
+impl ConstraintValue {
+    pub fn is_zero(&self) -> bool {
+        match self {
+            ConstraintValue::F(f) => f.is_zero(),
+            ConstraintValue::EF(ef) => ef.is_zero(),
+        }
+    }
+}
+
+/// A reference interpreter for the programs emitted by [`crate::optimizer::optimize`].
+///
+/// It mirrors the semantics of the CUDA interpreter kernel so generated code can be checked on
+/// machines without a GPU.
+pub struct Interpreter<'a> {
+    f_constants: &'a [F],
+    ef_constants: &'a [EF],
+    inputs: &'a InterpreterInputs,
+    f_registers: Vec<F>,
+    ef_registers: Vec<EF>,
+    f_spill: Vec<F>,
+    ef_spill: Vec<EF>,
+}
+
+impl<'a> Interpreter<'a> {
+    pub fn new(
+        f_constants: &'a [F],
+        ef_constants: &'a [EF],
+        inputs: &'a InterpreterInputs,
+    ) -> Self {
+        Self {
+            f_constants,
+            ef_constants,
+            inputs,
+            f_registers: Vec::new(),
+            ef_registers: Vec::new(),
+            f_spill: Vec::new(),
+            ef_spill: Vec::new(),
+        }
+    }
+
+    /// Executes `code` and returns the value of every assertion in program order.
+    ///
+    /// `code` must pass [`crate::program::ConstraintProgram::validate`]; invalid or unsupported
+    /// opcodes panic.
+    pub fn run(&mut self, code: &[Instruction16]) -> Vec<ConstraintValue> {
+        let (f_len, ef_len) = register_file_sizes(code);
+        self.f_registers = vec![F::zero(); f_len];
+        self.ef_registers = vec![EF::zero(); ef_len];
+        let (f_slots, ef_slots) = spill_slot_counts(code);
+        self.f_spill = vec![F::zero(); f_slots];
+        self.ef_spill = vec![EF::zero(); ef_slots];
+
+        let mut results = Vec::new();
+        for instr in code {
+            if let Some(result) = self.step(instr) {
+                results.push(result);
+            }
+        }
+        results
+    }
+
+    fn step(&mut self, instr: &Instruction16) -> Option<ConstraintValue> {
+        let a = instr.a as usize;
+        let b = instr.b as usize;
+        let c = instr.c as usize;
+        let f = &self.f_registers;
+        let e = &self.ef_registers;
+
+        let value = match instr.decode_opcode() {
+            Opcode::Empty => return None,
+            Opcode::FAssignC => ConstraintValue::F(self.f_constants[b]),
+            Opcode::FAssignV => ConstraintValue::F(self.f_var(instr.b_variant, b)),
+            Opcode::FAssignE => ConstraintValue::F(f[b]),
+            Opcode::FAddVC => {
+                ConstraintValue::F(self.f_var(instr.b_variant, b) + self.f_constants[c])
+            }
+            Opcode::FAddVV => {
+                ConstraintValue::F(self.f_var(instr.b_variant, b) + self.f_var(instr.c_variant, c))
+            }
+            Opcode::FAddVE => ConstraintValue::F(self.f_var(instr.b_variant, b) + f[c]),
+            Opcode::FAddEC => ConstraintValue::F(f[b] + self.f_constants[c]),
+            Opcode::FAddEV => ConstraintValue::F(f[b] + self.f_var(instr.c_variant, c)),
+            Opcode::FAddEE => ConstraintValue::F(f[b] + f[c]),
+            Opcode::FAddAssignE => ConstraintValue::F(f[a] + f[b]),
+            Opcode::FSubVC => {
+                ConstraintValue::F(self.f_var(instr.b_variant, b) - self.f_constants[c])
+            }
+            Opcode::FSubVV => {
+                ConstraintValue::F(self.f_var(instr.b_variant, b) - self.f_var(instr.c_variant, c))
+            }
+            Opcode::FSubVE => ConstraintValue::F(self.f_var(instr.b_variant, b) - f[c]),
+            Opcode::FSubEC => ConstraintValue::F(f[b] - self.f_constants[c]),
+            Opcode::FSubEV => ConstraintValue::F(f[b] - self.f_var(instr.c_variant, c)),
+            Opcode::FSubEE => ConstraintValue::F(f[b] - f[c]),
+            Opcode::FSubAssignE => ConstraintValue::F(f[a] - f[b]),
+            Opcode::FMulVC => {
+                ConstraintValue::F(self.f_var(instr.b_variant, b) * self.f_constants[c])
+            }
+            Opcode::FMulVV => {
+                ConstraintValue::F(self.f_var(instr.b_variant, b) * self.f_var(instr.c_variant, c))
+            }
+            Opcode::FMulVE => ConstraintValue::F(self.f_var(instr.b_variant, b) * f[c]),
+            Opcode::FMulEC => ConstraintValue::F(f[b] * self.f_constants[c]),
+            Opcode::FMulEV => ConstraintValue::F(f[b] * self.f_var(instr.c_variant, c)),
+            Opcode::FMulEE => ConstraintValue::F(f[b] * f[c]),
+            Opcode::FMulAssignE => ConstraintValue::F(f[a] * f[b]),
+            Opcode::FNegE => ConstraintValue::F(-f[b]),
+            Opcode::EAssignC => ConstraintValue::EF(self.ef_constants[b]),
+            Opcode::EAssignV => ConstraintValue::EF(self.ef_var(instr.b_variant, b)),
+            Opcode::EAssignE => ConstraintValue::EF(e[b]),
+            Opcode::EAddVC => {
+                ConstraintValue::EF(self.ef_var(instr.b_variant, b) + self.ef_constants[c])
+            }
+            Opcode::EAddVV => ConstraintValue::EF(
+                self.ef_var(instr.b_variant, b) + self.ef_var(instr.c_variant, c),
+            ),
+            Opcode::EAddVE => ConstraintValue::EF(self.ef_var(instr.b_variant, b) + e[c]),
+            Opcode::EAddEC => ConstraintValue::EF(e[b] + self.ef_constants[c]),
+            Opcode::EAddEV => ConstraintValue::EF(e[b] + self.ef_var(instr.c_variant, c)),
+            Opcode::EAddEE => ConstraintValue::EF(e[b] + e[c]),
+            Opcode::EAddAssignE => ConstraintValue::EF(e[a] + e[b]),
+            Opcode::ESubVC => {
+                ConstraintValue::EF(self.ef_var(instr.b_variant, b) - self.ef_constants[c])
+            }
+            Opcode::ESubVV => ConstraintValue::EF(
+                self.ef_var(instr.b_variant, b) - self.ef_var(instr.c_variant, c),
+            ),
+            Opcode::ESubVE => ConstraintValue::EF(self.ef_var(instr.b_variant, b) - e[c]),
+            Opcode::ESubEC => ConstraintValue::EF(e[b] - self.ef_constants[c]),
+            Opcode::ESubEV => ConstraintValue::EF(e[b] - self.ef_var(instr.c_variant, c)),
+            Opcode::ESubEE => ConstraintValue::EF(e[b] - e[c]),
+            Opcode::ESubAssignE => ConstraintValue::EF(e[a] - e[b]),
+            Opcode::EMulVC => {
+                ConstraintValue::EF(self.ef_var(instr.b_variant, b) * self.ef_constants[c])
+            }
+            Opcode::EMulVV => ConstraintValue::EF(
+                self.ef_var(instr.b_variant, b) * self.ef_var(instr.c_variant, c),
+            ),
+            Opcode::EMulVE => ConstraintValue::EF(self.ef_var(instr.b_variant, b) * e[c]),
+            Opcode::EMulEC => ConstraintValue::EF(e[b] * self.ef_constants[c]),
+            Opcode::EMulEV => ConstraintValue::EF(e[b] * self.ef_var(instr.c_variant, c)),
+            Opcode::EMulEE => ConstraintValue::EF(e[b] * e[c]),
+            Opcode::EMulAssignE => ConstraintValue::EF(e[a] * e[b]),
+            Opcode::ENegE => ConstraintValue::EF(-e[b]),
+            Opcode::EFFromE => ConstraintValue::EF(EF::from_base(f[b])),
+            Opcode::EFAddEE => ConstraintValue::EF(e[b] + f[c]),
+            Opcode::EFAddAssignE => ConstraintValue::EF(e[a] + f[b]),
+            Opcode::EFSubEE => ConstraintValue::EF(e[b] - f[c]),
+            Opcode::EFSubAssignE => ConstraintValue::EF(e[a] - f[b]),
+            Opcode::EFMulEE => ConstraintValue::EF(e[b] * f[c]),
+            Opcode::EFMulAssignE => ConstraintValue::EF(e[a] * f[b]),
+            Opcode::EFAsBaseSlice => unreachable!("EFAsBaseSlice is rejected by validate"),
+            Opcode::FAssertZero => return Some(ConstraintValue::F(f[a])),
+            Opcode::EAssertZero => return Some(ConstraintValue::EF(e[a])),
+            Opcode::FSpill => {
+                self.f_spill[a] = f[b];
+                return None;
+            }
+            Opcode::FReload => ConstraintValue::F(self.f_spill[b]),
+            Opcode::ESpill => {
+                self.ef_spill[a] = e[b];
+                return None;
+            }
+            Opcode::EReload => ConstraintValue::EF(self.ef_spill[b]),
+            Opcode::FMulAddVVE => ConstraintValue::F(
+                self.f_var(instr.b_variant, b) * self.f_var(instr.c_variant, c) + f[a],
+            ),
+            Opcode::FMulAddVEE => ConstraintValue::F(self.f_var(instr.b_variant, b) * f[c] + f[a]),
+            Opcode::FMulAddEVE => ConstraintValue::F(f[b] * self.f_var(instr.c_variant, c) + f[a]),
+            Opcode::FMulAddEEE => ConstraintValue::F(f[b] * f[c] + f[a]),
+            Opcode::FMulSubVVE => ConstraintValue::F(
+                self.f_var(instr.b_variant, b) * self.f_var(instr.c_variant, c) - f[a],
+            ),
+            Opcode::FMulSubVEE => ConstraintValue::F(self.f_var(instr.b_variant, b) * f[c] - f[a]),
+            Opcode::FMulSubEVE => ConstraintValue::F(f[b] * self.f_var(instr.c_variant, c) - f[a]),
+            Opcode::FMulSubEEE => ConstraintValue::F(f[b] * f[c] - f[a]),
+            Opcode::EMulAddVVE => ConstraintValue::EF(
+                self.ef_var(instr.b_variant, b) * self.ef_var(instr.c_variant, c) + e[a],
+            ),
+            Opcode::EMulAddVEE => {
+                ConstraintValue::EF(self.ef_var(instr.b_variant, b) * e[c] + e[a])
+            }
+            Opcode::EMulAddEVE => {
+                ConstraintValue::EF(e[b] * self.ef_var(instr.c_variant, c) + e[a])
+            }
+            Opcode::EMulAddEEE => ConstraintValue::EF(e[b] * e[c] + e[a]),
+            Opcode::EMulSubVVE => ConstraintValue::EF(
+                self.ef_var(instr.b_variant, b) * self.ef_var(instr.c_variant, c) - e[a],
+            ),
+            Opcode::EMulSubVEE => {
+                ConstraintValue::EF(self.ef_var(instr.b_variant, b) * e[c] - e[a])
+            }
+            Opcode::EMulSubEVE => {
+                ConstraintValue::EF(e[b] * self.ef_var(instr.c_variant, c) - e[a])
+            }
+            Opcode::EMulSubEEE => ConstraintValue::EF(e[b] * e[c] - e[a]),
+        };
+
+        match value {
+            ConstraintValue::F(value) => self.f_registers[a] = value,
+            ConstraintValue::EF(value) => self.ef_registers[a] = value,
+        }
+        None
+    }
+
+    /// Reads the `SymbolicVarF` operand encoded by `variant` and `idx`.
+    fn f_var(&self, variant: u8, idx: usize) -> F {
+        match SymbolicVarF::decode(variant, idx as u32) {
+            Some(SymbolicVarF::Constant(_)) => self.f_constants[idx],
+            Some(SymbolicVarF::PreprocessedLocal(_)) => self.inputs.preprocessed_local[idx],
+            Some(SymbolicVarF::PreprocessedNext(_)) => self.inputs.preprocessed_next[idx],
+            Some(SymbolicVarF::MainLocal(_)) => self.inputs.main_local[idx],
+            Some(SymbolicVarF::MainNext(_)) => self.inputs.main_next[idx],
+            Some(SymbolicVarF::IsFirstRow) => self.inputs.is_first_row,
+            Some(SymbolicVarF::IsLastRow) => self.inputs.is_last_row,
+            Some(SymbolicVarF::IsTransition) => self.inputs.is_transition,
+            Some(SymbolicVarF::PublicValue(_)) => self.inputs.public_values[idx],
+            Some(SymbolicVarF::GlobalCumulativeSum(_)) => self.inputs.global_cumulative_sum[idx],
+            Some(SymbolicVarF::InvZeroifier) => self.inputs.inv_zeroifier,
+            Some(SymbolicVarF::Empty) | None => {
+                panic!("invalid SymbolicVarF variant {:#04x}", variant)
+            }
+        }
+    }
+
+    /// Reads the `SymbolicVarEF` operand encoded by `variant` and `idx`.
+    fn ef_var(&self, variant: u8, idx: usize) -> EF {
+        match SymbolicVarEF::decode(variant, idx as u32) {
+            Some(SymbolicVarEF::PermutationLocal(_)) => self.inputs.permutation_local[idx],
+            Some(SymbolicVarEF::PermutationNext(_)) => self.inputs.permutation_next[idx],
+            Some(SymbolicVarEF::PermutationChallenge(_)) => self.inputs.permutation_challenges[idx],
+            Some(SymbolicVarEF::CumulativeSum(_)) => self.inputs.local_cumulative_sum,
+            Some(SymbolicVarEF::Alpha) => self.inputs.alpha,
+            Some(SymbolicVarEF::Empty) | None => {
+                panic!("invalid SymbolicVarEF variant {:#04x}", variant)
+            }
+        }
+    }
+}
+
+/// Returns the number of F and EF registers `code` needs.
+fn register_file_sizes(code: &[Instruction16]) -> (usize, usize) {
+    let mut f_len = 0;
+    let mut ef_len = 0;
+    for instr in code {
+        let opcode = instr.decode_opcode();
+        let mut f_reg = |reg: u16| f_len = f_len.max(reg as usize + 1);
+        if opcode.is_f_assign() {
+            f_reg(instr.a);
+        }
+        if opcode.is_f_arg1() {
+            f_reg(instr.b);
+        }
+        if opcode.is_f_arg2() {
+            f_reg(instr.c);
+        }
+        let mut ef_reg = |reg: u16| ef_len = ef_len.max(reg as usize + 1);
+        if opcode.is_e_assign() {
+            ef_reg(instr.a);
+        }
+        if opcode.is_e_arg1() {
+            ef_reg(instr.b);
+        }
+        if opcode.is_e_arg2() {
+            ef_reg(instr.c);
+        }
+    }
+    (f_len, ef_len)
+}
+
+/// Returns the number of F and EF spill slots `code` uses.
+fn spill_slot_counts(code: &[Instruction16]) -> (usize, usize) {
+    let mut f_slots = 0;
+    let mut ef_slots = 0;
+    for instr in code {
+        match instr.decode_opcode() {
+            Opcode::FSpill => f_slots = f_slots.max(instr.a as usize + 1),
+            Opcode::FReload => f_slots = f_slots.max(instr.b as usize + 1),
+            Opcode::ESpill => ef_slots = ef_slots.max(instr.a as usize + 1),
+            Opcode::EReload => ef_slots = ef_slots.max(instr.b as usize + 1),
+            _ => {}
+        }
+    }
+    (f_slots, ef_slots)
+}
+
+/// Executes `code` on a single row and returns the value of every assertion in program order.
+/// See [`Interpreter::run`] for the code it accepts.
+pub fn interpret(
+    code: &[Instruction16],
+    f_constants: &[F],
+    ef_constants: &[EF],
+    inputs: &InterpreterInputs,
+) -> Vec<ConstraintValue> {
+    Interpreter::new(f_constants, ef_constants, inputs).run(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use p3_field::{AbstractExtensionField, AbstractField};
+
+    use crate::{
+        instruction::Instruction32, optimizer, symbolic_expr_ef::SymbolicExprEF,
+        symbolic_var_ef::SymbolicVarEF, symbolic_var_f::SymbolicVarF, trace::TraceContext, EF, F,
+    };
+
+    use super::{interpret, ConstraintValue, InterpreterInputs};
+
+    #[test]
+    fn test_interpret_f_and_ef_asserts() {
+        TraceContext::reset();
+        let x = SymbolicVarF::main_local(0) * SymbolicVarF::main_local(1);
+        let y = x - SymbolicVarF::main_next(0) + F::from_canonical_u32(3);
+        TraceContext::push(Instruction32::f_assert_zero(y));
+        let z = SymbolicVarF::is_first_row() * (SymbolicVarF::public_value(1) - y);
+        TraceContext::push(Instruction32::f_assert_zero(z));
+        let e = SymbolicVarEF::permutation_local(0) * SymbolicVarEF::permutation_challenge(1);
+        let e: SymbolicExprEF = e - SymbolicVarEF::cumulative_sum(0) + y;
+        TraceContext::push(Instruction32::e_assert_zero(e));
+        let (code, mut f_constants, mut ef_constants) = TraceContext::take().into_parts();
+        let (code, _, _) = optimizer::optimize(code, &mut f_constants, &mut ef_constants).unwrap();
+
+        let f = F::from_canonical_u32;
+        let ef = |x| EF::from_base(F::from_canonical_u32(x));
+        let inputs = InterpreterInputs {
+            main_local: vec![f(5), f(7)],
+            main_next: vec![f(30)],
+            public_values: vec![f(0), f(11)],
+            permutation_local: vec![ef(2)],
+            permutation_challenges: vec![ef(0), ef(9)],
+            local_cumulative_sum: ef(4),
+            is_first_row: f(1),
+            ..Default::default()
+        };
+
+        let results = interpret(&code, &f_constants, &ef_constants, &inputs);
+        assert_eq!(
+            results,
+            vec![ConstraintValue::F(f(8)), ConstraintValue::F(f(3)), ConstraintValue::EF(ef(22)),]
+        );
+    }
+}
@@ -1,16 +1,30 @@
 #![allow(clippy::assign_op_pattern)]
 
+pub mod asm;
+pub mod binary;
+pub mod cpu;
+pub mod cuda;
+pub mod degree;
+pub mod error;
+#[cfg(test)]
+mod harness;
 pub mod instruction;
+pub mod interpreter;
 pub mod optimizer;
+pub mod permutation;
+pub mod program;
 pub mod symbolic_expr_ef;
 pub mod symbolic_expr_f;
 pub mod symbolic_var_ef;
 pub mod symbolic_var_f;
+pub mod trace;
 
-use std::sync::Mutex;
+use std::collections::BTreeMap;
 
-use instruction::{Instruction16, Instruction32};
-use lazy_static::lazy_static;
+use degree::{DegreeReport, Degrees};
+use error::{CodegenError, CodegenErrorKind};
+use instruction::Instruction32;
+use optimizer::RegisterBudget;
 use p3_air::BaseAir;
 use p3_air::{
     Air, AirBuilder, AirBuilderWithPublicValues, ExtensionBuilder, PairBuilder,
@@ -19,30 +33,83 @@ use p3_air::{
 use p3_baby_bear::BabyBear;
 use p3_field::extension::BinomialExtensionField;
 use p3_matrix::{dense::RowMajorMatrixView, stack::VerticalPair};
+use program::{ColumnWidths, ConstraintProgram};
+use rayon::prelude::*;
 use sp1_stark::septic_curve::SepticCurve;
 use sp1_stark::septic_extension::SepticExtension;
 use sp1_stark::{
-    air::{EmptyMessageBuilder, MachineAir, MultiTableAirBuilder},
+    air::{AirInteraction, InteractionScope, MachineAir, MessageBuilder, MultiTableAirBuilder},
     septic_digest::SepticDigest,
-    Chip,
+    Chip, InteractionKind, StarkGenericConfig, StarkMachine,
 };
 use sp1_stark::{AirOpenedValues, PROOF_MAX_NUM_PVS};
 use symbolic_expr_ef::SymbolicExprEF;
 use symbolic_expr_f::SymbolicExprF;
 use symbolic_var_ef::SymbolicVarEF;
 use symbolic_var_f::SymbolicVarF;
+use trace::{ConstantStats, TraceContext, TraceSession};
 
 pub type F = BabyBear;
 
 pub type EF = BinomialExtensionField<F, 4>;
 
-lazy_static! {
-    pub static ref CUDA_P3_EVAL_LOCK: Mutex<()> = Mutex::new(());
-    pub static ref CUDA_P3_EVAL_CODE: Mutex<Vec<Instruction32>> = Mutex::new(Vec::new());
-    pub static ref CUDA_P3_EVAL_F_CONSTANTS: Mutex<Vec<F>> = Mutex::new(Vec::new());
-    pub static ref CUDA_P3_EVAL_EF_CONSTANTS: Mutex<Vec<EF>> = Mutex::new(Vec::new());
-    pub static ref CUDA_P3_EVAL_EXPR_F_CTR: Mutex<u32> = Mutex::new(0);
-    pub static ref CUDA_P3_EVAL_EXPR_EF_CTR: Mutex<u32> = Mutex::new(0);
+/// What a generated program computes for each row.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EvalMode {
+    /// Assert every constraint separately, in evaluation order.
+    #[default]
+    Constraints,
+    /// Fold the constraints as `acc = acc * alpha + constraint`, in the same order as
+    /// sp1-stark's `ProverConstraintFolder`, and assert only the accumulated value. `alpha` is
+    /// read from [`SymbolicVarEF::Alpha`].
+    Folded,
+    /// Fold the constraints like [`EvalMode::Folded`], then divide by the vanishing polynomial
+    /// by multiplying with [`SymbolicVarF::InvZeroifier`], and assert the resulting quotient
+    /// value. This matches sp1-stark's `quotient_values`.
+    Quotient,
+}
+
+/// Whether an interaction sends or receives its message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InteractionDirection {
+    Send,
+    Receive,
+}
+
+/// A `send` or `receive` made by a chip's `eval`, recorded while tracing.
+///
+/// The values and multiplicity are expressions of the traced code, which
+/// [`permutation::eval_permutation_constraints`] builds the chip's permutation constraints from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolicInteraction {
+    pub direction: InteractionDirection,
+    pub values: Vec<SymbolicExprF>,
+    pub multiplicity: SymbolicExprF,
+    pub kind: InteractionKind,
+    pub scope: InteractionScope,
+}
+
+/// A summary of one interaction of a chip, used to audit lookup balance across chips.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InteractionInfo {
+    pub direction: InteractionDirection,
+    pub kind: InteractionKind,
+    pub scope: InteractionScope,
+    pub num_values: usize,
+    /// The degree of the multiplicity in the trace columns.
+    pub multiplicity_degree: usize,
+    /// The highest degree of any of the values, or 0 if there are none.
+    pub value_degree: usize,
+}
+
+/// The registers a chip's program needs, in the order the chip's `eval` builds its expressions
+/// and after [`optimizer::schedule`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegisterCounts {
+    pub f_before: usize,
+    pub ef_before: usize,
+    pub f_after: usize,
+    pub ef_after: usize,
 }
 
 pub struct SymbolicProverFolder<'a> {
@@ -59,6 +126,11 @@ pub struct SymbolicProverFolder<'a> {
     pub is_last_row: SymbolicVarF,
     pub is_transition: SymbolicVarF,
     pub public_values: &'a [SymbolicVarF],
+    /// The running random linear combination of the constraints, or `None` to assert each
+    /// constraint separately.
+    pub accumulator: Option<SymbolicExprEF>,
+    /// Every interaction the chip's `eval` made, in order.
+    pub interactions: Vec<SymbolicInteraction>,
 }
 
 impl<'a> AirBuilder for SymbolicProverFolder<'a> {
@@ -84,15 +156,17 @@ impl<'a> AirBuilder for SymbolicProverFolder<'a> {
         if size == 2 {
             self.is_transition.into()
         } else {
-            panic!("uni-stark only supports a window size of 2")
+            TraceContext::fail(CodegenErrorKind::UnsupportedWindowSize(size));
+            self.is_transition.into()
         }
     }
 
     fn assert_zero<I: Into<Self::Expr>>(&mut self, x: I) {
         let x: Self::Expr = x.into();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::f_assert_zero(x));
-        drop(code);
+        match &mut self.accumulator {
+            Some(acc) => *acc = *acc * SymbolicVarEF::alpha() + x,
+            None => TraceContext::push(Instruction32::f_assert_zero(x)),
+        }
     }
 }
 
@@ -106,9 +180,10 @@ impl<'a> ExtensionBuilder for SymbolicProverFolder<'a> {
         I: Into<Self::ExprEF>,
     {
         let x: SymbolicExprEF = x.into();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::e_assert_zero(x));
-        drop(code);
+        match &mut self.accumulator {
+            Some(acc) => *acc = *acc * SymbolicVarEF::alpha() + x,
+            None => TraceContext::push(Instruction32::e_assert_zero(x)),
+        }
     }
 }
 
@@ -151,13 +226,146 @@ impl<'a> AirBuilderWithPublicValues for SymbolicProverFolder<'a> {
     }
 }
 
-impl<'a> EmptyMessageBuilder for SymbolicProverFolder<'a> {}
+impl<'a> SymbolicProverFolder<'a> {
+    fn record(
+        &mut self,
+        direction: InteractionDirection,
+        message: AirInteraction<SymbolicExprF>,
+        scope: InteractionScope,
+    ) {
+        self.interactions.push(SymbolicInteraction {
+            direction,
+            values: message.values,
+            multiplicity: message.multiplicity,
+            kind: message.kind,
+            scope,
+        });
+    }
+}
+
+impl<'a> MessageBuilder<AirInteraction<SymbolicExprF>> for SymbolicProverFolder<'a> {
+    fn send(&mut self, message: AirInteraction<SymbolicExprF>, scope: InteractionScope) {
+        self.record(InteractionDirection::Send, message, scope);
+    }
+
+    fn receive(&mut self, message: AirInteraction<SymbolicExprF>, scope: InteractionScope) {
+        self.record(InteractionDirection::Receive, message, scope);
+    }
+}
 
 /// Generates code in CUDA for evaluating the constraint polynomial on the device.
-pub fn codegen_cuda_eval<A>(chip: &Chip<F, A>) -> (Vec<Instruction16>, u32, u32, Vec<F>, Vec<EF>)
+///
+/// Tracing records into the calling thread's [`TraceContext`], so chips can be traced
+/// concurrently from different threads.
+///
+/// Values that do not fit in the device's register files are spilled to scratch memory. Fails if
+/// the chip uses an operation the tracer cannot express, or if an operand does not fit in 16
+/// bits. Chips that are too wide for [`instruction::Instruction16`] can use
+/// [`codegen_cuda_eval_wide`] instead.
+pub fn codegen_cuda_eval<A>(chip: &Chip<F, A>) -> Result<ConstraintProgram, CodegenError>
+where
+    A: for<'a> Air<SymbolicProverFolder<'a>> + MachineAir<F>,
+{
+    codegen_cuda_eval_with(chip, EvalMode::Constraints)
+}
+
+/// Generates code like [`codegen_cuda_eval`], but emits full-width [`Instruction32`]s.
+pub fn codegen_cuda_eval_wide<A>(
+    chip: &Chip<F, A>,
+) -> Result<ConstraintProgram<Instruction32>, CodegenError>
+where
+    A: for<'a> Air<SymbolicProverFolder<'a>> + MachineAir<F>,
+{
+    codegen_cuda_eval_wide_with(chip, EvalMode::Constraints)
+}
+
+/// Generates code like [`codegen_cuda_eval`] for the given [`EvalMode`].
+pub fn codegen_cuda_eval_with<A>(
+    chip: &Chip<F, A>,
+    mode: EvalMode,
+) -> Result<ConstraintProgram, CodegenError>
+where
+    A: for<'a> Air<SymbolicProverFolder<'a>> + MachineAir<F>,
+{
+    codegen_cuda_eval_with_budget(chip, mode, RegisterBudget::default())
+}
+
+/// Generates code like [`codegen_cuda_eval_wide`] for the given [`EvalMode`].
+pub fn codegen_cuda_eval_wide_with<A>(
+    chip: &Chip<F, A>,
+    mode: EvalMode,
+) -> Result<ConstraintProgram<Instruction32>, CodegenError>
+where
+    A: for<'a> Air<SymbolicProverFolder<'a>> + MachineAir<F>,
+{
+    codegen_cuda_eval_wide_with_budget(chip, mode, RegisterBudget::default())
+}
+
+/// Generates code like [`codegen_cuda_eval_with`] for a target with `budget` registers per file.
+///
+/// Fails if a single instruction needs more registers than `budget` provides.
+pub fn codegen_cuda_eval_with_budget<A>(
+    chip: &Chip<F, A>,
+    mode: EvalMode,
+    budget: RegisterBudget,
+) -> Result<ConstraintProgram, CodegenError>
+where
+    A: for<'a> Air<SymbolicProverFolder<'a>> + MachineAir<F>,
+{
+    codegen_cuda_eval_wide_with_budget(chip, mode, budget)?
+        .lower()
+        .map_err(|kind| CodegenError { chip: chip.name(), kind })
+}
+
+/// Generates code like [`codegen_cuda_eval_wide_with`] for a target with `budget` registers per
+/// file.
+pub fn codegen_cuda_eval_wide_with_budget<A>(
+    chip: &Chip<F, A>,
+    mode: EvalMode,
+    budget: RegisterBudget,
+) -> Result<ConstraintProgram<Instruction32>, CodegenError>
+where
+    A: for<'a> Air<SymbolicProverFolder<'a>> + MachineAir<F>,
+{
+    let _span = tracing::info_span!("codegen_cuda_eval", chip = %chip.name()).entered();
+
+    let (mut trace, _) = trace_chip(chip, mode);
+    let stats = trace.constant_stats();
+    tracing::debug!(
+        "constant pools: {} F constants ({} hits), {} EF constants ({} hits)",
+        stats.f.distinct,
+        stats.f.hits,
+        stats.ef.distinct,
+        stats.ef.hits
+    );
+    let error = trace.error.take();
+    let (code, mut f_constants, mut ef_constants) = trace.into_parts();
+
+    let optimized = match error {
+        Some(kind) => Err(kind),
+        None => {
+            optimizer::optimize_wide_with_budget(code, &mut f_constants, &mut ef_constants, budget)
+        }
+    };
+    let (code, f_max, ef_max) =
+        optimized.map_err(|kind| CodegenError { chip: chip.name(), kind })?;
+
+    let widths = ColumnWidths::of(chip);
+    Ok(ConstraintProgram::new(code, f_max + 1, ef_max + 1, f_constants, ef_constants, widths))
+}
+
+/// Traces `chip`'s `eval` in `mode` on the calling thread, returning the recorded trace and every
+/// interaction the chip made.
+///
+/// The AIR's own constraints are traced first, followed by the LogUp permutation constraints of the
+/// interactions recorded while tracing them, in the same order as `Chip::eval`, so the trace
+/// covers the chip's whole constraint system.
+fn trace_chip<A>(chip: &Chip<F, A>, mode: EvalMode) -> (TraceContext, Vec<SymbolicInteraction>)
 where
     A: for<'a> Air<SymbolicProverFolder<'a>> + MachineAir<F>,
 {
+    let session = TraceSession::start();
+
     let preprocessed_width = chip.preprocessed_width() as u32;
     let width = chip.width() as u32;
     let permutation_width = chip.permutation_width() as u32;
@@ -195,45 +403,179 @@ where
         is_first_row: SymbolicVarF::is_first_row(),
         is_last_row: SymbolicVarF::is_last_row(),
         is_transition: SymbolicVarF::is_transition(),
+        accumulator: match mode {
+            EvalMode::Constraints => None,
+            EvalMode::Folded | EvalMode::Quotient => Some(SymbolicExprEF::default()),
+        },
+        interactions: Vec::new(),
     };
 
-    chip.eval(&mut folder);
-    let code = CUDA_P3_EVAL_CODE.lock().unwrap().to_vec();
-    let f_constants = CUDA_P3_EVAL_F_CONSTANTS.lock().unwrap().to_vec();
-    let ef_constants = CUDA_P3_EVAL_EF_CONSTANTS.lock().unwrap().to_vec();
+    chip.air.eval(&mut folder);
+    let interactions = std::mem::take(&mut folder.interactions);
+    permutation::eval_permutation_constraints(
+        &interactions,
+        chip.logup_batch_size(),
+        chip.commit_scope(),
+        &mut folder,
+    );
+    match (mode, folder.accumulator) {
+        (EvalMode::Quotient, Some(acc)) => {
+            let quotient = acc * SymbolicExprF::from(SymbolicVarF::inv_zeroifier());
+            TraceContext::push(Instruction32::e_assert_zero(quotient));
+        }
+        (_, Some(acc)) => TraceContext::push(Instruction32::e_assert_zero(acc)),
+        (_, None) => {}
+    }
+    (session.finish(), interactions)
+}
+
+/// Lists every interaction `chip` sends or receives, in the order its `eval` makes them.
+///
+/// The degrees are computed from the symbolic expressions the chip builds for each value and
+/// multiplicity.
+pub fn interactions<A>(chip: &Chip<F, A>) -> Result<Vec<InteractionInfo>, CodegenError>
+where
+    A: for<'a> Air<SymbolicProverFolder<'a>> + MachineAir<F>,
+{
+    let (mut trace, interactions) = trace_chip(chip, EvalMode::Constraints);
+    if let Some(kind) = trace.error.take() {
+        return Err(CodegenError { chip: chip.name(), kind });
+    }
+    let degrees = Degrees::of(&trace.code);
+    let infos = interactions
+        .into_iter()
+        .map(|interaction| InteractionInfo {
+            direction: interaction.direction,
+            kind: interaction.kind,
+            scope: interaction.scope,
+            num_values: interaction.values.len(),
+            multiplicity_degree: degrees.f(interaction.multiplicity.0),
+            value_degree: interaction
+                .values
+                .iter()
+                .map(|value| degrees.f(value.0))
+                .max()
+                .unwrap_or(0),
+        })
+        .collect();
+    Ok(infos)
+}
+
+/// Computes the degree of every constraint of `chip` and flags those above `limit`, e.g.
+/// [`degree::degree_limit`]`(chip.log_quotient_degree())`.
+///
+/// Degrees are read off the traced code before optimization, the same way sp1-stark derives them
+/// from its symbolic expressions.
+pub fn analyze_degrees<A>(chip: &Chip<F, A>, limit: usize) -> Result<DegreeReport, CodegenError>
+where
+    A: for<'a> Air<SymbolicProverFolder<'a>> + MachineAir<F>,
+{
+    let (mut trace, _) = trace_chip(chip, EvalMode::Constraints);
+    if let Some(kind) = trace.error.take() {
+        return Err(CodegenError { chip: chip.name(), kind });
+    }
+    let report = degree::analyze(&trace.code, limit);
+    for &i in &report.over_limit {
+        tracing::warn!(
+            "chip {} constraint {} has degree {}, over the limit of {}",
+            chip.name(),
+            i,
+            report.constraint_degrees[i],
+            limit
+        );
+    }
+    Ok(report)
+}
 
-    CUDA_P3_EVAL_RESET();
+/// Counts the distinct constants `chip` traces and how often its constants are reused.
+pub fn constant_stats<A>(chip: &Chip<F, A>) -> Result<ConstantStats, CodegenError>
+where
+    A: for<'a> Air<SymbolicProverFolder<'a>> + MachineAir<F>,
+{
+    let (mut trace, _) = trace_chip(chip, EvalMode::Constraints);
+    if let Some(kind) = trace.error.take() {
+        return Err(CodegenError { chip: chip.name(), kind });
+    }
+    Ok(trace.constant_stats())
+}
 
-    let (code, f_ctr, ef_ctr) = optimizer::optimize(code);
+/// Compiles `chip` with and without instruction scheduling and counts the F and EF registers
+/// each program needs with the default [`RegisterBudget`].
+pub fn register_counts<A>(chip: &Chip<F, A>) -> Result<RegisterCounts, CodegenError>
+where
+    A: for<'a> Air<SymbolicProverFolder<'a>> + MachineAir<F>,
+{
+    let (mut trace, _) = trace_chip(chip, EvalMode::Constraints);
+    let error = trace.error.take();
+    let (code, mut f_constants, mut ef_constants) = trace.into_parts();
+    let fail = |kind| CodegenError { chip: chip.name(), kind };
+    if let Some(kind) = error {
+        return Err(fail(kind));
+    }
 
-    (code, f_ctr as u32, ef_ctr as u32, f_constants, ef_constants)
+    let code = optimizer::simplify_program(code, &mut f_constants, &mut ef_constants);
+    let budget = RegisterBudget::default();
+    let (_, f_before, ef_before) = optimizer::allocate_registers(&code, budget).map_err(fail)?;
+    let scheduled = optimizer::schedule(code);
+    let (_, f_after, ef_after) = optimizer::allocate_registers(&scheduled, budget).map_err(fail)?;
+    Ok(RegisterCounts {
+        f_before: f_before + 1,
+        ef_before: ef_before + 1,
+        f_after: f_after + 1,
+        ef_after: ef_after + 1,
+    })
 }
 
-#[allow(non_snake_case)]
-pub fn CUDA_P3_EVAL_RESET() {
-    *CUDA_P3_EVAL_CODE.lock().unwrap() = Vec::new();
-    *CUDA_P3_EVAL_EF_CONSTANTS.lock().unwrap() = Vec::new();
-    *CUDA_P3_EVAL_EXPR_F_CTR.lock().unwrap() = 0;
-    *CUDA_P3_EVAL_EXPR_EF_CTR.lock().unwrap() = 0;
+/// Generates code for every chip of `machine`, tracing the chips concurrently.
+///
+/// The output is keyed by chip name and is identical to calling [`codegen_cuda_eval`] on each
+/// chip in turn. Fails if any chip does not compile.
+pub fn codegen_machine<SC, A>(
+    machine: &StarkMachine<SC, A>,
+) -> Result<BTreeMap<String, ConstraintProgram>, CodegenError>
+where
+    SC: StarkGenericConfig<Val = F>,
+    A: for<'a> Air<SymbolicProverFolder<'a>> + MachineAir<F> + Sync,
+{
+    machine.chips().par_iter().map(|chip| Ok((chip.name(), codegen_cuda_eval(chip)?))).collect()
 }
 
 #[cfg(test)]
 mod tests {
 
     use p3_air::{Air, BaseAir};
-    use p3_field::{AbstractField, PrimeField32};
+    use p3_field::{AbstractExtensionField, AbstractField, PrimeField32};
     use p3_matrix::dense::RowMajorMatrix;
     use p3_matrix::Matrix;
     use sp1_core_executor::ExecutionRecord;
     use sp1_core_executor::Program;
     use sp1_core_machine::{operations::AddOperation, riscv::RiscvAir, utils::setup_logger};
     use sp1_derive::AlignedBorrow;
-    use sp1_stark::Chip;
     use sp1_stark::{air::MachineAir, baby_bear_poseidon2::BabyBearPoseidon2};
-    use sp1_stark::{air::SP1AirBuilder, Word};
+    use sp1_stark::{
+        air::{AirInteraction, InteractionScope, SP1AirBuilder},
+        Word,
+    };
+    use sp1_stark::{Chip, Interaction, InteractionKind};
     use std::borrow::Borrow;
 
-    use crate::codegen_cuda_eval;
+    use crate::{
+        analyze_degrees, codegen_cuda_eval, codegen_cuda_eval_with, codegen_cuda_eval_with_budget,
+        codegen_machine, constant_stats,
+        degree::degree_limit,
+        error::{CodegenError, CodegenErrorKind},
+        harness::{check_chip, eval_constraints, eval_folded_constraints, random_inputs},
+        instruction::Instruction32,
+        interactions,
+        interpreter::{interpret, ConstraintValue},
+        optimizer::RegisterBudget,
+        register_counts,
+        symbolic_expr_ef::SymbolicExprEF,
+        symbolic_expr_f::SymbolicExprF,
+        symbolic_var_ef::SymbolicVarEF,
+        trace::{TraceContext, TraceSession},
+        trace_chip, EvalMode, InteractionDirection, F,
+    };
 
     #[derive(AlignedBorrow, Default, Clone, Copy)]
     #[repr(C)]
@@ -257,19 +599,19 @@ mod tests {
             "Add".to_string()
         }
 
-        fn num_rows(&self, input: &Self::Record) -> Option<usize> {
+        fn num_rows(&self, _: &Self::Record) -> Option<usize> {
             todo!()
         }
 
         fn generate_trace(
             &self,
-            input: &ExecutionRecord,
+            _: &ExecutionRecord,
             _: &mut ExecutionRecord,
         ) -> RowMajorMatrix<F> {
             todo!()
         }
 
-        fn included(&self, shard: &Self::Record) -> bool {
+        fn included(&self, _: &Self::Record) -> bool {
             todo!()
         }
 
@@ -296,28 +638,482 @@ mod tests {
         }
     }
 
+    #[derive(Default)]
+    struct AddAssignChip;
+
+    impl<F: PrimeField32> MachineAir<F> for AddAssignChip {
+        type Record = ExecutionRecord;
+
+        type Program = Program;
+
+        fn name(&self) -> String {
+            "AddAssign".to_string()
+        }
+
+        fn generate_trace(
+            &self,
+            _: &ExecutionRecord,
+            _: &mut ExecutionRecord,
+        ) -> RowMajorMatrix<F> {
+            unimplemented!()
+        }
+
+        fn included(&self, _: &Self::Record) -> bool {
+            true
+        }
+    }
+
+    impl<F> BaseAir<F> for AddAssignChip {
+        fn width(&self) -> usize {
+            1
+        }
+    }
+
+    impl<AB> Air<AB> for AddAssignChip
+    where
+        AB: SP1AirBuilder,
+    {
+        fn eval(&self, builder: &mut AB) {
+            let mut local: AB::Expr = builder.main().row_slice(0)[0].into();
+            local += AB::Expr::one();
+            builder.assert_zero(local);
+        }
+    }
+
+    /// Sends two local messages and receives one, so a batch of four holds all three.
+    #[derive(Default)]
+    struct LookupChip;
+
+    impl<F: PrimeField32> MachineAir<F> for LookupChip {
+        type Record = ExecutionRecord;
+
+        type Program = Program;
+
+        fn name(&self) -> String {
+            "Lookup".to_string()
+        }
+
+        fn generate_trace(
+            &self,
+            _: &ExecutionRecord,
+            _: &mut ExecutionRecord,
+        ) -> RowMajorMatrix<F> {
+            unimplemented!()
+        }
+
+        fn included(&self, _: &Self::Record) -> bool {
+            true
+        }
+    }
+
+    impl<F> BaseAir<F> for LookupChip {
+        fn width(&self) -> usize {
+            3
+        }
+    }
+
+    impl<AB> Air<AB> for LookupChip
+    where
+        AB: SP1AirBuilder,
+    {
+        fn eval(&self, builder: &mut AB) {
+            let main = builder.main();
+            let local = main.row_slice(0);
+            let (a, b, m): (AB::Expr, AB::Expr, AB::Expr) =
+                (local[0].into(), local[1].into(), local[2].into());
+            builder.send(
+                AirInteraction::new(vec![a.clone(), b.clone()], m.clone(), InteractionKind::Byte),
+                InteractionScope::Local,
+            );
+            builder.send(
+                AirInteraction::new(vec![b.clone()], AB::Expr::one(), InteractionKind::Memory),
+                InteractionScope::Local,
+            );
+            builder.receive(
+                AirInteraction::new(vec![a + b], m, InteractionKind::Alu),
+                InteractionScope::Local,
+            );
+        }
+    }
+
     #[test]
     pub fn test_add() {
         setup_logger();
 
+        let chip = Chip::new(AddChip);
+        let program = codegen_cuda_eval(&chip).unwrap();
+        println!("{}", program);
+    }
+
+    #[test]
+    pub fn test_trace_chips_in_parallel() {
+        setup_logger();
+
         let config = BabyBearPoseidon2::default();
         let machine = RiscvAir::machine(config);
         let chips = machine.chips();
-        let mut chip = AddChip;
-        let mut chip = Chip::new(chip);
-        let (code, f_ctr, _, f_constants, ef_constants) = codegen_cuda_eval(&chip);
-        println!("{:#?}", code);
-
-        // for chip in chips {
-        //     if chip.name() == "AddSub" {
-        //         let (code, f_ctr, _, f_constants, ef_constants) = codegen_cuda_eval(chip);
-        //         println!("{:#?}", code);
-        //         println!("{}", f_ctr);
-        //         println!("{:?}", f_constants);
-        //         println!("{:?}", ef_constants);
-        //         return;
-        //     }
-        // }
-        // panic!("no AddSub chip found");
+
+        let sequential = chips.iter().map(codegen_cuda_eval).collect::<Vec<_>>();
+        let parallel = std::thread::scope(|s| {
+            let handles =
+                chips.iter().map(|chip| s.spawn(|| codegen_cuda_eval(chip))).collect::<Vec<_>>();
+            handles.into_iter().map(|handle| handle.join().unwrap()).collect::<Vec<_>>()
+        });
+
+        for (chip, (expected, actual)) in chips.iter().zip(sequential.iter().zip(parallel.iter())) {
+            assert_eq!(expected, actual, "chip {} traced differently in parallel", chip.name());
+        }
+    }
+
+    #[test]
+    pub fn test_codegen_machine() {
+        setup_logger();
+
+        let config = BabyBearPoseidon2::default();
+        let machine = RiscvAir::machine(config);
+        let programs = codegen_machine(&machine).unwrap();
+
+        assert_eq!(programs.len(), machine.chips().len());
+        for chip in machine.chips() {
+            let program = codegen_cuda_eval(chip).unwrap();
+            assert_eq!(programs[&chip.name()], program, "chip {}", chip.name());
+            assert_eq!(program.validate(), Ok(()), "chip {}", chip.name());
+        }
+    }
+
+    #[test]
+    pub fn test_folded_programs_match_verifier_folder() {
+        use rand::{rngs::StdRng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(0xa1fa);
+        let machine = RiscvAir::machine(BabyBearPoseidon2::default());
+        let names = ["Cpu", "AddSub", "Mul", "MemoryLocal", "Program"];
+        let chips = machine.chips().iter().filter(|chip| names.contains(&chip.name().as_str()));
+        assert_eq!(chips.clone().count(), names.len());
+        for chip in chips {
+            let program = codegen_cuda_eval_with(chip, EvalMode::Folded).unwrap();
+            assert_eq!(program.num_constraints(), 1, "chip {}", chip.name());
+            assert_eq!(program.validate(), Ok(()), "chip {}", chip.name());
+
+            let inputs = random_inputs(chip, &mut rng);
+            let actual =
+                interpret(program.code(), program.f_constants(), program.ef_constants(), &inputs);
+            let expected = eval_folded_constraints(chip, &inputs, inputs.alpha);
+            assert_eq!(actual, vec![ConstraintValue::EF(expected)], "chip {}", chip.name());
+        }
+    }
+
+    #[test]
+    pub fn test_spilled_programs_match_verifier_folder() {
+        use rand::{rngs::StdRng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(0x5b11);
+        let machine = RiscvAir::machine(BabyBearPoseidon2::default());
+        let budget = RegisterBudget { f: 8, ef: 4 };
+        for name in ["Cpu", "AddSub"] {
+            let chip = machine.chips().iter().find(|chip| chip.name() == name).unwrap();
+            let program = codegen_cuda_eval_with_budget(chip, EvalMode::Folded, budget).unwrap();
+            assert!(program.num_f_registers() <= budget.f, "chip {}", name);
+            assert!(program.num_ef_registers() <= budget.ef, "chip {}", name);
+            assert!(program.num_f_spill_slots() > 0, "chip {}", name);
+            assert_eq!(program.validate(), Ok(()), "chip {}", name);
+
+            let inputs = random_inputs(chip, &mut rng);
+            let actual =
+                interpret(program.code(), program.f_constants(), program.ef_constants(), &inputs);
+            let expected = eval_folded_constraints(chip, &inputs, inputs.alpha);
+            assert_eq!(actual, vec![ConstraintValue::EF(expected)], "chip {}", name);
+        }
+    }
+
+    /// Prints the registers every RiscvAir chip needs before and after scheduling. Run with
+    /// `--nocapture` to see the table.
+    #[test]
+    pub fn test_scheduling_reduces_register_counts() {
+        use rayon::prelude::*;
+
+        let machine = RiscvAir::machine(BabyBearPoseidon2::default());
+        let counts = machine
+            .chips()
+            .par_iter()
+            .map(|chip| (chip.name(), register_counts(chip).unwrap()))
+            .collect::<Vec<_>>();
+
+        println!(
+            "{:<24} {:>9} {:>9} {:>9} {:>9}",
+            "chip", "F before", "F after", "EF before", "EF after"
+        );
+        let footprint = |f: usize, ef: usize| f + 4 * ef;
+        let (mut before, mut after) = (0, 0);
+        for (name, c) in &counts {
+            println!(
+                "{:<24} {:>9} {:>9} {:>9} {:>9}",
+                name, c.f_before, c.f_after, c.ef_before, c.ef_after
+            );
+            assert!(
+                footprint(c.f_after, c.ef_after) <= footprint(c.f_before, c.ef_before),
+                "chip {}",
+                name
+            );
+            before += footprint(c.f_before, c.ef_before);
+            after += footprint(c.f_after, c.ef_after);
+        }
+        assert!(after < before);
+    }
+
+    #[test]
+    pub fn test_quotient_programs_match_quotient_values() {
+        use p3_commit::{PolynomialSpace, TwoAdicMultiplicativeCoset};
+        use p3_field::AbstractExtensionField;
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+        use sp1_stark::{quotient_values, PackedChallenge, PROOF_MAX_NUM_PVS};
+
+        use crate::{harness::global_cumulative_sum, interpreter::InterpreterInputs, EF, F};
+
+        type SC = BabyBearPoseidon2;
+
+        let mut rng = StdRng::seed_from_u64(0x9070);
+        let machine = RiscvAir::machine(SC::default());
+        let trace_domain = TwoAdicMultiplicativeCoset { log_n: 3, shift: F::one() };
+        let quotient_domain = trace_domain.create_disjoint_domain(1 << 5);
+        let selectors = trace_domain.selectors_on_coset(quotient_domain);
+        let quotient_size = 1 << quotient_domain.log_n;
+        let next_step = 1 << (quotient_domain.log_n - trace_domain.log_n);
+
+        for name in ["Program", "AddSub"] {
+            let chip = machine.chips().iter().find(|chip| chip.name() == name).unwrap();
+            let num_constraints = codegen_cuda_eval(chip).unwrap().num_constraints();
+            let program = codegen_cuda_eval_with(chip, EvalMode::Quotient).unwrap();
+            assert_eq!(program.validate(), Ok(()), "chip {}", name);
+
+            let mut random_matrix = |width: usize| {
+                let values = (0..quotient_size * width).map(|_| rng.gen::<F>()).collect();
+                RowMajorMatrix::new(values, width)
+            };
+            let preprocessed = random_matrix(chip.preprocessed_width());
+            let main = random_matrix(chip.width());
+            let perm = random_matrix(chip.permutation_width() * 4);
+
+            let alpha = rng.gen::<EF>();
+            let mut powers_of_alpha = alpha.powers().take(num_constraints).collect::<Vec<_>>();
+            powers_of_alpha.reverse();
+            let perm_challenges = [rng.gen::<EF>(), rng.gen::<EF>()];
+            let packed_challenges = perm_challenges.map(PackedChallenge::<SC>::from_f).to_vec();
+            let local_cumulative_sum = rng.gen::<EF>();
+            let global_sum = (0..14).map(|_| rng.gen::<F>()).collect::<Vec<_>>();
+            let public_values = (0..PROOF_MAX_NUM_PVS).map(|_| rng.gen::<F>()).collect::<Vec<_>>();
+
+            let expected = quotient_values::<SC, _, _>(
+                chip,
+                &local_cumulative_sum,
+                &global_cumulative_sum(&global_sum),
+                trace_domain,
+                quotient_domain,
+                (chip.preprocessed_width() > 0).then(|| preprocessed.clone()),
+                main.clone(),
+                perm.clone(),
+                &packed_challenges,
+                &powers_of_alpha,
+                &public_values,
+            );
+
+            let row = |matrix: &RowMajorMatrix<F>, i: usize| {
+                matrix.row(i % quotient_size).collect::<Vec<_>>()
+            };
+            let ext_row =
+                |i: usize| row(&perm, i).chunks(4).map(EF::from_base_slice).collect::<Vec<_>>();
+            for (i, &expected) in expected.iter().enumerate() {
+                let inputs = InterpreterInputs {
+                    preprocessed_local: row(&preprocessed, i),
+                    preprocessed_next: row(&preprocessed, i + next_step),
+                    main_local: row(&main, i),
+                    main_next: row(&main, i + next_step),
+                    permutation_local: ext_row(i),
+                    permutation_next: ext_row(i + next_step),
+                    permutation_challenges: perm_challenges.to_vec(),
+                    public_values: public_values.clone(),
+                    local_cumulative_sum,
+                    global_cumulative_sum: global_sum.clone(),
+                    is_first_row: selectors.is_first_row[i],
+                    is_last_row: selectors.is_last_row[i],
+                    is_transition: selectors.is_transition[i],
+                    inv_zeroifier: selectors.inv_zeroifier[i],
+                    alpha,
+                };
+                let actual = interpret(
+                    program.code(),
+                    program.f_constants(),
+                    program.ef_constants(),
+                    &inputs,
+                );
+                assert_eq!(actual, vec![ConstraintValue::EF(expected)], "chip {} row {}", name, i);
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_interactions_are_recorded() {
+        let machine = RiscvAir::machine(BabyBearPoseidon2::default());
+        for chip in machine.chips() {
+            let (_, interactions) = trace_chip(chip, EvalMode::Constraints);
+            let recorded = |direction| {
+                interactions
+                    .iter()
+                    .filter(|interaction| interaction.direction == direction)
+                    .map(|interaction| {
+                        (interaction.kind, interaction.scope, interaction.values.len())
+                    })
+                    .collect::<Vec<_>>()
+            };
+            let expected = |interactions: &[Interaction<F>]| {
+                interactions
+                    .iter()
+                    .map(|interaction| {
+                        (interaction.kind, interaction.scope, interaction.values.len())
+                    })
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(
+                recorded(InteractionDirection::Send),
+                expected(chip.sends()),
+                "{}",
+                chip.name()
+            );
+            assert_eq!(
+                recorded(InteractionDirection::Receive),
+                expected(chip.receives()),
+                "{}",
+                chip.name()
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_permutation_constraints_cover_recorded_interactions() {
+        use rand::{rngs::StdRng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(0x1096);
+        let lookup = Chip::new(LookupChip);
+        let with_sends = |sends: &[Interaction<F>]| Chip {
+            air: LookupChip,
+            sends: sends.to_vec(),
+            receives: lookup.receives.clone(),
+            log_quotient_degree: 2,
+        };
+        let chip = with_sends(&lookup.sends);
+        check_chip(&chip, &mut rng);
+
+        // A chip whose `sends` miss one its AIR makes: `Chip::eval` drops the interaction, but the
+        // generated constraints must still cover it.
+        let partial = with_sends(&lookup.sends[1..]);
+        assert_eq!(partial.permutation_width(), chip.permutation_width());
+        let program = codegen_cuda_eval(&partial).unwrap();
+        assert_eq!(program, codegen_cuda_eval(&chip).unwrap());
+        let inputs = random_inputs(&partial, &mut rng);
+        let actual =
+            interpret(program.code(), program.f_constants(), program.ef_constants(), &inputs);
+        assert_ne!(actual, eval_constraints(&partial, &inputs));
+
+        let narrow = Chip { log_quotient_degree: 1, ..with_sends(&lookup.sends[1..]) };
+        assert_eq!(
+            codegen_cuda_eval(&narrow).unwrap_err().kind,
+            CodegenErrorKind::PermutationWidthMismatch { expected: 3, actual: 2 }
+        );
+    }
+
+    #[test]
+    pub fn test_interaction_manifest() {
+        let machine = RiscvAir::machine(BabyBearPoseidon2::default());
+        let mut num_interactions = 0;
+        for chip in machine.chips() {
+            let infos = interactions(chip).unwrap();
+            assert_eq!(infos.len(), chip.sends().len() + chip.receives().len(), "{}", chip.name());
+            let sends = infos.iter().filter(|info| info.direction == InteractionDirection::Send);
+            for (info, send) in sends.zip(chip.sends()) {
+                assert_eq!((info.kind, info.scope), (send.kind, send.scope), "{}", chip.name());
+                assert_eq!(info.num_values, send.values.len(), "{}", chip.name());
+            }
+            // sp1-stark only accepts interactions that are linear in the columns.
+            for info in &infos {
+                assert!(info.multiplicity_degree <= 1, "{}: {:?}", chip.name(), info);
+                assert!(info.value_degree <= 1, "{}: {:?}", chip.name(), info);
+            }
+            num_interactions += infos.len();
+        }
+        assert!(num_interactions > 0);
+
+        let cpu = machine.chips().iter().find(|chip| chip.name() == "Cpu").unwrap();
+        let infos = interactions(cpu).unwrap();
+        let program = infos.iter().find(|info| info.kind == InteractionKind::Program).unwrap();
+        assert_eq!(program.direction, InteractionDirection::Send);
+        assert_eq!(program.multiplicity_degree, 1);
+        assert_eq!(program.value_degree, 1);
+    }
+
+    #[test]
+    pub fn test_riscv_chips_are_within_their_degree_limit() {
+        let machine = RiscvAir::machine(BabyBearPoseidon2::default());
+        let mut num_at_limit = 0;
+        for chip in machine.chips() {
+            let report = analyze_degrees(chip, degree_limit(chip.log_quotient_degree())).unwrap();
+            assert!(report.is_within_limit(), "{}: {:?}", chip.name(), report.over_limit);
+            if report.max_degree == report.limit {
+                num_at_limit += 1;
+            }
+        }
+        // Some chips use their whole degree budget.
+        assert!(num_at_limit > 0);
+    }
+
+    #[test]
+    pub fn test_constant_pools_are_independent_across_chips() {
+        let machine = RiscvAir::machine(BabyBearPoseidon2::default());
+        let chips = machine.chips();
+        let (first, second) = (&chips[0], &chips[1]);
+
+        let (alone, _) = std::thread::scope(|s| {
+            s.spawn(|| trace_chip(second, EvalMode::Constraints)).join().unwrap()
+        });
+        let (before, _) = trace_chip(first, EvalMode::Constraints);
+        let (after, _) = trace_chip(second, EvalMode::Constraints);
+        assert_eq!(after.f_constants(), alone.f_constants());
+        assert_eq!(after.ef_constants(), alone.ef_constants());
+        assert_eq!(after.code, alone.code);
+        assert_eq!(after.constant_stats(), alone.constant_stats());
+        assert_eq!(constant_stats(second).unwrap(), alone.constant_stats());
+
+        let (again, _) = trace_chip(first, EvalMode::Constraints);
+        assert_eq!(again.f_constants(), before.f_constants());
+        assert_eq!(again.ef_constants(), before.ef_constants());
+    }
+
+    #[test]
+    pub fn test_unsupported_operation() {
+        let chip = Chip::new(AddAssignChip);
+        let err = codegen_cuda_eval(&chip).unwrap_err();
+        assert_eq!(
+            err,
+            CodegenError {
+                chip: "AddAssign".to_string(),
+                kind: CodegenErrorKind::UnsupportedOperation("SymbolicExprF::add_assign")
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "codegen failed for chip AddAssign: SymbolicExprF::add_assign is not supported"
+        );
+    }
+
+    #[test]
+    pub fn test_as_base_slice_records_an_error() {
+        let session = TraceSession::start();
+        let x = SymbolicExprEF::from(SymbolicVarEF::alpha());
+        let coeffs = x.as_base_slice();
+        assert_eq!(coeffs.len(), <SymbolicExprEF as AbstractExtensionField<SymbolicExprF>>::D);
+        TraceContext::push(Instruction32::f_assert_zero(coeffs[3]));
+        assert_eq!(
+            session.finish().error,
+            Some(CodegenErrorKind::UnsupportedOperation("SymbolicExprEF::as_base_slice"))
+        );
     }
 }
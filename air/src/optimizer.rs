@@ -1,199 +1,1464 @@
-use std::collections::HashMap;
+use std::{
+    cmp::Reverse,
+    collections::{hash_map::Entry, BTreeSet, HashMap, HashSet},
+};
 
-use crate::instruction::{Instruction16, Instruction32, Opcode};
+use p3_field::{AbstractExtensionField, AbstractField, Field};
 
+use crate::{
+    error::{CodegenErrorKind, RegisterFile},
+    instruction::{Instruction16, Instruction32, Opcode},
+    symbolic_var_f::SymbolicVarF,
+    trace::PoolIndex,
+    EF, F,
+};
+
+/// The number of physical registers of each register file on the target, including register 0,
+/// which is never allocated. Values that do not fit are spilled to scratch arrays.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegisterBudget {
+    pub f: usize,
+    pub ef: usize,
+}
+
+impl Default for RegisterBudget {
+    /// The register files of the interpreter kernel, which live in shared memory.
+    fn default() -> Self {
+        Self { f: 2048, ef: 1024 }
+    }
+}
+
+/// Linear-scan allocation of the virtual registers of one register file.
+///
+/// A virtual register is live from its first to its last use. When every physical register is
+/// taken, the value whose next use is furthest away is spilled to a slot of the file's scratch
+/// array and reloaded before its next use.
 struct RegisterAllocator {
-    f_used: Vec<bool>,
-    ef_used: Vec<bool>,
-    f_vreg2phys_map: HashMap<u32, u32>,
-    ef_vreg2phys_map: HashMap<u32, u32>,
-    f_max: usize,
-    ef_max: usize,
+    file: RegisterFile,
+    capacity: usize,
+    /// The free physical registers, so the lowest one is reused first.
+    free: BTreeSet<u32>,
+    free_slots: BTreeSet<u32>,
+    num_slots: u32,
+    vreg2phys: HashMap<u32, u32>,
+    vreg2slot: HashMap<u32, u32>,
+    /// The positions of the instructions using each virtual register, in program order.
+    uses: HashMap<u32, Vec<u32>>,
+    max: usize,
+    spills: usize,
 }
 
 impl RegisterAllocator {
-    pub fn new() -> Self {
-        let mut f_used = vec![false; 2048];
-        let mut ef_used = vec![false; 1024];
-
-        // Make %v0 always map to %p0.
-        f_used[0] = true;
-        ef_used[0] = true;
-        let f_vreg2phys_map = HashMap::new();
-        let ef_vreg2phys_map = HashMap::new();
-
-        Self { f_used, ef_used, f_vreg2phys_map, ef_vreg2phys_map, f_max: 0, ef_max: 0 }
-    }
-
-    pub fn f_vreg2phys(&mut self, vreg: u32) -> u32 {
-        if self.f_vreg2phys_map.contains_key(&vreg) {
-            return self.f_vreg2phys_map[&vreg];
-        }
-        for i in 0..self.f_used.len() {
-            if !self.f_used[i] {
-                self.f_used[i] = true;
-                let phys = i as u32;
-                self.f_vreg2phys_map.insert(vreg, phys);
-                if i > self.f_max {
-                    self.f_max = i;
+    fn new(file: RegisterFile, capacity: usize, instructions: &[Instruction32]) -> Self {
+        let mut uses: HashMap<u32, Vec<u32>> = HashMap::new();
+        for (i, instr) in instructions.iter().enumerate() {
+            for vreg in file_registers(file, instr) {
+                let positions = uses.entry(vreg).or_default();
+                if positions.last() != Some(&(i as u32)) {
+                    positions.push(i as u32);
                 }
-                return phys;
             }
         }
-        unreachable!()
+        Self {
+            file,
+            capacity,
+            // Register 0 is reserved.
+            free: (1..capacity as u32).collect(),
+            free_slots: BTreeSet::new(),
+            num_slots: 0,
+            vreg2phys: HashMap::new(),
+            vreg2slot: HashMap::new(),
+            uses,
+            max: 0,
+            spills: 0,
+        }
     }
 
-    pub fn ef_vreg2phys(&mut self, vreg: u32) -> u32 {
-        if self.ef_vreg2phys_map.contains_key(&vreg) {
-            return self.ef_vreg2phys_map[&vreg];
+    /// The position of the first use of `vreg` at or after `at`.
+    fn next_use(&self, vreg: u32, at: u32) -> u32 {
+        let uses = &self.uses[&vreg];
+        uses.get(uses.partition_point(|&i| i < at)).copied().unwrap_or(u32::MAX)
+    }
+
+    fn is_last_use(&self, vreg: u32, at: u32) -> bool {
+        self.uses[&vreg].last() == Some(&at)
+    }
+
+    /// Takes a free physical register, spilling the value used furthest in the future if there
+    /// is none. The registers of `pinned` are in use by the current instruction and are never
+    /// spilled.
+    fn take(
+        &mut self,
+        at: u32,
+        pinned: &[u32],
+        output: &mut Vec<Instruction32>,
+    ) -> Result<u32, CodegenErrorKind> {
+        if let Some(phys) = self.free.pop_first() {
+            self.max = self.max.max(phys as usize);
+            return Ok(phys);
         }
-        for i in 0..self.ef_used.len() {
-            if !self.ef_used[i] {
-                self.ef_used[i] = true;
-                let phys = i as u32;
-                self.ef_vreg2phys_map.insert(vreg, phys);
-                if i > self.ef_max {
-                    self.ef_max = i;
-                }
-                return phys;
-            }
+        let victim = self
+            .vreg2phys
+            .keys()
+            .filter(|vreg| !pinned.contains(vreg))
+            .max_by_key(|&&vreg| (self.next_use(vreg, at), vreg))
+            .copied()
+            .ok_or(CodegenErrorKind::RegistersExhausted {
+                file: self.file,
+                capacity: self.capacity,
+            })?;
+        let phys = self.vreg2phys.remove(&victim).unwrap();
+        let slot = self.free_slots.pop_first().unwrap_or_else(|| {
+            self.num_slots += 1;
+            self.num_slots - 1
+        });
+        output.push(match self.file {
+            RegisterFile::F => Instruction32::f_spill(slot, phys),
+            RegisterFile::EF => Instruction32::e_spill(slot, phys),
+        });
+        self.vreg2slot.insert(victim, slot);
+        self.spills += 1;
+        Ok(phys)
+    }
+
+    /// The physical register holding `vreg` for instruction `at`, reloading it if it was
+    /// spilled. Reads of a register that was never written get a fresh register.
+    fn read(
+        &mut self,
+        vreg: u32,
+        at: u32,
+        pinned: &[u32],
+        output: &mut Vec<Instruction32>,
+    ) -> Result<u32, CodegenErrorKind> {
+        if let Some(&phys) = self.vreg2phys.get(&vreg) {
+            return Ok(phys);
         }
-        unreachable!()
+        let phys = self.take(at, pinned, output)?;
+        if let Some(slot) = self.vreg2slot.remove(&vreg) {
+            output.push(match self.file {
+                RegisterFile::F => Instruction32::f_reload(phys, slot),
+                RegisterFile::EF => Instruction32::e_reload(phys, slot),
+            });
+            self.free_slots.insert(slot);
+        }
+        self.vreg2phys.insert(vreg, phys);
+        Ok(phys)
     }
 
-    pub fn f_free(&mut self, vreg: u32) {
-        if self.f_vreg2phys_map.contains_key(&vreg) {
-            let phys = self.f_vreg2phys_map.remove(&vreg).unwrap();
-            self.f_used[phys as usize] = false;
+    /// The physical register instruction `at` writes `vreg` to, discarding any spilled value.
+    fn write(
+        &mut self,
+        vreg: u32,
+        at: u32,
+        pinned: &[u32],
+        output: &mut Vec<Instruction32>,
+    ) -> Result<u32, CodegenErrorKind> {
+        if let Some(slot) = self.vreg2slot.remove(&vreg) {
+            self.free_slots.insert(slot);
         }
+        self.read(vreg, at, pinned, output)
     }
 
-    pub fn ef_free(&mut self, vreg: u32) {
-        if self.ef_vreg2phys_map.contains_key(&vreg) {
-            let phys = self.ef_vreg2phys_map.remove(&vreg).unwrap();
-            self.ef_used[phys as usize] = false;
+    fn free(&mut self, vreg: u32) {
+        if let Some(phys) = self.vreg2phys.remove(&vreg) {
+            self.free.insert(phys);
+        }
+        if let Some(slot) = self.vreg2slot.remove(&vreg) {
+            self.free_slots.insert(slot);
         }
     }
 }
 
-pub fn optimize(instructions: Vec<Instruction32>) -> (Vec<Instruction16>, usize, usize) {
-    let mut f_first_time_vreg_used: HashMap<u32, u32> = HashMap::new();
-    let mut f_last_time_vreg_used: HashMap<u32, u32> = HashMap::new();
-    let mut ef_first_time_vreg_used: HashMap<u32, u32> = HashMap::new();
-    let mut ef_last_time_vreg_used: HashMap<u32, u32> = HashMap::new();
+/// Which of the `a`, `b` and `c` operands of an instruction with `opcode` are registers of
+/// `file`.
+fn file_operands(file: RegisterFile, opcode: Opcode) -> [bool; 3] {
+    match file {
+        RegisterFile::F => [opcode.is_f_assign(), opcode.is_f_arg1(), opcode.is_f_arg2()],
+        RegisterFile::EF => [opcode.is_e_assign(), opcode.is_e_arg1(), opcode.is_e_arg2()],
+    }
+}
+
+/// The registers of `file` that `instr` reads or writes.
+fn file_registers(file: RegisterFile, instr: &Instruction32) -> impl Iterator<Item = u32> {
+    let operands = file_operands(file, instr.decode_opcode());
+    [instr.a, instr.b, instr.c].into_iter().zip(operands).filter(|&(_, used)| used).map(|(r, _)| r)
+}
+
+/// Maps the virtual registers of `instructions` to at most `budget` physical registers per file,
+/// inserting spills and reloads where more values are live than fit. Returns the code and the
+/// highest F and EF physical registers used.
+pub fn allocate_registers(
+    instructions: &[Instruction32],
+    budget: RegisterBudget,
+) -> Result<(Vec<Instruction32>, usize, usize), CodegenErrorKind> {
+    let mut f = RegisterAllocator::new(RegisterFile::F, budget.f, instructions);
+    let mut ef = RegisterAllocator::new(RegisterFile::EF, budget.ef, instructions);
 
+    let mut output = Vec::with_capacity(instructions.len());
     for (i, instr) in instructions.iter().enumerate() {
         let i = i as u32;
-        let opcode = Opcode::from(instr.opcode);
+        let opcode = instr.decode_opcode();
+        let mut new_instr = *instr;
+        for allocator in [&mut f, &mut ef] {
+            let [in_a, in_b, in_c] = file_operands(allocator.file, opcode);
+            let pinned = file_registers(allocator.file, instr).collect::<Vec<_>>();
+            if in_b {
+                new_instr.b = allocator.read(instr.b, i, &pinned, &mut output)?;
+            }
+            if in_c {
+                new_instr.c = allocator.read(instr.c, i, &pinned, &mut output)?;
+            }
+            if in_a && (opcode.is_in_place() || opcode.is_assert()) {
+                new_instr.a = allocator.read(instr.a, i, &pinned, &mut output)?;
+            } else if in_a {
+                new_instr.a = allocator.write(instr.a, i, &pinned, &mut output)?;
+            }
+        }
+        output.push(new_instr);
 
-        if opcode.is_f_assign() {
-            f_first_time_vreg_used.entry(instr.a).or_insert(i);
-            f_last_time_vreg_used.insert(instr.a, i);
+        for allocator in [&mut f, &mut ef] {
+            for vreg in file_registers(allocator.file, instr) {
+                if allocator.is_last_use(vreg, i) {
+                    allocator.free(vreg);
+                }
+            }
         }
+    }
 
-        if opcode.is_f_arg1() {
-            f_first_time_vreg_used.entry(instr.b).or_insert(i);
-            f_last_time_vreg_used.insert(instr.b, i);
+    tracing::info!(
+        "register allocation spilled {} F values to {} slots and {} EF values to {} slots",
+        f.spills,
+        f.num_slots,
+        ef.spills,
+        ef.num_slots
+    );
+    Ok((output, f.max, ef.max))
+}
+
+/// The most values of each register file that are live at once in `instructions`, which is the
+/// highest physical register [`allocate_registers`] uses when nothing is spilled.
+pub fn register_pressure(instructions: &[Instruction32]) -> (usize, usize) {
+    let mut peaks = [0, 0];
+    for (peak, file) in peaks.iter_mut().zip([RegisterFile::F, RegisterFile::EF]) {
+        // Virtual registers are numbered densely by the tracer, so plain vectors index them.
+        let mut last_use = Vec::new();
+        for (i, instr) in instructions.iter().enumerate() {
+            for vreg in file_registers(file, instr) {
+                if last_use.len() <= vreg as usize {
+                    last_use.resize(vreg as usize + 1, 0);
+                }
+                last_use[vreg as usize] = i;
+            }
         }
+        let mut live = vec![false; last_use.len()];
+        let mut num_live = 0;
+        for (i, instr) in instructions.iter().enumerate() {
+            for vreg in file_registers(file, instr) {
+                if !live[vreg as usize] {
+                    live[vreg as usize] = true;
+                    num_live += 1;
+                }
+            }
+            *peak = (*peak).max(num_live);
+            for vreg in file_registers(file, instr) {
+                if last_use[vreg as usize] == i && live[vreg as usize] {
+                    live[vreg as usize] = false;
+                    num_live -= 1;
+                }
+            }
+        }
+    }
+    (peaks[0], peaks[1])
+}
 
-        if opcode.is_f_arg2() {
-            f_first_time_vreg_used.entry(instr.c).or_insert(i);
-            f_last_time_vreg_used.insert(instr.c, i);
+/// The shared-memory footprint of a register pressure, in F-sized words.
+fn footprint((f, ef): (usize, usize)) -> usize {
+    f + 4 * ef
+}
+
+/// Reorders independent instructions to reduce the number of values live at once.
+///
+/// Each assert is scheduled in program order, right after the instructions it depends on that
+/// were not scheduled yet. Those are emitted depth first, visiting the operand that needs the
+/// most registers first, as in Sethi-Ullman numbering; on a tree this evaluates each subexpression
+/// completely before starting the next. Instructions that write a register in place keep their
+/// order with every other read and write of that register. If the new order has a larger register
+/// footprint than the original, the original is returned.
+pub fn schedule(instructions: Vec<Instruction32>) -> Vec<Instruction32> {
+    schedule_with_pressure(instructions).0
+}
+
+/// [`schedule`], also returning the register pressure of the original and the returned code.
+fn schedule_with_pressure(
+    instructions: Vec<Instruction32>,
+) -> (Vec<Instruction32>, (usize, usize), (usize, usize)) {
+    let n = instructions.len();
+    let mut deps: Vec<Vec<usize>> = vec![Vec::new(); n];
+    let mut need = vec![0usize; n];
+    // The last writer and the readers since then of every virtual register, per file.
+    let mut last_writer: [HashMap<u32, usize>; 2] = Default::default();
+    let mut readers: [HashMap<u32, Vec<usize>>; 2] = Default::default();
+    for (i, instr) in instructions.iter().enumerate() {
+        let opcode = instr.decode_opcode();
+        let mut operand_needs = Vec::new();
+        for (k, file) in [RegisterFile::F, RegisterFile::EF].into_iter().enumerate() {
+            let [in_a, in_b, in_c] = file_operands(file, opcode);
+            let reads_a = in_a && (opcode.is_in_place() || opcode.is_assert());
+            let writes_a = in_a && !opcode.is_assert();
+            let reads = [(reads_a, instr.a), (in_b, instr.b), (in_c, instr.c)];
+            for (_, vreg) in reads.into_iter().filter(|&(read, _)| read) {
+                if let Some(&writer) = last_writer[k].get(&vreg) {
+                    deps[i].push(writer);
+                    operand_needs.push(need[writer]);
+                }
+                readers[k].entry(vreg).or_default().push(i);
+            }
+            if writes_a {
+                deps[i].extend(last_writer[k].insert(instr.a, i));
+                let war = readers[k].remove(&instr.a).unwrap_or_default();
+                deps[i].extend(war.into_iter().filter(|&r| r != i));
+            }
         }
+        operand_needs.sort_unstable_by(|x, y| y.cmp(x));
+        need[i] = operand_needs.iter().enumerate().map(|(k, need)| need + k).max().unwrap_or(1);
+        deps[i].sort_unstable();
+        deps[i].dedup();
+    }
 
-        if opcode.is_e_assign() {
-            ef_first_time_vreg_used.entry(instr.a).or_insert(i);
-            ef_last_time_vreg_used.insert(instr.a, i);
+    let asserts = (0..n).filter(|&i| instructions[i].decode_opcode().is_assert());
+    let mut scheduled = vec![false; n];
+    let mut order = Vec::with_capacity(n);
+    for root in asserts.chain(0..n) {
+        let mut stack = vec![(root, false)];
+        while let Some((node, expanded)) = stack.pop() {
+            if scheduled[node] {
+                continue;
+            }
+            if expanded {
+                scheduled[node] = true;
+                order.push(node);
+                continue;
+            }
+            stack.push((node, true));
+            let mut pending =
+                deps[node].iter().copied().filter(|&d| !scheduled[d]).collect::<Vec<_>>();
+            // The last dependency pushed is visited first: the one with the highest need, and
+            // the earliest among equals.
+            pending.sort_unstable_by_key(|&d| (need[d], Reverse(d)));
+            stack.extend(pending.into_iter().map(|d| (d, false)));
         }
+    }
 
-        if opcode.is_e_arg1() {
-            ef_first_time_vreg_used.entry(instr.b).or_insert(i);
-            ef_last_time_vreg_used.insert(instr.b, i);
+    let reordered = order.iter().map(|&i| instructions[i]).collect::<Vec<_>>();
+    let before = register_pressure(&instructions);
+    let after = register_pressure(&reordered);
+    if footprint(after) <= footprint(before) {
+        (reordered, before, after)
+    } else {
+        (instructions, before, before)
+    }
+}
+
+/// Collects the F and EF registers that some instruction writes in place.
+fn in_place_registers(instructions: &[Instruction32]) -> (HashSet<u32>, HashSet<u32>) {
+    let mut f_in_place = HashSet::new();
+    let mut ef_in_place = HashSet::new();
+    for instr in instructions.iter() {
+        let opcode = instr.decode_opcode();
+        if opcode.is_in_place() && opcode.is_f_assign() {
+            f_in_place.insert(instr.a);
+        }
+        if opcode.is_in_place() && opcode.is_e_assign() {
+            ef_in_place.insert(instr.a);
         }
+    }
+    (f_in_place, ef_in_place)
+}
+
+/// Rewrites every register `instr` reads through the rename maps.
+fn rename_operands(
+    instr: &mut Instruction32,
+    opcode: Opcode,
+    f_rename: &HashMap<u32, u32>,
+    ef_rename: &HashMap<u32, u32>,
+) {
+    let reads_a = opcode.is_in_place() || opcode.is_assert();
+    if reads_a && opcode.is_f_assign() {
+        instr.a = *f_rename.get(&instr.a).unwrap_or(&instr.a);
+    }
+    if reads_a && opcode.is_e_assign() {
+        instr.a = *ef_rename.get(&instr.a).unwrap_or(&instr.a);
+    }
+    if opcode.is_f_arg1() {
+        instr.b = *f_rename.get(&instr.b).unwrap_or(&instr.b);
+    }
+    if opcode.is_f_arg2() {
+        instr.c = *f_rename.get(&instr.c).unwrap_or(&instr.c);
+    }
+    if opcode.is_e_arg1() {
+        instr.b = *ef_rename.get(&instr.b).unwrap_or(&instr.b);
+    }
+    if opcode.is_e_arg2() {
+        instr.c = *ef_rename.get(&instr.c).unwrap_or(&instr.c);
+    }
+}
+
+/// The opcode and operands an instruction's result is keyed on during CSE.
+type ValueKey = (u8, u8, u32, u8, u32);
+
+fn value_key(opcode: Opcode, instr: &Instruction32) -> ValueKey {
+    let b = (instr.b_variant, instr.b);
+    let c = (instr.c_variant, instr.c);
+    let (opcode, b, c) = match opcode {
+        Opcode::FAddEV => (Opcode::FAddVE, c, b),
+        Opcode::FMulEV => (Opcode::FMulVE, c, b),
+        Opcode::EAddEV => (Opcode::EAddVE, c, b),
+        Opcode::EMulEV => (Opcode::EMulVE, c, b),
+        Opcode::FAddVV
+        | Opcode::FAddEE
+        | Opcode::FMulVV
+        | Opcode::FMulEE
+        | Opcode::EAddVV
+        | Opcode::EAddEE
+        | Opcode::EMulVV
+        | Opcode::EMulEE => (opcode, b.min(c), b.max(c)),
+        _ => (opcode, b, c),
+    };
+    (opcode as u8, b.0, b.1, c.0, c.1)
+}
+
+/// Removes instructions that recompute a value some earlier instruction already computed, and
+/// rewrites later uses to read the earlier result. Returns the new code and the number of
+/// instructions removed.
+///
+/// Constant and variable loads are cheap to repeat, so they are kept in place to avoid stretching
+/// their live ranges over the whole program; they are only value-numbered so that arithmetic on
+/// repeated loads still matches. Registers that are written in place hold different values over
+/// the program, so instructions that read or define them are never merged.
+pub fn eliminate_common_subexpressions(
+    instructions: Vec<Instruction32>,
+) -> (Vec<Instruction32>, usize) {
+    let (f_in_place, ef_in_place) = in_place_registers(&instructions);
+
+    let mut f_rename: HashMap<u32, u32> = HashMap::new();
+    let mut ef_rename: HashMap<u32, u32> = HashMap::new();
+    let mut values: HashMap<ValueKey, u32> = HashMap::new();
+    let mut f_value_numbers: HashMap<u32, u32> = HashMap::new();
+    let mut ef_value_numbers: HashMap<u32, u32> = HashMap::new();
+    let mut output = Vec::with_capacity(instructions.len());
+    let num_instructions = instructions.len();
 
+    for mut instr in instructions {
+        let opcode = instr.decode_opcode();
+        let reads_a = opcode.is_in_place() || opcode.is_assert();
+        rename_operands(&mut instr, opcode, &f_rename, &ef_rename);
+
+        let is_pure = (opcode.is_f_assign() || opcode.is_e_assign())
+            && !reads_a
+            && opcode != Opcode::EFAsBaseSlice;
+        let touches_in_place = (opcode.is_f_assign() && f_in_place.contains(&instr.a))
+            || (opcode.is_e_assign() && ef_in_place.contains(&instr.a))
+            || (opcode.is_f_arg1() && f_in_place.contains(&instr.b))
+            || (opcode.is_f_arg2() && f_in_place.contains(&instr.c))
+            || (opcode.is_e_arg1() && ef_in_place.contains(&instr.b))
+            || (opcode.is_e_arg2() && ef_in_place.contains(&instr.c));
+        if !is_pure || touches_in_place {
+            output.push(instr);
+            continue;
+        }
+
+        let mut keyed = instr;
+        if opcode.is_f_arg1() {
+            keyed.b = *f_value_numbers.get(&keyed.b).unwrap_or(&keyed.b);
+        }
+        if opcode.is_f_arg2() {
+            keyed.c = *f_value_numbers.get(&keyed.c).unwrap_or(&keyed.c);
+        }
+        if opcode.is_e_arg1() {
+            keyed.b = *ef_value_numbers.get(&keyed.b).unwrap_or(&keyed.b);
+        }
         if opcode.is_e_arg2() {
-            ef_first_time_vreg_used.entry(instr.c).or_insert(i);
-            ef_last_time_vreg_used.insert(instr.c, i);
+            keyed.c = *ef_value_numbers.get(&keyed.c).unwrap_or(&keyed.c);
+        }
+
+        let is_load = matches!(
+            opcode,
+            Opcode::FAssignC | Opcode::FAssignV | Opcode::EAssignC | Opcode::EAssignV
+        );
+        match values.entry(value_key(opcode, &keyed)) {
+            Entry::Occupied(entry) if is_load => {
+                let value_numbers =
+                    if opcode.is_f_assign() { &mut f_value_numbers } else { &mut ef_value_numbers };
+                value_numbers.insert(instr.a, *entry.get());
+                output.push(instr);
+            }
+            Entry::Occupied(entry) => {
+                let rename = if opcode.is_f_assign() { &mut f_rename } else { &mut ef_rename };
+                rename.insert(instr.a, *entry.get());
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(instr.a);
+                output.push(instr);
+            }
         }
     }
 
-    let mut optimized_instructions = Vec::new();
-    let mut allocator = RegisterAllocator::new();
-    for (i, instr) in instructions.iter().enumerate() {
-        let i = i as u32;
-        let opcode = Opcode::from(instr.opcode);
+    let removed = num_instructions - output.len();
+    (output, removed)
+}
 
-        let mut new_instr = *instr;
-        if opcode.is_f_assign() {
-            let phys_a = allocator.f_vreg2phys(instr.a);
-            new_instr.a = phys_a;
+/// Removes every instruction whose result never reaches an `FAssertZero` or `EAssertZero`.
+/// Returns the new code and the number of instructions removed.
+///
+/// Liveness is computed backwards from the asserts. An in-place instruction both reads and
+/// writes its destination, so it keeps the destination live; only the instruction that first
+/// defines a register ends its live range.
+pub fn eliminate_dead_code(instructions: Vec<Instruction32>) -> (Vec<Instruction32>, usize) {
+    let mut f_live = HashSet::new();
+    let mut ef_live = HashSet::new();
+    let mut output = Vec::with_capacity(instructions.len());
+    let num_instructions = instructions.len();
+
+    for instr in instructions.into_iter().rev() {
+        let opcode = instr.decode_opcode();
+        let reads_a = opcode.is_in_place() || opcode.is_assert();
+
+        let is_live = if opcode.is_assert() {
+            true
+        } else if opcode.is_f_assign() && reads_a {
+            f_live.contains(&instr.a)
+        } else if opcode.is_f_assign() {
+            f_live.remove(&instr.a)
+        } else if opcode.is_e_assign() && reads_a {
+            ef_live.contains(&instr.a)
+        } else if opcode.is_e_assign() {
+            ef_live.remove(&instr.a)
+        } else {
+            false
+        };
+        if !is_live {
+            continue;
         }
 
+        if reads_a && opcode.is_f_assign() {
+            f_live.insert(instr.a);
+        }
+        if reads_a && opcode.is_e_assign() {
+            ef_live.insert(instr.a);
+        }
         if opcode.is_f_arg1() {
-            let phys_b = allocator.f_vreg2phys(instr.b);
-            new_instr.b = phys_b;
+            f_live.insert(instr.b);
         }
-
         if opcode.is_f_arg2() {
-            let phys_c = allocator.f_vreg2phys(instr.c);
-            new_instr.c = phys_c;
+            f_live.insert(instr.c);
         }
+        if opcode.is_e_arg1() {
+            ef_live.insert(instr.b);
+        }
+        if opcode.is_e_arg2() {
+            ef_live.insert(instr.c);
+        }
+        output.push(instr);
+    }
+
+    output.reverse();
+    let removed = num_instructions - output.len();
+    (output, removed)
+}
 
-        if opcode.is_e_assign() {
-            let phys_a = allocator.ef_vreg2phys(instr.a);
-            new_instr.a = phys_a;
+/// The fused opcode computing `b * c + a`, or `b * c - a` if `sub`, for a multiplication with
+/// opcode `mul`. Multiplications by constants have none.
+fn fused_opcode(mul: Opcode, sub: bool) -> Option<Opcode> {
+    let fused = match (mul, sub) {
+        (Opcode::FMulVV, false) => Opcode::FMulAddVVE,
+        (Opcode::FMulVE, false) => Opcode::FMulAddVEE,
+        (Opcode::FMulEV, false) => Opcode::FMulAddEVE,
+        (Opcode::FMulEE, false) => Opcode::FMulAddEEE,
+        (Opcode::FMulVV, true) => Opcode::FMulSubVVE,
+        (Opcode::FMulVE, true) => Opcode::FMulSubVEE,
+        (Opcode::FMulEV, true) => Opcode::FMulSubEVE,
+        (Opcode::FMulEE, true) => Opcode::FMulSubEEE,
+        (Opcode::EMulVV, false) => Opcode::EMulAddVVE,
+        (Opcode::EMulVE, false) => Opcode::EMulAddVEE,
+        (Opcode::EMulEV, false) => Opcode::EMulAddEVE,
+        (Opcode::EMulEE, false) => Opcode::EMulAddEEE,
+        (Opcode::EMulVV, true) => Opcode::EMulSubVVE,
+        (Opcode::EMulVE, true) => Opcode::EMulSubVEE,
+        (Opcode::EMulEV, true) => Opcode::EMulSubEVE,
+        (Opcode::EMulEE, true) => Opcode::EMulSubEEE,
+        _ => return None,
+    };
+    Some(fused)
+}
+
+/// Fuses multiplications whose result is read once into the addition or subtraction reading it.
+/// Returns the new code and the number of instructions removed.
+///
+/// The fused instructions accumulate in place: `acc += t` with `t = x * y` becomes
+/// `acc = x * y + acc`, and `z = t + w` or `z = t - w` becomes `w = x * y + w` or `w = x * y - w`
+/// when that is the only read of `w`, with later reads of `z` renamed to `w`. Registers that are
+/// written in place are never renamed, and multiplications reading them are not moved.
+pub fn fuse_multiply_add(instructions: Vec<Instruction32>) -> (Vec<Instruction32>, usize) {
+    let (f_in_place, ef_in_place) = in_place_registers(&instructions);
+    let in_place = [f_in_place, ef_in_place];
+    let files = [RegisterFile::F, RegisterFile::EF];
+
+    let mut reads: [HashMap<u32, usize>; 2] = Default::default();
+    for instr in &instructions {
+        let opcode = instr.decode_opcode();
+        let reads_a = opcode.is_in_place() || opcode.is_assert();
+        for (k, file) in files.into_iter().enumerate() {
+            let [in_a, in_b, in_c] = file_operands(file, opcode);
+            let operands = [(in_a && reads_a, instr.a), (in_b, instr.b), (in_c, instr.c)];
+            for (_, reg) in operands.into_iter().filter(|&(read, _)| read) {
+                *reads[k].entry(reg).or_default() += 1;
+            }
         }
+    }
+    let is_single_read = |k: usize, reg: u32| reads[k].get(&reg) == Some(&1);
 
-        if opcode.is_e_arg1() {
-            let phys_b = allocator.ef_vreg2phys(instr.b);
-            new_instr.b = phys_b;
+    let mut f_rename: HashMap<u32, u32> = HashMap::new();
+    let mut ef_rename: HashMap<u32, u32> = HashMap::new();
+    // The fusable multiplications by destination register, with their position in `output`.
+    let mut products: [HashMap<u32, (usize, Instruction32)>; 2] = Default::default();
+    let mut output: Vec<Option<Instruction32>> = Vec::with_capacity(instructions.len());
+    let mut fused = 0;
+
+    for original in instructions {
+        let opcode = original.decode_opcode();
+        let mut instr = original;
+        rename_operands(&mut instr, opcode, &f_rename, &ef_rename);
+        let k = if opcode.is_f_assign() { 0 } else { 1 };
+
+        if fused_opcode(opcode, false).is_some() {
+            let [_, in_b, in_c] = file_operands(files[k], opcode);
+            let reads_in_place = (in_b && in_place[k].contains(&original.b))
+                || (in_c && in_place[k].contains(&original.c));
+            if is_single_read(k, original.a)
+                && !in_place[k].contains(&original.a)
+                && !reads_in_place
+            {
+                products[k].insert(original.a, (output.len(), instr));
+            }
         }
 
-        if opcode.is_e_arg2() {
-            let phys_c = allocator.ef_vreg2phys(instr.c);
-            new_instr.c = phys_c;
+        // The product, whether the result is `product - addend`, the accumulator register and
+        // the result register to rename to it.
+        let fusion = match opcode {
+            Opcode::FAddAssignE | Opcode::EAddAssignE => Some((original.b, false, instr.a, None)),
+            Opcode::FAddEE | Opcode::EAddEE if products[k].contains_key(&original.c) => {
+                Some((original.c, false, instr.b, Some(original.b)))
+            }
+            Opcode::FAddEE | Opcode::EAddEE => Some((original.b, false, instr.c, Some(original.c))),
+            Opcode::FSubEE | Opcode::ESubEE => Some((original.b, true, instr.c, Some(original.c))),
+            _ => None,
+        };
+        let fusion = fusion.filter(|&(product, _, _, addend)| {
+            products[k].contains_key(&product)
+                && addend.map_or(true, |addend| {
+                    addend != product
+                        && is_single_read(k, addend)
+                        && !in_place[k].contains(&addend)
+                        && !in_place[k].contains(&original.a)
+                })
+        });
+        let Some((product, sub, accumulator, addend)) = fusion else {
+            output.push(Some(instr));
+            continue;
+        };
+
+        let (position, mul) = products[k].remove(&product).unwrap();
+        output[position] = None;
+        let fused_opcode = fused_opcode(mul.decode_opcode(), sub).unwrap();
+        output.push(Some(Instruction32 { opcode: fused_opcode as u8, a: accumulator, ..mul }));
+        if addend.is_some() {
+            let rename = if k == 0 { &mut f_rename } else { &mut ef_rename };
+            rename.insert(original.a, accumulator);
+        }
+        fused += 1;
+    }
+
+    (output.into_iter().flatten().collect(), fused)
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ArithOp {
+    Add,
+    Sub,
+    Mul,
+}
+
+impl ArithOp {
+    fn apply<T: Field>(self, x: T, y: T) -> T {
+        match self {
+            ArithOp::Add => x + y,
+            ArithOp::Sub => x - y,
+            ArithOp::Mul => x * y,
+        }
+    }
+}
+
+/// How an operand of an arithmetic instruction is encoded.
+#[derive(Debug, Clone, Copy)]
+enum OperandKind {
+    Const,
+    Var,
+    Expr,
+}
+
+/// The operation and operand encodings of the binary F and EF opcodes.
+fn arithmetic_shape(opcode: Opcode) -> Option<(ArithOp, OperandKind, OperandKind)> {
+    use OperandKind::{Const, Expr, Var};
+    let shape = match opcode {
+        Opcode::FAddVC | Opcode::EAddVC => (ArithOp::Add, Var, Const),
+        Opcode::FAddVV | Opcode::EAddVV => (ArithOp::Add, Var, Var),
+        Opcode::FAddVE | Opcode::EAddVE => (ArithOp::Add, Var, Expr),
+        Opcode::FAddEC | Opcode::EAddEC => (ArithOp::Add, Expr, Const),
+        Opcode::FAddEV | Opcode::EAddEV => (ArithOp::Add, Expr, Var),
+        Opcode::FAddEE | Opcode::EAddEE => (ArithOp::Add, Expr, Expr),
+        Opcode::FSubVC | Opcode::ESubVC => (ArithOp::Sub, Var, Const),
+        Opcode::FSubVV | Opcode::ESubVV => (ArithOp::Sub, Var, Var),
+        Opcode::FSubVE | Opcode::ESubVE => (ArithOp::Sub, Var, Expr),
+        Opcode::FSubEC | Opcode::ESubEC => (ArithOp::Sub, Expr, Const),
+        Opcode::FSubEV | Opcode::ESubEV => (ArithOp::Sub, Expr, Var),
+        Opcode::FSubEE | Opcode::ESubEE => (ArithOp::Sub, Expr, Expr),
+        Opcode::FMulVC | Opcode::EMulVC => (ArithOp::Mul, Var, Const),
+        Opcode::FMulVV | Opcode::EMulVV => (ArithOp::Mul, Var, Var),
+        Opcode::FMulVE | Opcode::EMulVE => (ArithOp::Mul, Var, Expr),
+        Opcode::FMulEC | Opcode::EMulEC => (ArithOp::Mul, Expr, Const),
+        Opcode::FMulEV | Opcode::EMulEV => (ArithOp::Mul, Expr, Var),
+        Opcode::FMulEE | Opcode::EMulEE => (ArithOp::Mul, Expr, Expr),
+        _ => return None,
+    };
+    Some(shape)
+}
+
+/// A value as seen by constant folding: either an operand or the simplified result of an
+/// instruction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Value<T> {
+    Const(T),
+    Var(u8, u32),
+    Expr(u32),
+    /// The negation of an expression register.
+    Neg(u32),
+    /// An F expression register embedded into EF.
+    FromBase(u32),
+}
+
+/// Applies constant folding and the identities x+0, x-0, 0-x, x-x, x*1 and x*0.
+fn simplify<T: Field>(op: ArithOp, b: Value<T>, c: Value<T>) -> Option<Value<T>> {
+    use Value::{Const, Expr, Neg};
+    let value = match (op, b, c) {
+        (_, Const(x), Const(y)) => Const(op.apply(x, y)),
+        (ArithOp::Add, x, Const(zero)) | (ArithOp::Add, Const(zero), x) if zero.is_zero() => x,
+        (ArithOp::Sub, x, Const(zero)) if zero.is_zero() => x,
+        (ArithOp::Sub, Const(zero), Expr(x)) if zero.is_zero() => Neg(x),
+        (ArithOp::Sub, x, y) if x == y => Const(T::zero()),
+        (ArithOp::Mul, x, Const(one)) | (ArithOp::Mul, Const(one), x) if one.is_one() => x,
+        (ArithOp::Mul, _, Const(zero)) | (ArithOp::Mul, Const(zero), _) if zero.is_zero() => {
+            Const(T::zero())
         }
+        _ => return None,
+    };
+    Some(value)
+}
+
+fn load(opcode: Opcode, a: u32, b_variant: u8, b: u32) -> Instruction32 {
+    Instruction32 { opcode: opcode as u8, a, b_variant, b, c_variant: 0, c: 0 }
+}
 
-        optimized_instructions.push(new_instr);
+struct ConstantFolder<'a> {
+    f_constants: &'a mut Vec<F>,
+    ef_constants: &'a mut Vec<EF>,
+    f_index: PoolIndex<F>,
+    ef_index: PoolIndex<EF>,
+    f_in_place: HashSet<u32>,
+    ef_in_place: HashSet<u32>,
+    f_known: HashMap<u32, F>,
+    ef_known: HashMap<u32, EF>,
+    f_negated: HashMap<u32, u32>,
+    ef_negated: HashMap<u32, u32>,
+    f_rename: HashMap<u32, u32>,
+    ef_rename: HashMap<u32, u32>,
+}
 
-        if opcode.is_f_assign() && f_last_time_vreg_used.get(&instr.a).unwrap() == &i {
-            allocator.f_free(instr.a);
+impl<'a> ConstantFolder<'a> {
+    fn f_operand(&self, kind: OperandKind, variant: u8, x: u32) -> Value<F> {
+        match kind {
+            OperandKind::Const => Value::Const(self.f_constants[x as usize]),
+            OperandKind::Var => match SymbolicVarF::decode(variant, x) {
+                Some(SymbolicVarF::Constant(i)) => Value::Const(self.f_constants[i as usize]),
+                _ => Value::Var(variant, x),
+            },
+            OperandKind::Expr => self.f_known.get(&x).map_or(Value::Expr(x), |&v| Value::Const(v)),
         }
+    }
 
-        if opcode.is_f_arg1() && f_last_time_vreg_used.get(&instr.b).unwrap() == &i {
-            allocator.f_free(instr.b);
+    fn ef_operand(&self, kind: OperandKind, variant: u8, x: u32) -> Value<EF> {
+        match kind {
+            OperandKind::Const => Value::Const(self.ef_constants[x as usize]),
+            OperandKind::Var => Value::Var(variant, x),
+            OperandKind::Expr => self.ef_known.get(&x).map_or(Value::Expr(x), |&v| Value::Const(v)),
         }
+    }
 
-        if opcode.is_f_arg2() && f_last_time_vreg_used.get(&instr.c).unwrap() == &i {
-            allocator.f_free(instr.c);
+    /// Simplifies an instruction that assigns an F register.
+    fn fold_f(&self, opcode: Opcode, instr: &Instruction32) -> Option<Value<F>> {
+        if let Some((op, b, c)) = arithmetic_shape(opcode) {
+            let b = self.f_operand(b, instr.b_variant, instr.b);
+            let c = self.f_operand(c, instr.c_variant, instr.c);
+            return simplify(op, b, c);
+        }
+        match opcode {
+            Opcode::FAssignV
+                if matches!(
+                    SymbolicVarF::decode(instr.b_variant, instr.b),
+                    Some(SymbolicVarF::Constant(_))
+                ) =>
+            {
+                Some(self.f_operand(OperandKind::Var, instr.b_variant, instr.b))
+            }
+            Opcode::FAssignE => Some(self.f_operand(OperandKind::Expr, 0, instr.b)),
+            Opcode::FNegE => match self.f_operand(OperandKind::Expr, 0, instr.b) {
+                Value::Const(x) => Some(Value::Const(-x)),
+                _ => self.f_negated.get(&instr.b).map(|&x| Value::Expr(x)),
+            },
+            _ => None,
         }
+    }
 
-        if opcode.is_e_assign() && ef_last_time_vreg_used.get(&instr.a).unwrap() == &i {
-            allocator.ef_free(instr.a);
+    /// Simplifies an instruction that assigns an EF register.
+    fn fold_ef(&self, opcode: Opcode, instr: &Instruction32) -> Option<Value<EF>> {
+        if let Some((op, b, c)) = arithmetic_shape(opcode) {
+            let b = self.ef_operand(b, instr.b_variant, instr.b);
+            let c = self.ef_operand(c, instr.c_variant, instr.c);
+            return simplify(op, b, c);
+        }
+        match opcode {
+            Opcode::EAssignE => Some(self.ef_operand(OperandKind::Expr, 0, instr.b)),
+            Opcode::ENegE => match self.ef_operand(OperandKind::Expr, 0, instr.b) {
+                Value::Const(x) => Some(Value::Const(-x)),
+                _ => self.ef_negated.get(&instr.b).map(|&x| Value::Expr(x)),
+            },
+            Opcode::EFFromE => self.f_known.get(&instr.b).map(|&x| Value::Const(EF::from_base(x))),
+            Opcode::EFAddEE | Opcode::EFSubEE | Opcode::EFMulEE => {
+                use Value::{Const, FromBase};
+                let b = self.ef_operand(OperandKind::Expr, 0, instr.b);
+                let c = self.f_known.get(&instr.c).map(|&x| EF::from_base(x));
+                match (opcode, b, c) {
+                    (Opcode::EFAddEE, Const(x), Some(y)) => Some(Const(x + y)),
+                    (Opcode::EFSubEE, Const(x), Some(y)) => Some(Const(x - y)),
+                    (Opcode::EFMulEE, Const(x), Some(y)) => Some(Const(x * y)),
+                    (Opcode::EFAddEE | Opcode::EFSubEE, x, Some(zero)) if zero.is_zero() => Some(x),
+                    (Opcode::EFAddEE, Const(zero), None) if zero.is_zero() => {
+                        Some(FromBase(instr.c))
+                    }
+                    (Opcode::EFMulEE, x, Some(one)) if one.is_one() => Some(x),
+                    (Opcode::EFMulEE, Const(one), None) if one.is_one() => Some(FromBase(instr.c)),
+                    (Opcode::EFMulEE, _, Some(zero)) | (Opcode::EFMulEE, Const(zero), _)
+                        if zero.is_zero() =>
+                    {
+                        Some(Const(EF::zero()))
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
         }
+    }
+
+    /// Lowers a simplified F value back into an instruction assigning `a`, or renames `a` when
+    /// the value already lives in another register.
+    fn emit_f(&mut self, a: u32, value: Value<F>) -> Option<Instruction32> {
+        let instr = match value {
+            Value::Const(x) => {
+                load(Opcode::FAssignC, a, 0, self.f_index.intern(self.f_constants, x))
+            }
+            Value::Var(variant, x) => load(Opcode::FAssignV, a, variant, x),
+            Value::Expr(x) if !self.f_in_place.contains(&a) && !self.f_in_place.contains(&x) => {
+                self.f_rename.insert(a, x);
+                return None;
+            }
+            Value::Expr(x) => load(Opcode::FAssignE, a, 0, x),
+            Value::Neg(x) => load(Opcode::FNegE, a, 0, x),
+            Value::FromBase(_) => unreachable!("F values are never embedded"),
+        };
+        Some(instr)
+    }
+
+    /// Lowers a simplified EF value back into an instruction assigning `a`, or renames `a` when
+    /// the value already lives in another register.
+    fn emit_ef(&mut self, a: u32, value: Value<EF>) -> Option<Instruction32> {
+        let instr = match value {
+            Value::Const(x) => {
+                load(Opcode::EAssignC, a, 0, self.ef_index.intern(self.ef_constants, x))
+            }
+            Value::Var(variant, x) => load(Opcode::EAssignV, a, variant, x),
+            Value::Expr(x) if !self.ef_in_place.contains(&a) && !self.ef_in_place.contains(&x) => {
+                self.ef_rename.insert(a, x);
+                return None;
+            }
+            Value::Expr(x) => load(Opcode::EAssignE, a, 0, x),
+            Value::Neg(x) => load(Opcode::ENegE, a, 0, x),
+            Value::FromBase(x) => load(Opcode::EFFromE, a, 0, x),
+        };
+        Some(instr)
+    }
 
-        if opcode.is_e_arg1() && ef_last_time_vreg_used.get(&instr.b).unwrap() == &i {
-            allocator.ef_free(instr.b);
+    /// Remembers the constants and negations `instr` defines. Negations of registers that are
+    /// written in place are not remembered.
+    fn record(&mut self, instr: &Instruction32) {
+        let opcode = instr.decode_opcode();
+        if opcode.is_f_assign() && self.f_in_place.contains(&instr.a) {
+            return;
         }
+        if opcode.is_e_assign() && self.ef_in_place.contains(&instr.a) {
+            return;
+        }
+        match opcode {
+            Opcode::FAssignC => {
+                self.f_known.insert(instr.a, self.f_constants[instr.b as usize]);
+            }
+            Opcode::EAssignC => {
+                self.ef_known.insert(instr.a, self.ef_constants[instr.b as usize]);
+            }
+            Opcode::FNegE if !self.f_in_place.contains(&instr.b) => {
+                self.f_negated.insert(instr.a, instr.b);
+            }
+            Opcode::ENegE if !self.ef_in_place.contains(&instr.b) => {
+                self.ef_negated.insert(instr.a, instr.b);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Folds instructions whose inputs are all known constants into constant loads and applies the
+/// identities x+0, x-0, 0-x, x-x, x*1, x*0 and -(-x). Returns the new code and the number of
+/// instructions simplified or removed.
+///
+/// Constants produced by folding are appended to `f_constants` and `ef_constants`. Registers that
+/// are written in place are never treated as constants or negated values, and are never renamed.
+pub fn fold_constants(
+    instructions: Vec<Instruction32>,
+    f_constants: &mut Vec<F>,
+    ef_constants: &mut Vec<EF>,
+) -> (Vec<Instruction32>, usize) {
+    let (f_in_place, ef_in_place) = in_place_registers(&instructions);
+    let mut folder = ConstantFolder {
+        f_index: PoolIndex::new(f_constants),
+        ef_index: PoolIndex::new(ef_constants),
+        f_constants,
+        ef_constants,
+        f_in_place,
+        ef_in_place,
+        f_known: HashMap::new(),
+        ef_known: HashMap::new(),
+        f_negated: HashMap::new(),
+        ef_negated: HashMap::new(),
+        f_rename: HashMap::new(),
+        ef_rename: HashMap::new(),
+    };
+    let mut output = Vec::with_capacity(instructions.len());
+    let mut folded = 0;
 
-        if opcode.is_e_arg2() && ef_last_time_vreg_used.get(&instr.c).unwrap() == &i {
-            allocator.ef_free(instr.c);
+    for mut instr in instructions {
+        let opcode = instr.decode_opcode();
+        rename_operands(&mut instr, opcode, &folder.f_rename, &folder.ef_rename);
+
+        let simplified = if opcode.is_in_place() || opcode.is_assert() {
+            None
+        } else if opcode.is_f_assign() {
+            folder.fold_f(opcode, &instr).map(|value| folder.emit_f(instr.a, value))
+        } else if opcode.is_e_assign() {
+            folder.fold_ef(opcode, &instr).map(|value| folder.emit_ef(instr.a, value))
+        } else {
+            None
+        };
+
+        match simplified {
+            Some(Some(new_instr)) if new_instr == instr => {
+                folder.record(&instr);
+                output.push(instr);
+            }
+            Some(Some(new_instr)) => {
+                folded += 1;
+                folder.record(&new_instr);
+                output.push(new_instr);
+            }
+            Some(None) => folded += 1,
+            None => {
+                folder.record(&instr);
+                output.push(instr);
+            }
         }
     }
 
-    (
-        optimized_instructions
-            .into_iter()
-            .map(|instr| Instruction16 {
+    (output, folded)
+}
+
+/// Lowers register-allocated code to [`Instruction16`], failing on the first operand that does
+/// not fit in 16 bits instead of truncating it.
+pub fn lower_to_instruction16(
+    instructions: &[Instruction32],
+) -> Result<Vec<Instruction16>, CodegenErrorKind> {
+    instructions
+        .iter()
+        .enumerate()
+        .map(|(index, instr)| {
+            let narrow = |operand: char, value: u32| {
+                u16::try_from(value).map_err(|_| CodegenErrorKind::OperandOutOfRange {
+                    index,
+                    operand,
+                    value,
+                })
+            };
+            Ok(Instruction16 {
                 opcode: instr.opcode,
                 b_variant: instr.b_variant,
                 c_variant: instr.c_variant,
-                a: instr.a as u16,
-                b: instr.b as u16,
-                c: instr.c as u16,
+                a: narrow('a', instr.a)?,
+                b: narrow('b', instr.b)?,
+                c: narrow('c', instr.c)?,
             })
-            .collect(),
-        allocator.f_max,
-        allocator.ef_max,
-    )
+        })
+        .collect()
+}
+
+/// Optimizes and register-allocates `instructions` into 16-bit instructions for the default
+/// [`RegisterBudget`]. Returns the code and the highest F and EF physical registers used.
+pub fn optimize(
+    instructions: Vec<Instruction32>,
+    f_constants: &mut Vec<F>,
+    ef_constants: &mut Vec<EF>,
+) -> Result<(Vec<Instruction16>, usize, usize), CodegenErrorKind> {
+    let (code, f_max, ef_max) = optimize_wide(instructions, f_constants, ef_constants)?;
+    Ok((lower_to_instruction16(&code)?, f_max, ef_max))
+}
+
+/// Like [`optimize`], but keeps the full-width [`Instruction32`] encoding for programs whose
+/// column or constant indices do not fit in 16 bits.
+pub fn optimize_wide(
+    instructions: Vec<Instruction32>,
+    f_constants: &mut Vec<F>,
+    ef_constants: &mut Vec<EF>,
+) -> Result<(Vec<Instruction32>, usize, usize), CodegenErrorKind> {
+    optimize_wide_with_budget(instructions, f_constants, ef_constants, RegisterBudget::default())
+}
+
+/// Like [`optimize_wide`], but allocates at most `budget` registers per file.
+pub fn optimize_wide_with_budget(
+    instructions: Vec<Instruction32>,
+    f_constants: &mut Vec<F>,
+    ef_constants: &mut Vec<EF>,
+    budget: RegisterBudget,
+) -> Result<(Vec<Instruction32>, usize, usize), CodegenErrorKind> {
+    let instructions = simplify_program(instructions, f_constants, ef_constants);
+
+    let (instructions, before, after) = schedule_with_pressure(instructions);
+    tracing::info!(
+        "scheduling changed the register pressure from {} F and {} EF to {} F and {} EF",
+        before.0,
+        before.1,
+        after.0,
+        after.1
+    );
+
+    allocate_registers(&instructions, budget)
+}
+
+/// Runs constant folding, common subexpression elimination, dead code elimination and
+/// multiply-add fusion, the passes that come before scheduling and register allocation.
+pub fn simplify_program(
+    instructions: Vec<Instruction32>,
+    f_constants: &mut Vec<F>,
+    ef_constants: &mut Vec<EF>,
+) -> Vec<Instruction32> {
+    let (instructions, folded) = fold_constants(instructions, f_constants, ef_constants);
+    tracing::info!("constant folding simplified {} instructions", folded);
+
+    let (instructions, removed) = eliminate_common_subexpressions(instructions);
+    tracing::info!("common subexpression elimination removed {} instructions", removed);
+
+    let (instructions, removed) = eliminate_dead_code(instructions);
+    tracing::info!("dead code elimination removed {} instructions", removed);
+
+    let (instructions, fused) = fuse_multiply_add(instructions);
+    tracing::info!("multiply-add fusion removed {} instructions", fused);
+    instructions
+}
+
+#[cfg(test)]
+mod tests {
+    use p3_field::{AbstractExtensionField, AbstractField};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use crate::{
+        asm::mnemonic,
+        error::{CodegenErrorKind, RegisterFile},
+        instruction::{Instruction32, Opcode},
+        interpreter::{interpret, ConstraintValue, InterpreterInputs},
+        symbolic_expr_ef::SymbolicExprEF,
+        symbolic_expr_f::SymbolicExprF,
+        symbolic_var_ef::SymbolicVarEF,
+        symbolic_var_f::SymbolicVarF,
+        trace::TraceContext,
+        EF, F,
+    };
+
+    use super::{
+        allocate_registers, eliminate_common_subexpressions, eliminate_dead_code, fold_constants,
+        fuse_multiply_add, lower_to_instruction16, optimize, optimize_wide,
+        optimize_wide_with_budget, register_pressure, schedule, simplify_program, RegisterBudget,
+    };
+
+    #[test]
+    fn test_cse_commutative() {
+        TraceContext::reset();
+        let x = SymbolicVarF::main_local(0) * SymbolicVarF::main_local(1);
+        let y = SymbolicVarF::main_local(1) * SymbolicVarF::main_local(0);
+        let u = x + SymbolicVarF::main_next(0);
+        let v = SymbolicVarF::main_next(0) + y;
+        TraceContext::push(Instruction32::f_assert_zero(u - v));
+        let code = TraceContext::take().code;
+
+        let (code, removed) = eliminate_common_subexpressions(code);
+        assert_eq!(removed, 2);
+        assert_eq!(code.len(), 4);
+    }
+
+    #[test]
+    fn test_cse_keeps_loads() {
+        TraceContext::reset();
+        let x = SymbolicExprF::from(SymbolicVarF::main_local(0));
+        let y = SymbolicExprF::from(SymbolicVarF::main_local(0));
+        TraceContext::push(Instruction32::f_assert_zero(x * x - y * y));
+        let code = TraceContext::take().code;
+
+        let (code, removed) = eliminate_common_subexpressions(code);
+        assert_eq!(removed, 1);
+        assert_eq!(code.len(), 5);
+    }
+
+    #[test]
+    fn test_cse_keeps_in_place_registers() {
+        TraceContext::reset();
+        let mut acc = SymbolicExprEF::zero();
+        let zero = SymbolicExprEF::zero();
+        acc += SymbolicExprEF::from(SymbolicVarEF::permutation_local(0));
+        TraceContext::push(Instruction32::e_assert_zero(acc));
+        TraceContext::push(Instruction32::e_assert_zero(zero));
+        let (code, mut f_constants, mut ef_constants) = TraceContext::take().into_parts();
+
+        let (code, _, _) = optimize(code, &mut f_constants, &mut ef_constants).unwrap();
+        let inputs = InterpreterInputs {
+            permutation_local: vec![EF::from_canonical_u32(7)],
+            ..Default::default()
+        };
+        let results = interpret(&code, &f_constants, &ef_constants, &inputs);
+        assert_eq!(
+            results,
+            vec![ConstraintValue::EF(EF::from_canonical_u32(7)), ConstraintValue::EF(EF::zero())]
+        );
+    }
+
+    #[test]
+    fn test_fold_constants_f() {
+        TraceContext::reset();
+        let x = SymbolicExprF::from(SymbolicVarF::main_local(0));
+        let y = SymbolicExprF::from(SymbolicVarF::main_local(1));
+        let sum: SymbolicExprF = [x, y].into_iter().sum();
+        let product: SymbolicExprF = [x, y].into_iter().product();
+        let twelve = SymbolicExprF::from_canonical_u32(3) * SymbolicExprF::from_canonical_u32(4);
+        let zero = x - x;
+        let double_neg = -(-x);
+        for value in [sum, product, twelve, zero, double_neg] {
+            TraceContext::push(Instruction32::f_assert_zero(value));
+        }
+        let (code, mut f_constants, mut ef_constants) = TraceContext::take().into_parts();
+
+        let (folded_code, folded) =
+            fold_constants(code.clone(), &mut f_constants, &mut ef_constants);
+        assert_eq!(folded, 5);
+        assert_eq!(folded_code.len(), code.len() - 3);
+
+        let (code, _, _) = optimize(code, &mut f_constants, &mut ef_constants).unwrap();
+        let inputs = InterpreterInputs {
+            main_local: vec![F::from_canonical_u32(2), F::from_canonical_u32(5)],
+            ..Default::default()
+        };
+        let results = interpret(&code, &f_constants, &ef_constants, &inputs);
+        let expected = [7, 10, 12, 0, 2].map(|x| ConstraintValue::F(F::from_canonical_u32(x)));
+        assert_eq!(results, expected);
+    }
+
+    #[test]
+    fn test_fold_constants_ef() {
+        TraceContext::reset();
+        let e = SymbolicExprEF::from(SymbolicVarEF::permutation_local(0));
+        let x = SymbolicExprF::from(SymbolicVarF::main_local(0));
+        let times_one = e * SymbolicExprF::one();
+        let plus_zero = e + SymbolicExprF::zero();
+        let times_zero = e * SymbolicExprF::zero();
+        let embedded = SymbolicExprEF::zero() + x;
+        let five = SymbolicExprEF::from(SymbolicExprF::from_canonical_u32(5));
+        let zero = e - e;
+        for value in [times_one, plus_zero, times_zero, embedded, five, zero] {
+            TraceContext::push(Instruction32::e_assert_zero(value));
+        }
+        let (code, mut f_constants, mut ef_constants) = TraceContext::take().into_parts();
+
+        let (_, folded) = fold_constants(code.clone(), &mut f_constants, &mut ef_constants);
+        assert_eq!(folded, 6);
+
+        let (code, _, _) = optimize(code, &mut f_constants, &mut ef_constants).unwrap();
+        let e = EF::from_base_slice(&[1, 2, 3, 4].map(F::from_canonical_u32));
+        let inputs = InterpreterInputs {
+            main_local: vec![F::from_canonical_u32(9)],
+            permutation_local: vec![e],
+            ..Default::default()
+        };
+        let results = interpret(&code, &f_constants, &ef_constants, &inputs);
+        let expected =
+            [e, e, EF::zero(), EF::from_canonical_u32(9), EF::from_canonical_u32(5), EF::zero()]
+                .map(ConstraintValue::EF);
+        assert_eq!(results, expected);
+    }
+
+    #[test]
+    fn test_fold_keeps_negations_of_in_place_registers() {
+        TraceContext::reset();
+        let x = SymbolicExprF::from(SymbolicVarF::main_local(0));
+        let neg_x = -x;
+        TraceContext::push(Instruction32::f_add_assign_e(x, SymbolicVarF::main_local(1).into()));
+        TraceContext::push(Instruction32::f_assert_zero(-neg_x));
+        let mut e = SymbolicExprEF::from(SymbolicVarEF::permutation_local(0));
+        let neg_e = -e;
+        e += SymbolicExprEF::from(SymbolicVarEF::permutation_local(1));
+        TraceContext::push(Instruction32::e_assert_zero(-neg_e));
+        let (code, mut f_constants, mut ef_constants) = TraceContext::take().into_parts();
+
+        let (code, _) = fold_constants(code, &mut f_constants, &mut ef_constants);
+        let inputs = InterpreterInputs {
+            main_local: [2, 3].map(F::from_canonical_u32).to_vec(),
+            permutation_local: [5, 7].map(EF::from_canonical_u32).to_vec(),
+            ..Default::default()
+        };
+        let results = interpret(
+            &lower_to_instruction16(&code).unwrap(),
+            &f_constants,
+            &ef_constants,
+            &inputs,
+        );
+        assert_eq!(
+            results,
+            vec![
+                ConstraintValue::F(F::from_canonical_u32(2)),
+                ConstraintValue::EF(EF::from_canonical_u32(5))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dce_keeps_only_asserted_values() {
+        TraceContext::reset();
+        let _unused = SymbolicExprF::default();
+        let x = SymbolicVarF::main_local(0) * SymbolicVarF::main_local(1);
+        let _discarded = x + SymbolicVarF::main_next(0);
+        TraceContext::push(Instruction32::f_assert_zero(x));
+        let code = TraceContext::take().code;
+
+        let (code, removed) = eliminate_dead_code(code);
+        assert_eq!(removed, 2);
+        assert_eq!(code.len(), 2);
+    }
+
+    #[test]
+    fn test_dce_handles_in_place_registers() {
+        TraceContext::reset();
+        let mut acc = SymbolicExprEF::zero();
+        acc += SymbolicExprEF::from(SymbolicVarEF::permutation_local(0));
+        acc *= SymbolicExprEF::from(SymbolicVarEF::permutation_local(1));
+        let mut unused = SymbolicExprEF::zero();
+        unused += SymbolicExprEF::from(SymbolicVarEF::permutation_local(2));
+        TraceContext::push(Instruction32::e_assert_zero(acc));
+        let (code, mut f_constants, mut ef_constants) = TraceContext::take().into_parts();
+
+        let (dce_code, removed) = eliminate_dead_code(code.clone());
+        assert_eq!(removed, 3);
+        assert_eq!(dce_code.len(), code.len() - 3);
+
+        let (code, _, _) = optimize(code, &mut f_constants, &mut ef_constants).unwrap();
+        let inputs = InterpreterInputs {
+            permutation_local: [2, 3, 5].map(EF::from_canonical_u32).to_vec(),
+            ..Default::default()
+        };
+        let results = interpret(&code, &f_constants, &ef_constants, &inputs);
+        assert_eq!(results, vec![ConstraintValue::EF(EF::from_canonical_u32(6))]);
+    }
+
+    #[test]
+    fn test_spills_when_registers_run_out() {
+        TraceContext::reset();
+        let values = (0..2100).map(|i| SymbolicExprF::from(SymbolicVarF::main_local(i)));
+        let sum: SymbolicExprF = values.collect::<Vec<_>>().into_iter().sum();
+        TraceContext::push(Instruction32::f_assert_zero(sum));
+        let (code, mut f_constants, mut ef_constants) = TraceContext::take().into_parts();
+
+        // Without scheduling, every load comes before the first addition.
+        let code = simplify_program(code, &mut f_constants, &mut ef_constants);
+        let (code, f_max, _) = allocate_registers(&code, RegisterBudget::default()).unwrap();
+        let code = lower_to_instruction16(&code).unwrap();
+        assert_eq!(f_max, 2047);
+        assert!(code.iter().any(|instr| instr.decode_opcode() == Opcode::FSpill));
+        let inputs = InterpreterInputs {
+            main_local: (0..2100).map(F::from_canonical_u32).collect(),
+            ..Default::default()
+        };
+        let results = interpret(&code, &f_constants, &ef_constants, &inputs);
+        assert_eq!(results, vec![ConstraintValue::F(F::from_canonical_u32(2100 * 2099 / 2))]);
+    }
+
+    #[test]
+    fn test_small_register_budget() {
+        TraceContext::reset();
+        let x = (0..6).map(|i| SymbolicVarF::main_local(i) * SymbolicVarF::main_next(i));
+        let x = x.collect::<Vec<_>>();
+        let e = (0..4).map(|i| SymbolicExprEF::from(SymbolicVarEF::permutation_local(i)));
+        let e = e.zip(&x).map(|(e, &x)| e * x).collect::<Vec<_>>();
+        let f_sum = x.iter().rev().fold(SymbolicExprF::one(), |acc, &x| acc * x + x);
+        let e_sum = e.iter().rev().fold(SymbolicExprEF::one(), |acc, &e| acc * e + f_sum);
+        TraceContext::push(Instruction32::f_assert_zero(f_sum));
+        TraceContext::push(Instruction32::e_assert_zero(e_sum));
+        let (code, mut f_constants, mut ef_constants) = TraceContext::take().into_parts();
+
+        let (expected, _, _) =
+            optimize_wide(code.clone(), &mut f_constants, &mut ef_constants).unwrap();
+        let budget = RegisterBudget { f: 4, ef: 4 };
+        let simplified = simplify_program(code.clone(), &mut f_constants, &mut ef_constants);
+        let (spilled, f_max, ef_max) = allocate_registers(&simplified, budget).unwrap();
+        assert_eq!((f_max, ef_max), (3, 3));
+        let opcodes = spilled.iter().map(|instr| instr.decode_opcode()).collect::<Vec<_>>();
+        assert!(opcodes.contains(&Opcode::FSpill) && opcodes.contains(&Opcode::FReload));
+        assert!(opcodes.contains(&Opcode::ESpill) && opcodes.contains(&Opcode::EReload));
+
+        let mut rng = StdRng::seed_from_u64(7);
+        let inputs = InterpreterInputs {
+            main_local: (0..6).map(|_| rng.gen()).collect(),
+            main_next: (0..6).map(|_| rng.gen()).collect(),
+            permutation_local: (0..4).map(|_| rng.gen()).collect(),
+            ..Default::default()
+        };
+        let run = |code: &[Instruction32]| {
+            interpret(&lower_to_instruction16(code).unwrap(), &f_constants, &ef_constants, &inputs)
+        };
+        assert_eq!(run(&spilled), run(&expected));
+
+        let too_small = RegisterBudget { f: 3, ef: 4 };
+        let err = optimize_wide_with_budget(code, &mut f_constants, &mut ef_constants, too_small)
+            .unwrap_err();
+        assert_eq!(
+            err,
+            CodegenErrorKind::RegistersExhausted { file: RegisterFile::F, capacity: 3 }
+        );
+    }
+
+    #[test]
+    fn test_lowering_rejects_wide_operands() {
+        TraceContext::reset();
+        let x = SymbolicVarF::main_local(70000) * SymbolicVarF::main_local(1);
+        TraceContext::push(Instruction32::f_assert_zero(x));
+        let (code, mut f_constants, mut ef_constants) = TraceContext::take().into_parts();
+
+        let err = optimize(code.clone(), &mut f_constants, &mut ef_constants).unwrap_err();
+        assert_eq!(
+            err,
+            CodegenErrorKind::OperandOutOfRange { index: 0, operand: 'b', value: 70000 }
+        );
+
+        let (code, _, _) = optimize_wide(code, &mut f_constants, &mut ef_constants).unwrap();
+        assert_eq!(code[0].b, 70000);
+    }
+
+    #[test]
+    fn test_schedule_evaluates_subexpressions_one_at_a_time() {
+        TraceContext::reset();
+        // Sums rather than products, which multiply-add fusion would fold into the accumulator.
+        let terms = (0..8).map(|i| SymbolicVarF::main_local(i) + SymbolicVarF::main_next(i));
+        let terms = terms.collect::<Vec<_>>();
+        let sum = terms.into_iter().fold(SymbolicExprF::zero(), |acc, x| acc + x);
+        TraceContext::push(Instruction32::f_assert_zero(sum));
+        let mut acc = SymbolicExprEF::from(SymbolicVarEF::permutation_local(0));
+        TraceContext::push(Instruction32::e_assert_zero(acc));
+        acc *= SymbolicExprEF::from(SymbolicVarEF::permutation_local(1)) + sum;
+        TraceContext::push(Instruction32::e_assert_zero(acc));
+        let (code, mut f_constants, mut ef_constants) = TraceContext::take().into_parts();
+        let code = simplify_program(code, &mut f_constants, &mut ef_constants);
+
+        let scheduled = schedule(code.clone());
+        assert_eq!(register_pressure(&code), (9, 3));
+        assert_eq!(register_pressure(&scheduled), (3, 3));
+        let (_, f_max, ef_max) = allocate_registers(&scheduled, RegisterBudget::default()).unwrap();
+        assert_eq!((f_max, ef_max), register_pressure(&scheduled));
+
+        let mut rng = StdRng::seed_from_u64(11);
+        let inputs = InterpreterInputs {
+            main_local: (0..8).map(|_| rng.gen()).collect(),
+            main_next: (0..8).map(|_| rng.gen()).collect(),
+            permutation_local: (0..2).map(|_| rng.gen()).collect(),
+            ..Default::default()
+        };
+        let run = |code: &[Instruction32]| {
+            let (code, _, _) = allocate_registers(code, RegisterBudget::default()).unwrap();
+            interpret(&lower_to_instruction16(&code).unwrap(), &f_constants, &ef_constants, &inputs)
+        };
+        assert_eq!(run(&scheduled), run(&code));
+    }
+
+    #[test]
+    fn test_fuse_multiply_add() {
+        TraceContext::reset();
+        let w = SymbolicVarF::main_local(2) + F::one();
+        let y = SymbolicVarF::main_local(0) * SymbolicVarF::main_local(1) + w;
+        let u = SymbolicVarF::main_next(1) + F::two();
+        let z = y * SymbolicVarF::main_next(0) - u;
+        TraceContext::push(Instruction32::f_assert_zero(z));
+        // Read twice, so it stays a separate multiplication.
+        let v = SymbolicVarF::main_next(2) * SymbolicVarF::main_next(3);
+        TraceContext::push(Instruction32::f_assert_zero(v + v));
+        let mut acc = SymbolicExprEF::from(SymbolicVarEF::permutation_local(0));
+        acc += SymbolicExprEF::from(SymbolicVarEF::permutation_local(1)) * acc;
+        acc += SymbolicExprEF::from(SymbolicVarEF::permutation_local(1))
+            * SymbolicVarEF::permutation_challenge(0);
+        TraceContext::push(Instruction32::e_assert_zero(acc));
+        let (code, f_constants, ef_constants) = TraceContext::take().into_parts();
+
+        let (fused, removed) = fuse_multiply_add(code.clone());
+        assert_eq!(removed, 3);
+        let opcodes = fused.iter().map(|instr| instr.decode_opcode()).collect::<Vec<_>>();
+        assert_eq!(
+            opcodes.iter().filter(|opcode| mnemonic(**opcode).starts_with("mul")).count(),
+            5
+        );
+        for opcode in [Opcode::FMulAddVVE, Opcode::FMulSubEVE, Opcode::EMulAddEVE] {
+            assert!(opcodes.contains(&opcode), "{:?}", opcode);
+        }
+        // `acc` is written in place, so the multiplication reading it is not moved.
+        assert!(opcodes.contains(&Opcode::EMulEE) && opcodes.contains(&Opcode::FMulVV));
+
+        let mut rng = StdRng::seed_from_u64(13);
+        let inputs = InterpreterInputs {
+            main_local: (0..3).map(|_| rng.gen()).collect(),
+            main_next: (0..4).map(|_| rng.gen()).collect(),
+            permutation_local: (0..2).map(|_| rng.gen()).collect(),
+            permutation_challenges: vec![rng.gen()],
+            ..Default::default()
+        };
+        let run = |code: &[Instruction32]| {
+            let (code, _, _) = allocate_registers(code, RegisterBudget::default()).unwrap();
+            interpret(&lower_to_instruction16(&code).unwrap(), &f_constants, &ef_constants, &inputs)
+        };
+        assert_eq!(run(&fused), run(&code));
+    }
 }
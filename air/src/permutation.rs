@@ -0,0 +1,115 @@
+//! LogUp permutation constraints built from the interactions a chip made while being traced.
+//!
+//! sp1-stark's `Chip::eval` builds these from the `sends` and `receives` recorded when the chip
+//! was created. Building them from the traced interactions instead keeps every permutation
+//! constraint tied to the value and multiplicity expressions the traced code computes.
+
+use p3_air::{AirBuilder, ExtensionBuilder};
+use p3_field::{AbstractExtensionField, AbstractField};
+use p3_matrix::Matrix;
+use sp1_stark::{air::InteractionScope, local_permutation_trace_width};
+
+use crate::{
+    error::CodegenErrorKind, symbolic_expr_ef::SymbolicExprEF, symbolic_var_ef::SymbolicVarEF,
+    symbolic_var_f::SymbolicVarF, trace::TraceContext, InteractionDirection, SymbolicInteraction,
+    SymbolicProverFolder, F,
+};
+
+/// Asserts the permutation constraints of `interactions` on `builder`, in the same order as
+/// sp1-stark's `eval_permutation_constraints`.
+///
+/// Only local interactions are batched into the permutation trace, sends before receives, with
+/// `batch_size` interactions per column. A chip committed in the global scope also asserts that
+/// its last 14 main columns hold the global cumulative sum on the last row. Records an error if
+/// the permutation trace of `builder` does not have one column per batch plus the running sum.
+pub fn eval_permutation_constraints(
+    interactions: &[SymbolicInteraction],
+    batch_size: usize,
+    commit_scope: InteractionScope,
+    builder: &mut SymbolicProverFolder,
+) {
+    let local = |direction| {
+        interactions.iter().filter(move |interaction| {
+            interaction.direction == direction && interaction.scope == InteractionScope::Local
+        })
+    };
+    let local_interactions = local(InteractionDirection::Send)
+        .chain(local(InteractionDirection::Receive))
+        .collect::<Vec<_>>();
+
+    let width = local_permutation_trace_width(local_interactions.len(), batch_size);
+    if builder.perm.width() != width {
+        TraceContext::fail(CodegenErrorKind::PermutationWidthMismatch {
+            expected: width,
+            actual: builder.perm.width(),
+        });
+        return;
+    }
+    let perm_local: Vec<SymbolicVarEF> = builder.perm.row_slice(0).to_vec();
+    let perm_next: Vec<SymbolicVarEF> = builder.perm.row_slice(1).to_vec();
+    let main_local: Vec<SymbolicVarF> = builder.main.row_slice(0).to_vec();
+
+    let alpha = SymbolicExprEF::from(builder.perm_challenges[0]);
+    let beta = SymbolicExprEF::from(builder.perm_challenges[1]);
+    if !local_interactions.is_empty() {
+        // Each column but the last holds the sum of m_i / rlc_i over its batch, asserted as
+        // entry * prod_i rlc_i = sum_i m_i * prod_{j != i} rlc_j.
+        for (entry, chunk) in
+            perm_local[..width - 1].iter().zip(local_interactions.chunks(batch_size))
+        {
+            let mut rlcs = Vec::with_capacity(batch_size);
+            let mut multiplicities = Vec::with_capacity(batch_size);
+            for interaction in chunk {
+                let mut betas = beta.powers();
+                let mut rlc = alpha
+                    + betas.next().unwrap()
+                        * SymbolicExprEF::from_canonical_usize(interaction.kind as usize);
+                for (value, beta) in interaction.values.iter().zip(betas) {
+                    rlc = rlc + beta * *value;
+                }
+                rlcs.push(rlc);
+
+                let sign = match interaction.direction {
+                    InteractionDirection::Send => F::one(),
+                    InteractionDirection::Receive => -F::one(),
+                };
+                multiplicities.push(interaction.multiplicity * sign);
+            }
+
+            let mut product = SymbolicExprEF::one();
+            let mut numerator = SymbolicExprEF::zero();
+            for (i, (m, rlc)) in multiplicities.into_iter().zip(rlcs.iter()).enumerate() {
+                product = product * *rlc;
+                let mut all_but_current = SymbolicExprEF::one();
+                for (_, other) in rlcs.iter().enumerate().filter(|(j, _)| i != *j) {
+                    all_but_current = all_but_current * *other;
+                }
+                numerator = numerator + SymbolicExprEF::from_base(m) * all_but_current;
+            }
+            builder.assert_eq_ext(product * SymbolicExprEF::from(*entry), numerator);
+        }
+
+        // The last column accumulates the batch sums over the rows.
+        let sum_local = perm_local[..width - 1].iter().map(|&x| x.into()).sum::<SymbolicExprEF>();
+        let sum_next = perm_next[..width - 1].iter().map(|&x| x.into()).sum::<SymbolicExprEF>();
+        let phi_local = SymbolicExprEF::from(perm_local[width - 1]);
+        let phi_next = SymbolicExprEF::from(perm_next[width - 1]);
+        builder.when_first_row().assert_eq_ext(phi_local, sum_local);
+        builder.when_transition().assert_eq_ext(phi_next - phi_local, sum_next);
+        let local_cumulative_sum = *builder.local_cumulative_sum;
+        builder.when_last_row().assert_eq_ext(perm_local[width - 1], local_cumulative_sum);
+    }
+
+    if commit_scope == InteractionScope::Global {
+        let global_cumulative_sum = builder.global_cumulative_sum;
+        let len = main_local.len();
+        for i in 0..7 {
+            builder
+                .when_last_row()
+                .assert_eq(main_local[len - 14 + i], global_cumulative_sum.0.x.0[i]);
+            builder
+                .when_last_row()
+                .assert_eq(main_local[len - 7 + i], global_cumulative_sum.0.y.0[i]);
+        }
+    }
+}
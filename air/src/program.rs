@@ -0,0 +1,465 @@
+use std::fmt;
+
+use p3_air::BaseAir;
+use sp1_stark::{air::MachineAir, Chip, PROOF_MAX_NUM_PVS};
+
+use crate::{
+    asm,
+    error::{CodegenErrorKind, ProgramError},
+    instruction::{Instruction16, Instruction32, Opcode, Operand},
+    optimizer,
+    symbolic_var_ef::SymbolicVarEF,
+    symbolic_var_f::SymbolicVarF,
+    EF, F,
+};
+
+/// The number of F elements in a chip's global cumulative sum digest.
+const GLOBAL_CUMULATIVE_SUM_LEN: usize = 14;
+
+/// The number of permutation challenges sp1-stark samples for a proof.
+pub(crate) const NUM_PERMUTATION_CHALLENGES: usize = 2;
+
+/// The column widths of the chip a program was compiled from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct ColumnWidths {
+    pub preprocessed: usize,
+    pub main: usize,
+    pub permutation: usize,
+}
+
+impl ColumnWidths {
+    /// The current column widths of `chip`.
+    pub fn of<A: MachineAir<F>>(chip: &Chip<F, A>) -> Self {
+        Self {
+            preprocessed: chip.preprocessed_width(),
+            main: chip.width(),
+            permutation: chip.permutation_width(),
+        }
+    }
+}
+
+/// A compiled constraint program together with everything needed to run it.
+///
+/// `I` is the instruction encoding: [`Instruction16`] for the device, or [`Instruction32`] for
+/// chips whose indices do not fit in 16 bits.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConstraintProgram<I = Instruction16> {
+    code: Vec<I>,
+    num_f_registers: usize,
+    num_ef_registers: usize,
+    f_constants: Vec<F>,
+    ef_constants: Vec<EF>,
+    num_constraints: usize,
+    widths: ColumnWidths,
+    num_public_values: usize,
+    num_challenges: usize,
+    num_f_spill_slots: usize,
+    num_ef_spill_slots: usize,
+}
+
+impl<I: Copy + Into<Instruction32>> ConstraintProgram<I> {
+    /// Wraps register-allocated code. The number of constraints, public values, challenges and
+    /// spill slots are read off the code; instructions with an invalid opcode are left for
+    /// [`Self::validate`] to report.
+    pub fn new(
+        code: Vec<I>,
+        num_f_registers: usize,
+        num_ef_registers: usize,
+        f_constants: Vec<F>,
+        ef_constants: Vec<EF>,
+        widths: ColumnWidths,
+    ) -> Self {
+        let mut num_constraints = 0;
+        let mut num_public_values = 0;
+        let mut num_challenges = 0;
+        let mut num_f_spill_slots = 0;
+        let mut num_ef_spill_slots = 0;
+        for &instr in code.iter() {
+            let instr: Instruction32 = instr.into();
+            let Ok(opcode) = Opcode::try_from(instr.opcode) else {
+                continue;
+            };
+            if opcode.is_assert() {
+                num_constraints += 1;
+            }
+            let (a, b, c) = opcode.operands();
+            for (operand, variant, value) in
+                [(a, 0, instr.a), (b, instr.b_variant, instr.b), (c, instr.c_variant, instr.c)]
+            {
+                if operand == Operand::FVariable
+                    && SymbolicVarF::decode(variant, value)
+                        == Some(SymbolicVarF::PublicValue(value))
+                {
+                    num_public_values = num_public_values.max(value as usize + 1);
+                }
+                if operand == Operand::EFVariable
+                    && SymbolicVarEF::decode(variant, value)
+                        == Some(SymbolicVarEF::PermutationChallenge(value))
+                {
+                    num_challenges = num_challenges.max(value as usize + 1);
+                }
+                if operand == Operand::FSpillSlot {
+                    num_f_spill_slots = num_f_spill_slots.max(value as usize + 1);
+                }
+                if operand == Operand::EFSpillSlot {
+                    num_ef_spill_slots = num_ef_spill_slots.max(value as usize + 1);
+                }
+            }
+        }
+
+        Self {
+            code,
+            num_f_registers,
+            num_ef_registers,
+            f_constants,
+            ef_constants,
+            num_constraints,
+            widths,
+            num_public_values,
+            num_challenges,
+            num_f_spill_slots,
+            num_ef_spill_slots,
+        }
+    }
+
+    pub fn code(&self) -> &[I] {
+        &self.code
+    }
+
+    pub fn num_f_registers(&self) -> usize {
+        self.num_f_registers
+    }
+
+    pub fn num_ef_registers(&self) -> usize {
+        self.num_ef_registers
+    }
+
+    pub fn f_constants(&self) -> &[F] {
+        &self.f_constants
+    }
+
+    pub fn ef_constants(&self) -> &[EF] {
+        &self.ef_constants
+    }
+
+    /// The number of `FAssertZero` and `EAssertZero` instructions.
+    pub fn num_constraints(&self) -> usize {
+        self.num_constraints
+    }
+
+    pub fn widths(&self) -> ColumnWidths {
+        self.widths
+    }
+
+    pub fn preprocessed_width(&self) -> usize {
+        self.widths.preprocessed
+    }
+
+    pub fn main_width(&self) -> usize {
+        self.widths.main
+    }
+
+    pub fn permutation_width(&self) -> usize {
+        self.widths.permutation
+    }
+
+    /// One more than the highest public value the program reads.
+    pub fn num_public_values(&self) -> usize {
+        self.num_public_values
+    }
+
+    /// One more than the highest permutation challenge the program reads.
+    pub fn num_challenges(&self) -> usize {
+        self.num_challenges
+    }
+
+    /// The size of the F spill scratch array the program needs.
+    pub fn num_f_spill_slots(&self) -> usize {
+        self.num_f_spill_slots
+    }
+
+    /// The size of the EF spill scratch array the program needs.
+    pub fn num_ef_spill_slots(&self) -> usize {
+        self.num_ef_spill_slots
+    }
+
+    /// Checks that every opcode is valid and supported, that every variable operand has a valid
+    /// `SymbolicVarF`/`SymbolicVarEF` tag, that every register, constant and variable the code
+    /// refers to exists, and that every spill slot is spilled before it is reloaded. Public values
+    /// and permutation challenges are bounded by what a proof provides.
+    ///
+    /// Programs that did not come straight from the tracer must pass this before they are run.
+    pub fn validate(&self) -> Result<(), ProgramError> {
+        let mut f_spilled = vec![false; self.num_f_spill_slots];
+        let mut ef_spilled = vec![false; self.num_ef_spill_slots];
+        for (index, &instr) in self.code.iter().enumerate() {
+            let instr: Instruction32 = instr.into();
+            let opcode = Opcode::try_from(instr.opcode)
+                .map_err(|_| ProgramError::InvalidOpcode { index, opcode: instr.opcode })?;
+            // The tracer never emits `EFAsBaseSlice`, and no backend implements it.
+            if opcode == Opcode::EFAsBaseSlice {
+                return Err(ProgramError::UnsupportedOpcode { index, opcode });
+            }
+            let (a, b, c) = opcode.operands();
+            self.validate_operand(index, a, 0, instr.a)?;
+            self.validate_operand(index, b, instr.b_variant, instr.b)?;
+            self.validate_operand(index, c, instr.c_variant, instr.c)?;
+
+            // Spill slots are sized from the code, so they are always in range. Reloads read `b`
+            // and spills write `a`.
+            let spilled = match b {
+                Operand::FSpillSlot => Some(&f_spilled),
+                Operand::EFSpillSlot => Some(&ef_spilled),
+                _ => None,
+            };
+            if spilled.is_some_and(|spilled| !spilled[instr.b as usize]) {
+                return Err(ProgramError::ReloadBeforeSpill { index, operand: b, slot: instr.b });
+            }
+            match a {
+                Operand::FSpillSlot => f_spilled[instr.a as usize] = true,
+                Operand::EFSpillSlot => ef_spilled[instr.a as usize] = true,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_operand(
+        &self,
+        index: usize,
+        operand: Operand,
+        variant: u8,
+        value: u32,
+    ) -> Result<(), ProgramError> {
+        let invalid_variant = ProgramError::InvalidVariant { index, operand, variant };
+        let is_variable = matches!(operand, Operand::FVariable | Operand::EFVariable);
+        if !is_variable && variant != 0 {
+            return Err(invalid_variant);
+        }
+        let len = match operand {
+            Operand::None => return Ok(()),
+            Operand::FRegister => self.num_f_registers,
+            Operand::EFRegister => self.num_ef_registers,
+            Operand::FConstant => self.f_constants.len(),
+            Operand::EFConstant => self.ef_constants.len(),
+            Operand::FVariable => self.f_variable_len(variant).ok_or(invalid_variant)?,
+            Operand::EFVariable => self.ef_variable_len(variant).ok_or(invalid_variant)?,
+            Operand::FSpillSlot => self.num_f_spill_slots,
+            Operand::EFSpillSlot => self.num_ef_spill_slots,
+        };
+        if value as usize >= len {
+            return Err(ProgramError::OperandOutOfRange { index, operand, value, len });
+        }
+        Ok(())
+    }
+
+    /// The number of values a `SymbolicVarF` with `variant` can index.
+    fn f_variable_len(&self, variant: u8) -> Option<usize> {
+        let len = match SymbolicVarF::decode(variant, 0)? {
+            SymbolicVarF::Empty => return None,
+            SymbolicVarF::Constant(_) => self.f_constants.len(),
+            SymbolicVarF::PreprocessedLocal(_) | SymbolicVarF::PreprocessedNext(_) => {
+                self.widths.preprocessed
+            }
+            SymbolicVarF::MainLocal(_) | SymbolicVarF::MainNext(_) => self.widths.main,
+            SymbolicVarF::IsFirstRow
+            | SymbolicVarF::IsLastRow
+            | SymbolicVarF::IsTransition
+            | SymbolicVarF::InvZeroifier => 1,
+            SymbolicVarF::PublicValue(_) => PROOF_MAX_NUM_PVS,
+            SymbolicVarF::GlobalCumulativeSum(_) => GLOBAL_CUMULATIVE_SUM_LEN,
+        };
+        Some(len)
+    }
+
+    /// The number of values a `SymbolicVarEF` with `variant` can index.
+    fn ef_variable_len(&self, variant: u8) -> Option<usize> {
+        let len = match SymbolicVarEF::decode(variant, 0)? {
+            SymbolicVarEF::Empty => return None,
+            SymbolicVarEF::PermutationLocal(_) | SymbolicVarEF::PermutationNext(_) => {
+                self.widths.permutation
+            }
+            SymbolicVarEF::PermutationChallenge(_) => NUM_PERMUTATION_CHALLENGES,
+            SymbolicVarEF::CumulativeSum(_) | SymbolicVarEF::Alpha => 1,
+        };
+        Some(len)
+    }
+}
+
+impl<I: Copy + Into<Instruction32>> fmt::Display for ConstraintProgram<I> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", asm::disassemble(&self.code, &self.f_constants, &self.ef_constants))
+    }
+}
+
+impl ConstraintProgram<Instruction32> {
+    /// Lowers the program to [`Instruction16`], failing if any operand does not fit in 16 bits.
+    pub fn lower(self) -> Result<ConstraintProgram<Instruction16>, CodegenErrorKind> {
+        let code = optimizer::lower_to_instruction16(&self.code)?;
+        Ok(ConstraintProgram {
+            code,
+            num_f_registers: self.num_f_registers,
+            num_ef_registers: self.num_ef_registers,
+            f_constants: self.f_constants,
+            ef_constants: self.ef_constants,
+            num_constraints: self.num_constraints,
+            widths: self.widths,
+            num_public_values: self.num_public_values,
+            num_challenges: self.num_challenges,
+            num_f_spill_slots: self.num_f_spill_slots,
+            num_ef_spill_slots: self.num_ef_spill_slots,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use p3_field::AbstractField;
+    use sp1_stark::PROOF_MAX_NUM_PVS;
+
+    use crate::{
+        error::ProgramError,
+        instruction::{Instruction32, Opcode, Operand},
+        symbolic_expr_ef::SymbolicExprEF,
+        symbolic_var_ef::SymbolicVarEF,
+        symbolic_var_f::SymbolicVarF,
+        trace::TraceContext,
+        F,
+    };
+
+    use super::{ColumnWidths, ConstraintProgram};
+
+    #[test]
+    fn test_validate() {
+        TraceContext::reset();
+        let x = SymbolicVarF::main_local(3) * SymbolicVarF::public_value(5) + F::two();
+        TraceContext::push(Instruction32::f_assert_zero(x));
+        let y = SymbolicExprEF::from(SymbolicVarEF::permutation_challenge(1)) * x;
+        TraceContext::push(Instruction32::e_assert_zero(y));
+        let trace = TraceContext::take();
+        let (expr_f_ctr, expr_ef_ctr) = (trace.expr_f_ctr, trace.expr_ef_ctr);
+        let (code, f_constants, ef_constants) = trace.into_parts();
+
+        let widths = ColumnWidths { preprocessed: 0, main: 4, permutation: 0 };
+        let program = ConstraintProgram::new(
+            code.clone(),
+            expr_f_ctr as usize,
+            expr_ef_ctr as usize,
+            f_constants.clone(),
+            ef_constants.clone(),
+            widths,
+        );
+        assert_eq!(program.num_constraints(), 2);
+        assert_eq!(program.num_public_values(), 6);
+        assert_eq!(program.num_challenges(), 2);
+        assert_eq!(program.validate(), Ok(()));
+
+        let narrow = ColumnWidths { main: 3, ..widths };
+        let program = ConstraintProgram::new(
+            code,
+            expr_f_ctr as usize,
+            expr_ef_ctr as usize,
+            f_constants,
+            ef_constants,
+            narrow,
+        );
+        assert_eq!(
+            program.validate(),
+            Err(ProgramError::OperandOutOfRange {
+                index: 0,
+                operand: Operand::FVariable,
+                value: 3,
+                len: 3
+            })
+        );
+    }
+
+    #[test]
+    fn test_validate_rejects_bad_encodings() {
+        TraceContext::reset();
+        let x = (SymbolicVarF::main_local(0) + SymbolicVarF::main_local(1))
+            * SymbolicVarF::main_local(0);
+        TraceContext::push(Instruction32::f_assert_zero(x));
+        let trace = TraceContext::take();
+        let (expr_f_ctr, expr_ef_ctr) = (trace.expr_f_ctr, trace.expr_ef_ctr);
+        let (code, f_constants, ef_constants) = trace.into_parts();
+        let widths = ColumnWidths { preprocessed: 0, main: 2, permutation: 0 };
+        let build = |code: Vec<Instruction32>| {
+            ConstraintProgram::new(
+                code,
+                expr_f_ctr as usize,
+                expr_ef_ctr as usize,
+                f_constants.clone(),
+                ef_constants.clone(),
+                widths,
+            )
+        };
+        assert_eq!(build(code.clone()).validate(), Ok(()));
+
+        let mut bad_opcode = code.clone();
+        bad_opcode[1].opcode = 200;
+        assert_eq!(
+            build(bad_opcode).validate(),
+            Err(ProgramError::InvalidOpcode { index: 1, opcode: 200 })
+        );
+
+        let mut unsupported = code.clone();
+        unsupported[1].opcode = Opcode::EFAsBaseSlice as u8;
+        assert_eq!(
+            build(unsupported).validate(),
+            Err(ProgramError::UnsupportedOpcode { index: 1, opcode: Opcode::EFAsBaseSlice })
+        );
+
+        let mut bad_variable = code.clone();
+        bad_variable[0].b_variant = 0x7f;
+        assert_eq!(
+            build(bad_variable).validate(),
+            Err(ProgramError::InvalidVariant {
+                index: 0,
+                operand: Operand::FVariable,
+                variant: 0x7f
+            })
+        );
+
+        let mut public_value = code.clone();
+        let last = SymbolicVarF::public_value(PROOF_MAX_NUM_PVS as u32 - 1);
+        public_value[0].b_variant = last.variant();
+        public_value[0].b = last.data();
+        assert_eq!(build(public_value.clone()).validate(), Ok(()));
+        public_value[0].b += 1;
+        assert_eq!(
+            build(public_value).validate(),
+            Err(ProgramError::OperandOutOfRange {
+                index: 0,
+                operand: Operand::FVariable,
+                value: PROOF_MAX_NUM_PVS as u32,
+                len: PROOF_MAX_NUM_PVS
+            })
+        );
+
+        let mut reload = code.clone();
+        reload.insert(0, Instruction32::f_reload(1, 0));
+        assert_eq!(
+            build(reload.clone()).validate(),
+            Err(ProgramError::ReloadBeforeSpill {
+                index: 0,
+                operand: Operand::FSpillSlot,
+                slot: 0
+            })
+        );
+        reload.insert(0, Instruction32::f_spill(0, 1));
+        assert_eq!(build(reload).validate(), Ok(()));
+
+        // Register operands carry no variant.
+        let mut bad_register = code;
+        bad_register[1].b_variant = 0x04;
+        assert_eq!(
+            build(bad_register).validate(),
+            Err(ProgramError::InvalidVariant {
+                index: 1,
+                operand: Operand::FRegister,
+                variant: 0x04
+            })
+        );
+    }
+}
@@ -6,8 +6,8 @@ use std::{
 use p3_field::{AbstractExtensionField, AbstractField};
 
 use crate::{
-    instruction::Instruction32, symbolic_expr_f::SymbolicExprF, symbolic_var_ef::SymbolicVarEF,
-    CUDA_P3_EVAL_CODE, CUDA_P3_EVAL_EXPR_EF_CTR, EF,
+    error::CodegenErrorKind, instruction::Instruction32, symbolic_expr_f::SymbolicExprF,
+    symbolic_var_ef::SymbolicVarEF, trace::TraceContext, EF,
 };
 
 #[derive(Debug, Copy, PartialEq, Eq, Hash)]
@@ -22,11 +22,7 @@ impl SymbolicExprEF {
 
     // #[instrument(skip_all, level = "trace", name = "Alloc for SymbolicExprEF")]
     pub fn alloc() -> Self {
-        let mut tmp = CUDA_P3_EVAL_EXPR_EF_CTR.lock().unwrap();
-        let id = *tmp;
-        *tmp += 1;
-        drop(tmp);
-        Self(id)
+        Self(TraceContext::with(TraceContext::alloc_ef))
     }
 
     pub fn variant(&self) -> u8 {
@@ -42,9 +38,7 @@ impl Default for SymbolicExprEF {
     // #[instrument(skip_all, level = "trace", name = "Default for SymbolicExprEF")]
     fn default() -> Self {
         let output = SymbolicExprEF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::e_assign_c(output, EF::zero()));
-        drop(code);
+        TraceContext::push(Instruction32::e_assign_c(output, EF::zero()));
         output
     }
 }
@@ -53,9 +47,7 @@ impl From<EF> for SymbolicExprEF {
     // #[instrument(skip_all, level = "trace", name = "From<EF> for SymbolicExprEF")]
     fn from(f: EF) -> Self {
         let output = SymbolicExprEF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::e_assign_c(output, f));
-        drop(code);
+        TraceContext::push(Instruction32::e_assign_c(output, f));
         output
     }
 }
@@ -66,9 +58,7 @@ impl Add<EF> for SymbolicExprEF {
     // #[instrument(skip_all, level = "trace", name = "Add<EF> for SymbolicExprEF")]
     fn add(self, rhs: EF) -> Self::Output {
         let output = SymbolicExprEF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::e_add_ec(output, self, rhs));
-        drop(code);
+        TraceContext::push(Instruction32::e_add_ec(output, self, rhs));
         output
     }
 }
@@ -79,9 +69,7 @@ impl Add<SymbolicVarEF> for SymbolicExprEF {
     // #[instrument(skip_all, level = "trace", name = "Add<SymbolicVarEF> for SymbolicExprEF")]
     fn add(self, rhs: SymbolicVarEF) -> Self::Output {
         let output = SymbolicExprEF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::e_add_ev(output, self, rhs));
-        drop(code);
+        TraceContext::push(Instruction32::e_add_ev(output, self, rhs));
         output
     }
 }
@@ -92,9 +80,7 @@ impl Add<SymbolicExprEF> for SymbolicExprEF {
     // #[instrument(skip_all, level = "trace", name = "Add<SymbolicExprEF> for SymbolicExprEF")]
     fn add(self, rhs: SymbolicExprEF) -> Self::Output {
         let output = SymbolicExprEF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::e_add_ee(output, self, rhs));
-        drop(code);
+        TraceContext::push(Instruction32::e_add_ee(output, self, rhs));
         output
     }
 }
@@ -102,9 +88,7 @@ impl Add<SymbolicExprEF> for SymbolicExprEF {
 impl AddAssign for SymbolicExprEF {
     // #[instrument(skip_all, level = "trace", name = "AddAssign for SymbolicExprEF")]
     fn add_assign(&mut self, rhs: Self) {
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::e_add_assign_e(*self, rhs));
-        drop(code);
+        TraceContext::push(Instruction32::e_add_assign_e(*self, rhs));
     }
 }
 
@@ -114,9 +98,7 @@ impl Sub<EF> for SymbolicExprEF {
     // #[instrument(skip_all, level = "trace", name = "Sub<EF> for SymbolicExprEF")]
     fn sub(self, rhs: EF) -> Self::Output {
         let output = SymbolicExprEF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::e_sub_ec(output, self, rhs));
-        drop(code);
+        TraceContext::push(Instruction32::e_sub_ec(output, self, rhs));
         output
     }
 }
@@ -127,9 +109,7 @@ impl Sub<SymbolicVarEF> for SymbolicExprEF {
     // #[instrument(skip_all, level = "trace", name = "Sub<SymbolicVarEF> for SymbolicExprEF")]
     fn sub(self, rhs: SymbolicVarEF) -> Self::Output {
         let output = SymbolicExprEF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::e_sub_ev(output, self, rhs));
-        drop(code);
+        TraceContext::push(Instruction32::e_sub_ev(output, self, rhs));
         output
     }
 }
@@ -140,9 +120,7 @@ impl Sub<SymbolicExprEF> for SymbolicExprEF {
     // #[instrument(skip_all, level = "trace", name = "Sub<SymbolicExprEF> for SymbolicExprEF")]
     fn sub(self, rhs: SymbolicExprEF) -> Self::Output {
         let output = SymbolicExprEF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::e_sub_ee(output, self, rhs));
-        drop(code);
+        TraceContext::push(Instruction32::e_sub_ee(output, self, rhs));
         output
     }
 }
@@ -150,9 +128,7 @@ impl Sub<SymbolicExprEF> for SymbolicExprEF {
 impl SubAssign for SymbolicExprEF {
     // #[instrument(skip_all, level = "trace", name = "SubAssign for SymbolicExprEF")]
     fn sub_assign(&mut self, rhs: Self) {
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::e_sub_assign_e(*self, rhs));
-        drop(code);
+        TraceContext::push(Instruction32::e_sub_assign_e(*self, rhs));
     }
 }
 
@@ -162,9 +138,7 @@ impl Mul<EF> for SymbolicExprEF {
     // #[instrument(skip_all, level = "trace", name = "Mul<EF> for SymbolicExprEF")]
     fn mul(self, rhs: EF) -> Self::Output {
         let output = SymbolicExprEF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::e_mul_ec(output, self, rhs));
-        drop(code);
+        TraceContext::push(Instruction32::e_mul_ec(output, self, rhs));
         output
     }
 }
@@ -175,9 +149,7 @@ impl Mul<SymbolicVarEF> for SymbolicExprEF {
     // #[instrument(skip_all, level = "trace", name = "Mul<SymbolicVarEF> for SymbolicExprEF")]
     fn mul(self, rhs: SymbolicVarEF) -> Self::Output {
         let output = SymbolicExprEF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::e_mul_ev(output, self, rhs));
-        drop(code);
+        TraceContext::push(Instruction32::e_mul_ev(output, self, rhs));
         output
     }
 }
@@ -188,9 +160,7 @@ impl Mul<SymbolicExprEF> for SymbolicExprEF {
     // #[instrument(skip_all, level = "trace", name = "Mul<SymbolicExprEF> for SymbolicExprEF")]
     fn mul(self, rhs: SymbolicExprEF) -> Self::Output {
         let output = SymbolicExprEF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::e_mul_ee(output, self, rhs));
-        drop(code);
+        TraceContext::push(Instruction32::e_mul_ee(output, self, rhs));
         output
     }
 }
@@ -198,9 +168,7 @@ impl Mul<SymbolicExprEF> for SymbolicExprEF {
 impl MulAssign for SymbolicExprEF {
     // #[instrument(skip_all, level = "trace", name = "MulAssign for SymbolicExprEF")]
     fn mul_assign(&mut self, rhs: Self) {
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::e_mul_assign_e(*self, rhs));
-        drop(code);
+        TraceContext::push(Instruction32::e_mul_assign_e(*self, rhs));
     }
 }
 
@@ -210,9 +178,7 @@ impl Neg for SymbolicExprEF {
     // #[instrument(skip_all, level = "trace", name = "Neg for SymbolicExprEF")]
     fn neg(self) -> Self::Output {
         let output = SymbolicExprEF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::e_neg_e(output, self));
-        drop(code);
+        TraceContext::push(Instruction32::e_neg_e(output, self));
         output
     }
 }
@@ -243,10 +209,8 @@ impl Clone for SymbolicExprEF {
     #[allow(clippy::non_canonical_clone_impl)]
     // #[instrument(skip_all, level = "trace", name = "Clone for SymbolicExprEF")]
     fn clone(&self) -> Self {
-        // let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
         // let output = SymbolicExprEF::alloc();
-        // code.push(Instruction32::e_assign_e(output, *self));
-        // drop(code);
+        // TraceContext::push(Instruction32::e_assign_e(output, *self));
         // output
         *self
     }
@@ -258,126 +222,98 @@ impl AbstractField for SymbolicExprEF {
     // #[instrument(skip_all, level = "trace", name = "Zero for SymbolicExprEF")]
     fn zero() -> Self {
         let output = SymbolicExprEF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::e_assign_c(output, EF::zero()));
-        drop(code);
+        TraceContext::push(Instruction32::e_assign_c(output, EF::zero()));
         output
     }
 
     // #[instrument(skip_all, level = "trace", name = "One for SymbolicExprEF")]
     fn one() -> Self {
         let output = SymbolicExprEF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::e_assign_c(output, EF::one()));
-        drop(code);
+        TraceContext::push(Instruction32::e_assign_c(output, EF::one()));
         output
     }
 
     // #[instrument(skip_all, level = "trace", name = "Two for SymbolicExprEF")]
     fn two() -> Self {
         let output = SymbolicExprEF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::e_assign_c(output, EF::two()));
-        drop(code);
+        TraceContext::push(Instruction32::e_assign_c(output, EF::two()));
         output
     }
 
     // #[instrument(skip_all, level = "trace", name = "NegOne for SymbolicExprEF")]
     fn neg_one() -> Self {
         let output = SymbolicExprEF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::e_assign_c(output, EF::neg_one()));
-        drop(code);
+        TraceContext::push(Instruction32::e_assign_c(output, EF::neg_one()));
         output
     }
 
     // #[instrument(skip_all, level = "trace", name = "From<EF> for SymbolicExprEF")]
     fn from_f(f: Self::F) -> Self {
         let output = SymbolicExprEF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::e_assign_c(output, f));
-        drop(code);
+        TraceContext::push(Instruction32::e_assign_c(output, f));
         output
     }
 
     // #[instrument(skip_all, level = "trace", name = "From<bool> for SymbolicExprEF")]
     fn from_bool(b: bool) -> Self {
         let output = SymbolicExprEF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::e_assign_c(output, EF::from_bool(b)));
-        drop(code);
+        TraceContext::push(Instruction32::e_assign_c(output, EF::from_bool(b)));
         output
     }
 
     // #[instrument(skip_all, level = "trace", name = "From<u8> for SymbolicExprEF")]
     fn from_canonical_u8(n: u8) -> Self {
         let output = SymbolicExprEF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::e_assign_c(output, EF::from_canonical_u8(n)));
-        drop(code);
+        TraceContext::push(Instruction32::e_assign_c(output, EF::from_canonical_u8(n)));
         output
     }
 
     // #[instrument(skip_all, level = "trace", name = "From<u16> for SymbolicExprEF")]
     fn from_canonical_u16(n: u16) -> Self {
         let output = SymbolicExprEF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::e_assign_c(output, EF::from_canonical_u16(n)));
-        drop(code);
+        TraceContext::push(Instruction32::e_assign_c(output, EF::from_canonical_u16(n)));
         output
     }
 
     // #[instrument(skip_all, level = "trace", name = "From<u32> for SymbolicExprEF")]
     fn from_canonical_u32(n: u32) -> Self {
         let output = SymbolicExprEF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::e_assign_c(output, EF::from_canonical_u32(n)));
-        drop(code);
+        TraceContext::push(Instruction32::e_assign_c(output, EF::from_canonical_u32(n)));
         output
     }
 
     // #[instrument(skip_all, level = "trace", name = "From<u64> for SymbolicExprEF")]
     fn from_canonical_u64(n: u64) -> Self {
         let output = SymbolicExprEF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::e_assign_c(output, EF::from_canonical_u64(n)));
-        drop(code);
+        TraceContext::push(Instruction32::e_assign_c(output, EF::from_canonical_u64(n)));
         output
     }
 
     // #[instrument(skip_all, level = "trace", name = "From<usize> for SymbolicExprEF")]
     fn from_canonical_usize(n: usize) -> Self {
         let output = SymbolicExprEF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::e_assign_c(output, EF::from_canonical_usize(n)));
-        drop(code);
+        TraceContext::push(Instruction32::e_assign_c(output, EF::from_canonical_usize(n)));
         output
     }
 
     // #[instrument(skip_all, level = "trace", name = "From<u32> for SymbolicExprEF")]
     fn from_wrapped_u32(n: u32) -> Self {
         let output = SymbolicExprEF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::e_assign_c(output, EF::from_wrapped_u32(n)));
-        drop(code);
+        TraceContext::push(Instruction32::e_assign_c(output, EF::from_wrapped_u32(n)));
         output
     }
 
     // #[instrument(skip_all, level = "trace", name = "From<u64> for SymbolicExprEF")]
     fn from_wrapped_u64(n: u64) -> Self {
         let output = SymbolicExprEF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::e_assign_c(output, EF::from_wrapped_u64(n)));
-        drop(code);
+        TraceContext::push(Instruction32::e_assign_c(output, EF::from_wrapped_u64(n)));
         output
     }
 
     // #[instrument(skip_all, level = "trace", name = "Generator for SymbolicExprEF")]
     fn generator() -> Self {
         let output = SymbolicExprEF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::e_assign_c(output, EF::generator()));
-        drop(code);
+        TraceContext::push(Instruction32::e_assign_c(output, EF::generator()));
         output
     }
 }
@@ -386,9 +322,7 @@ impl From<SymbolicExprF> for SymbolicExprEF {
     // #[instrument(skip_all, level = "trace", name = "From<SymbolicExprF> for SymbolicExprEF")]
     fn from(value: SymbolicExprF) -> Self {
         let output = SymbolicExprEF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::ef_from_e(output, value));
-        drop(code);
+        TraceContext::push(Instruction32::ef_from_e(output, value));
         output
     }
 }
@@ -399,9 +333,7 @@ impl Add<SymbolicExprF> for SymbolicExprEF {
     // #[instrument(skip_all, level = "trace", name = "Add<SymbolicExprF> for SymbolicExprEF")]
     fn add(self, rhs: SymbolicExprF) -> Self::Output {
         let output = SymbolicExprEF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::ef_add_ee(output, self, rhs));
-        drop(code);
+        TraceContext::push(Instruction32::ef_add_ee(output, self, rhs));
         output
     }
 }
@@ -409,9 +341,7 @@ impl Add<SymbolicExprF> for SymbolicExprEF {
 impl AddAssign<SymbolicExprF> for SymbolicExprEF {
     // #[instrument(skip_all, level = "trace", name = "AddAssign<SymbolicExprF> for SymbolicExprEF")]
     fn add_assign(&mut self, rhs: SymbolicExprF) {
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::ef_add_assign_e(*self, rhs));
-        drop(code);
+        TraceContext::push(Instruction32::ef_add_assign_e(*self, rhs));
     }
 }
 
@@ -421,9 +351,7 @@ impl Sub<SymbolicExprF> for SymbolicExprEF {
     // #[instrument(skip_all, level = "trace", name = "Sub<SymbolicExprF> for SymbolicExprEF")]
     fn sub(self, rhs: SymbolicExprF) -> Self::Output {
         let output = SymbolicExprEF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::ef_sub_ee(output, self, rhs));
-        drop(code);
+        TraceContext::push(Instruction32::ef_sub_ee(output, self, rhs));
         output
     }
 }
@@ -431,9 +359,7 @@ impl Sub<SymbolicExprF> for SymbolicExprEF {
 impl SubAssign<SymbolicExprF> for SymbolicExprEF {
     // #[instrument(skip_all, level = "trace", name = "SubAssign<SymbolicExprF> for SymbolicExprEF")]
     fn sub_assign(&mut self, rhs: SymbolicExprF) {
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::ef_sub_assign_e(*self, rhs));
-        drop(code);
+        TraceContext::push(Instruction32::ef_sub_assign_e(*self, rhs));
     }
 }
 
@@ -443,9 +369,7 @@ impl Mul<SymbolicExprF> for SymbolicExprEF {
     // #[instrument(skip_all, level = "trace", name = "Mul<SymbolicExprF> for SymbolicExprEF")]
     fn mul(self, rhs: SymbolicExprF) -> Self::Output {
         let output = SymbolicExprEF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::ef_mul_ee(output, self, rhs));
-        drop(code);
+        TraceContext::push(Instruction32::ef_mul_ee(output, self, rhs));
         output
     }
 }
@@ -453,9 +377,7 @@ impl Mul<SymbolicExprF> for SymbolicExprEF {
 impl MulAssign<SymbolicExprF> for SymbolicExprEF {
     // #[instrument(skip_all, level = "trace", name = "MulAssign<SymbolicExprF> for SymbolicExprEF")]
     fn mul_assign(&mut self, rhs: SymbolicExprF) {
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::ef_mul_assign_e(*self, rhs));
-        drop(code);
+        TraceContext::push(Instruction32::ef_mul_assign_e(*self, rhs));
     }
 }
 
@@ -464,21 +386,25 @@ impl AbstractExtensionField<SymbolicExprF> for SymbolicExprEF {
 
     fn from_base(value: SymbolicExprF) -> Self {
         let output = SymbolicExprEF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::ef_from_e(output, value));
-        drop(code);
+        TraceContext::push(Instruction32::ef_from_e(output, value));
         output
     }
 
     fn from_base_slice(_: &[SymbolicExprF]) -> Self {
-        todo!()
+        TraceContext::fail(CodegenErrorKind::UnsupportedOperation(
+            "SymbolicExprEF::from_base_slice",
+        ));
+        SymbolicExprEF::empty()
     }
 
     fn from_base_fn<F: FnMut(usize) -> SymbolicExprF>(_: F) -> Self {
-        todo!()
+        TraceContext::fail(CodegenErrorKind::UnsupportedOperation("SymbolicExprEF::from_base_fn"));
+        SymbolicExprEF::empty()
     }
 
     fn as_base_slice(&self) -> &[SymbolicExprF] {
-        todo!()
+        TraceContext::fail(CodegenErrorKind::UnsupportedOperation("SymbolicExprEF::as_base_slice"));
+        // Placeholders keep indexing callers tracing until the error is reported.
+        &[SymbolicExprF(u32::MAX); 4]
     }
 }
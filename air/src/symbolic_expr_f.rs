@@ -4,8 +4,8 @@ use std::{
 };
 
 use crate::{
-    instruction::Instruction32, symbolic_var_f::SymbolicVarF, CUDA_P3_EVAL_CODE,
-    CUDA_P3_EVAL_EXPR_F_CTR, F,
+    error::CodegenErrorKind, instruction::Instruction32, symbolic_var_f::SymbolicVarF,
+    trace::TraceContext, F,
 };
 
 use p3_field::AbstractField;
@@ -22,11 +22,7 @@ impl SymbolicExprF {
 
     // #[instrument(skip_all, level = "trace", name = "Alloc for SymbolicExprF")]
     pub fn alloc() -> Self {
-        let mut tmp = CUDA_P3_EVAL_EXPR_F_CTR.lock().unwrap();
-        let id = *tmp;
-        *tmp += 1;
-        drop(tmp);
-        Self(id)
+        Self(TraceContext::with(TraceContext::alloc_f))
     }
 
     pub fn variant(&self) -> u8 {
@@ -42,9 +38,7 @@ impl Default for SymbolicExprF {
     // #[instrument(skip_all, level = "trace", name = "Default for SymbolicExprF")]
     fn default() -> Self {
         let output = SymbolicExprF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::f_assign_c(output, F::zero()));
-        drop(code);
+        TraceContext::push(Instruction32::f_assign_c(output, F::zero()));
         output
     }
 }
@@ -53,9 +47,7 @@ impl From<F> for SymbolicExprF {
     // #[instrument(skip_all, level = "trace", name = "From<F> for SymbolicExprF")]
     fn from(f: F) -> Self {
         let output = SymbolicExprF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::f_assign_c(output, f));
-        drop(code);
+        TraceContext::push(Instruction32::f_assign_c(output, f));
         output
     }
 }
@@ -66,9 +58,7 @@ impl Add<F> for SymbolicExprF {
     // #[instrument(skip_all, level = "trace", name = "Add<F> for SymbolicExprF")]
     fn add(self, rhs: F) -> Self::Output {
         let output = SymbolicExprF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::f_add_ec(output, self, rhs));
-        drop(code);
+        TraceContext::push(Instruction32::f_add_ec(output, self, rhs));
         output
     }
 }
@@ -79,9 +69,7 @@ impl Add<SymbolicVarF> for SymbolicExprF {
     // #[instrument(skip_all, level = "trace", name = "Add<SymbolicVarF> for SymbolicExprF")]
     fn add(self, rhs: SymbolicVarF) -> Self::Output {
         let output = SymbolicExprF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::f_add_ev(output, self, rhs));
-        drop(code);
+        TraceContext::push(Instruction32::f_add_ev(output, self, rhs));
         output
     }
 }
@@ -92,9 +80,7 @@ impl Add<SymbolicExprF> for SymbolicExprF {
     // #[instrument(skip_all, level = "trace", name = "Add<SymbolicExprF> for SymbolicExprF")]
     fn add(self, rhs: SymbolicExprF) -> Self::Output {
         let output = SymbolicExprF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::f_add_ee(output, self, rhs));
-        drop(code);
+        TraceContext::push(Instruction32::f_add_ee(output, self, rhs));
         output
     }
 }
@@ -102,7 +88,7 @@ impl Add<SymbolicExprF> for SymbolicExprF {
 impl AddAssign<SymbolicExprF> for SymbolicExprF {
     // #[instrument(skip_all, level = "trace", name = "AddAssign<SymbolicExprF> for SymbolicExprF")]
     fn add_assign(&mut self, _: SymbolicExprF) {
-        unreachable!()
+        TraceContext::fail(CodegenErrorKind::UnsupportedOperation("SymbolicExprF::add_assign"));
     }
 }
 
@@ -112,9 +98,7 @@ impl Sub<F> for SymbolicExprF {
     // #[instrument(skip_all, level = "trace", name = "Sub<F> for SymbolicExprF")]
     fn sub(self, rhs: F) -> Self::Output {
         let output = SymbolicExprF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::f_sub_ec(output, self, rhs));
-        drop(code);
+        TraceContext::push(Instruction32::f_sub_ec(output, self, rhs));
         output
     }
 }
@@ -125,9 +109,7 @@ impl Sub<SymbolicVarF> for SymbolicExprF {
     // #[instrument(skip_all, level = "trace", name = "Sub<SymbolicVarF> for SymbolicExprF")]
     fn sub(self, rhs: SymbolicVarF) -> Self::Output {
         let output = SymbolicExprF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::f_sub_ev(output, self, rhs));
-        drop(code);
+        TraceContext::push(Instruction32::f_sub_ev(output, self, rhs));
         output
     }
 }
@@ -138,9 +120,7 @@ impl Sub<SymbolicExprF> for SymbolicExprF {
     // #[instrument(skip_all, level = "trace", name = "Sub<SymbolicExprF> for SymbolicExprF")]
     fn sub(self, rhs: SymbolicExprF) -> Self::Output {
         let output = SymbolicExprF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::f_sub_ee(output, self, rhs));
-        drop(code);
+        TraceContext::push(Instruction32::f_sub_ee(output, self, rhs));
         output
     }
 }
@@ -148,7 +128,7 @@ impl Sub<SymbolicExprF> for SymbolicExprF {
 impl SubAssign<SymbolicExprF> for SymbolicExprF {
     // #[instrument(skip_all, level = "trace", name = "SubAssign<SymbolicExprF> for SymbolicExprF")]
     fn sub_assign(&mut self, _: SymbolicExprF) {
-        unreachable!()
+        TraceContext::fail(CodegenErrorKind::UnsupportedOperation("SymbolicExprF::sub_assign"));
     }
 }
 
@@ -158,9 +138,7 @@ impl Mul<F> for SymbolicExprF {
     // #[instrument(skip_all, level = "trace", name = "Mul<F> for SymbolicExprF")]
     fn mul(self, rhs: F) -> Self::Output {
         let output = SymbolicExprF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::f_mul_ec(output, self, rhs));
-        drop(code);
+        TraceContext::push(Instruction32::f_mul_ec(output, self, rhs));
         output
     }
 }
@@ -171,9 +149,7 @@ impl Mul<SymbolicVarF> for SymbolicExprF {
     // #[instrument(skip_all, level = "trace", name = "Mul<SymbolicVarF> for SymbolicExprF")]
     fn mul(self, rhs: SymbolicVarF) -> Self::Output {
         let output = SymbolicExprF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::f_mul_ev(output, self, rhs));
-        drop(code);
+        TraceContext::push(Instruction32::f_mul_ev(output, self, rhs));
         output
     }
 }
@@ -184,9 +160,7 @@ impl Mul<SymbolicExprF> for SymbolicExprF {
     // #[instrument(skip_all, level = "trace", name = "Mul<SymbolicExprF> for SymbolicExprF")]
     fn mul(self, rhs: SymbolicExprF) -> Self::Output {
         let output = SymbolicExprF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::f_mul_ee(output, self, rhs));
-        drop(code);
+        TraceContext::push(Instruction32::f_mul_ee(output, self, rhs));
         output
     }
 }
@@ -194,7 +168,7 @@ impl Mul<SymbolicExprF> for SymbolicExprF {
 impl MulAssign<SymbolicExprF> for SymbolicExprF {
     // #[instrument(skip_all, level = "trace", name = "MulAssign<SymbolicExprF> for SymbolicExprF")]
     fn mul_assign(&mut self, _: SymbolicExprF) {
-        unreachable!()
+        TraceContext::fail(CodegenErrorKind::UnsupportedOperation("SymbolicExprF::mul_assign"));
     }
 }
 
@@ -204,9 +178,7 @@ impl Neg for SymbolicExprF {
     // #[instrument(skip_all, level = "trace", name = "Neg for SymbolicExprF")]
     fn neg(self) -> Self::Output {
         let output = SymbolicExprF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::f_neg_e(output, self));
-        drop(code);
+        TraceContext::push(Instruction32::f_neg_e(output, self));
         output
     }
 }
@@ -238,9 +210,7 @@ impl Clone for SymbolicExprF {
     // #[instrument(skip_all, level = "trace", name = "Clone for SymbolicExprF")]
     fn clone(&self) -> Self {
         // let output = SymbolicExprF::alloc();
-        // let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        // code.push(Instruction32::f_assign_e(output, *self));
-        // drop(code);
+        // TraceContext::push(Instruction32::f_assign_e(output, *self));
         // output
         *self
     }
@@ -252,126 +222,98 @@ impl AbstractField for SymbolicExprF {
     // #[instrument(skip_all, level = "trace", name = "Zero for SymbolicExprF")]
     fn zero() -> Self {
         let output = SymbolicExprF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::f_assign_c(output, F::zero()));
-        drop(code);
+        TraceContext::push(Instruction32::f_assign_c(output, F::zero()));
         output
     }
 
     // #[instrument(skip_all, level = "trace", name = "One for SymbolicExprF")]
     fn one() -> Self {
         let output = SymbolicExprF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::f_assign_c(output, F::one()));
-        drop(code);
+        TraceContext::push(Instruction32::f_assign_c(output, F::one()));
         output
     }
 
     // #[instrument(skip_all, level = "trace", name = "Two for SymbolicExprF")]
     fn two() -> Self {
         let output = SymbolicExprF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::f_assign_c(output, F::two()));
-        drop(code);
+        TraceContext::push(Instruction32::f_assign_c(output, F::two()));
         output
     }
 
     // #[instrument(skip_all, level = "trace", name = "NegOne for SymbolicExprF")]
     fn neg_one() -> Self {
         let output = SymbolicExprF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::f_assign_c(output, F::neg_one()));
-        drop(code);
+        TraceContext::push(Instruction32::f_assign_c(output, F::neg_one()));
         output
     }
 
     // #[instrument(skip_all, level = "trace", name = "From<F> for SymbolicExprF")]
     fn from_f(f: Self::F) -> Self {
         let output = SymbolicExprF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::f_assign_c(output, f));
-        drop(code);
+        TraceContext::push(Instruction32::f_assign_c(output, f));
         output
     }
 
     // #[instrument(skip_all, level = "trace", name = "From<bool> for SymbolicExprF")]
     fn from_bool(b: bool) -> Self {
         let output = SymbolicExprF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::f_assign_c(output, F::from_bool(b)));
-        drop(code);
+        TraceContext::push(Instruction32::f_assign_c(output, F::from_bool(b)));
         output
     }
 
     // #[instrument(skip_all, level = "trace", name = "From<u8> for SymbolicExprF")]
     fn from_canonical_u8(n: u8) -> Self {
         let output = SymbolicExprF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::f_assign_c(output, F::from_canonical_u8(n)));
-        drop(code);
+        TraceContext::push(Instruction32::f_assign_c(output, F::from_canonical_u8(n)));
         output
     }
 
     // #[instrument(skip_all, level = "trace", name = "From<u16> for SymbolicExprF")]
     fn from_canonical_u16(n: u16) -> Self {
         let output = SymbolicExprF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::f_assign_c(output, F::from_canonical_u16(n)));
-        drop(code);
+        TraceContext::push(Instruction32::f_assign_c(output, F::from_canonical_u16(n)));
         output
     }
 
     // #[instrument(skip_all, level = "trace", name = "From<u32> for SymbolicExprF")]
     fn from_canonical_u32(n: u32) -> Self {
         let output = SymbolicExprF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::f_assign_c(output, F::from_canonical_u32(n)));
-        drop(code);
+        TraceContext::push(Instruction32::f_assign_c(output, F::from_canonical_u32(n)));
         output
     }
 
     // #[instrument(skip_all, level = "trace", name = "From<u64> for SymbolicExprF")]
     fn from_canonical_u64(n: u64) -> Self {
         let output = SymbolicExprF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::f_assign_c(output, F::from_canonical_u64(n)));
-        drop(code);
+        TraceContext::push(Instruction32::f_assign_c(output, F::from_canonical_u64(n)));
         output
     }
 
     // #[instrument(skip_all, level = "trace", name = "From<usize> for SymbolicExprF")]
     fn from_canonical_usize(n: usize) -> Self {
         let output = SymbolicExprF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::f_assign_c(output, F::from_canonical_usize(n)));
-        drop(code);
+        TraceContext::push(Instruction32::f_assign_c(output, F::from_canonical_usize(n)));
         output
     }
 
     // #[instrument(skip_all, level = "trace", name = "From<u32> for SymbolicExprF")]
     fn from_wrapped_u32(n: u32) -> Self {
         let output = SymbolicExprF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::f_assign_c(output, F::from_wrapped_u32(n)));
-        drop(code);
+        TraceContext::push(Instruction32::f_assign_c(output, F::from_wrapped_u32(n)));
         output
     }
 
     // #[instrument(skip_all, level = "trace", name = "From<u64> for SymbolicExprF")]
     fn from_wrapped_u64(n: u64) -> Self {
         let output = SymbolicExprF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::f_assign_c(output, F::from_wrapped_u64(n)));
-        drop(code);
+        TraceContext::push(Instruction32::f_assign_c(output, F::from_wrapped_u64(n)));
         output
     }
 
     // #[instrument(skip_all, level = "trace", name = "Generator for SymbolicExprF")]
     fn generator() -> Self {
         let output = SymbolicExprF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::f_assign_c(output, F::generator()));
-        drop(code);
+        TraceContext::push(Instruction32::f_assign_c(output, F::generator()));
         output
     }
 }
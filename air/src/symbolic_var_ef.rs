@@ -1,7 +1,9 @@
 use std::fmt::Debug;
 use std::ops::{Add, Mul, Sub};
 
-use crate::{instruction::Instruction32, symbolic_expr_ef::SymbolicExprEF, CUDA_P3_EVAL_CODE, EF};
+use crate::{
+    instruction::Instruction32, symbolic_expr_ef::SymbolicExprEF, trace::TraceContext, EF,
+};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SymbolicVarEF {
@@ -10,6 +12,8 @@ pub enum SymbolicVarEF {
     PermutationNext(u32),
     PermutationChallenge(u32),
     CumulativeSum(u32),
+    /// The random challenge constraints are folded with.
+    Alpha,
 }
 
 impl SymbolicVarEF {
@@ -38,16 +42,36 @@ impl SymbolicVarEF {
         Self::CumulativeSum(idx)
     }
 
-    pub fn variant(&self) -> u8 {
+    pub fn alpha() -> Self {
+        Self::Alpha
+    }
+
+    pub const fn variant(&self) -> u8 {
         match self {
             Self::Empty => 0x00,
             Self::PermutationLocal(_) => 0x01,
             Self::PermutationNext(_) => 0x02,
             Self::PermutationChallenge(_) => 0x03,
             Self::CumulativeSum(_) => 0x04,
+            Self::Alpha => 0x05,
         }
     }
 
+    /// The variable with tag `variant` and payload `data`, the inverse of [`Self::variant`] and
+    /// [`Self::data`]. Returns `None` for an unknown tag.
+    pub fn decode(variant: u8, data: u32) -> Option<Self> {
+        let var = match variant {
+            0x00 => Self::Empty,
+            0x01 => Self::PermutationLocal(data),
+            0x02 => Self::PermutationNext(data),
+            0x03 => Self::PermutationChallenge(data),
+            0x04 => Self::CumulativeSum(data),
+            0x05 => Self::Alpha,
+            _ => return None,
+        };
+        Some(var)
+    }
+
     pub fn data(&self) -> u32 {
         match self {
             Self::Empty => 0,
@@ -55,6 +79,7 @@ impl SymbolicVarEF {
             Self::PermutationNext(idx) => *idx,
             Self::PermutationChallenge(idx) => *idx,
             Self::CumulativeSum(idx) => *idx,
+            Self::Alpha => 0,
         }
     }
 }
@@ -63,9 +88,7 @@ impl From<SymbolicVarEF> for SymbolicExprEF {
     // #[instrument(skip_all, level = "trace", name = "From<SymbolicVarEF> for SymbolicExprEF")]
     fn from(value: SymbolicVarEF) -> Self {
         let output = SymbolicExprEF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::e_assign_v(output, value));
-        drop(code);
+        TraceContext::push(Instruction32::e_assign_v(output, value));
         output
     }
 }
@@ -76,9 +99,7 @@ impl Add<EF> for SymbolicVarEF {
     // #[instrument(skip_all, level = "trace", name = "Add<EF> for SymbolicVarEF")]
     fn add(self, rhs: EF) -> Self::Output {
         let output = SymbolicExprEF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::e_add_vc(output, self, rhs));
-        drop(code);
+        TraceContext::push(Instruction32::e_add_vc(output, self, rhs));
         output
     }
 }
@@ -89,9 +110,7 @@ impl Add<SymbolicVarEF> for SymbolicVarEF {
     // #[instrument(skip_all, level = "trace", name = "Add<SymbolicVarEF> for SymbolicVarEF")]
     fn add(self, rhs: SymbolicVarEF) -> Self::Output {
         let output = SymbolicExprEF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::e_add_vv(output, self, rhs));
-        drop(code);
+        TraceContext::push(Instruction32::e_add_vv(output, self, rhs));
         output
     }
 }
@@ -102,9 +121,7 @@ impl Add<SymbolicExprEF> for SymbolicVarEF {
     // #[instrument(skip_all, level = "trace", name = "Add<SymbolicExprEF> for SymbolicVarEF")]
     fn add(self, rhs: SymbolicExprEF) -> Self::Output {
         let output = SymbolicExprEF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::e_add_ve(output, self, rhs));
-        drop(code);
+        TraceContext::push(Instruction32::e_add_ve(output, self, rhs));
         output
     }
 }
@@ -115,9 +132,7 @@ impl Sub<EF> for SymbolicVarEF {
     // #[instrument(skip_all, level = "trace", name = "Sub<EF> for SymbolicVarEF")]
     fn sub(self, rhs: EF) -> Self::Output {
         let output = SymbolicExprEF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::e_sub_vc(output, self, rhs));
-        drop(code);
+        TraceContext::push(Instruction32::e_sub_vc(output, self, rhs));
         output
     }
 }
@@ -128,9 +143,7 @@ impl Sub<SymbolicVarEF> for SymbolicVarEF {
     // #[instrument(skip_all, level = "trace", name = "Sub<SymbolicVarEF> for SymbolicVarEF")]
     fn sub(self, rhs: SymbolicVarEF) -> Self::Output {
         let output = SymbolicExprEF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::e_sub_vv(output, self, rhs));
-        drop(code);
+        TraceContext::push(Instruction32::e_sub_vv(output, self, rhs));
         output
     }
 }
@@ -141,9 +154,7 @@ impl Sub<SymbolicExprEF> for SymbolicVarEF {
     // #[instrument(skip_all, level = "trace", name = "Sub<SymbolicExprEF> for SymbolicVarEF")]
     fn sub(self, rhs: SymbolicExprEF) -> Self::Output {
         let output = SymbolicExprEF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::e_sub_ve(output, self, rhs));
-        drop(code);
+        TraceContext::push(Instruction32::e_sub_ve(output, self, rhs));
         output
     }
 }
@@ -154,9 +165,7 @@ impl Mul<EF> for SymbolicVarEF {
     // #[instrument(skip_all, level = "trace", name = "Mul<EF> for SymbolicVarEF")]
     fn mul(self, rhs: EF) -> Self::Output {
         let output = SymbolicExprEF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::e_mul_vc(output, self, rhs));
-        drop(code);
+        TraceContext::push(Instruction32::e_mul_vc(output, self, rhs));
         output
     }
 }
@@ -167,9 +176,7 @@ impl Mul<SymbolicVarEF> for SymbolicVarEF {
     // #[instrument(skip_all, level = "trace", name = "Mul<SymbolicVarEF> for SymbolicVarEF")]
     fn mul(self, rhs: SymbolicVarEF) -> Self::Output {
         let output = SymbolicExprEF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::e_mul_vv(output, self, rhs));
-        drop(code);
+        TraceContext::push(Instruction32::e_mul_vv(output, self, rhs));
         output
     }
 }
@@ -180,9 +187,7 @@ impl Mul<SymbolicExprEF> for SymbolicVarEF {
     // #[instrument(skip_all, level = "trace", name = "Mul<SymbolicExprEF> for SymbolicVarEF")]
     fn mul(self, rhs: SymbolicExprEF) -> Self::Output {
         let output = SymbolicExprEF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::e_mul_ve(output, self, rhs));
-        drop(code);
+        TraceContext::push(Instruction32::e_mul_ve(output, self, rhs));
         output
     }
 }
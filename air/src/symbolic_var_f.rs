@@ -2,7 +2,7 @@ use std::fmt::Debug;
 use std::ops::{Add, Mul, Sub};
 
 use crate::instruction::f_constant;
-use crate::{instruction::Instruction32, symbolic_expr_f::SymbolicExprF, CUDA_P3_EVAL_CODE, F};
+use crate::{instruction::Instruction32, symbolic_expr_f::SymbolicExprF, trace::TraceContext, F};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum SymbolicVarF {
@@ -17,6 +17,8 @@ pub enum SymbolicVarF {
     IsTransition,
     PublicValue(u32),
     GlobalCumulativeSum(u32),
+    /// The inverse of the trace domain's vanishing polynomial at the current point.
+    InvZeroifier,
 }
 
 impl SymbolicVarF {
@@ -65,7 +67,11 @@ impl SymbolicVarF {
         Self::GlobalCumulativeSum(idx)
     }
 
-    pub fn variant(&self) -> u8 {
+    pub fn inv_zeroifier() -> Self {
+        Self::InvZeroifier
+    }
+
+    pub const fn variant(&self) -> u8 {
         match self {
             Self::Empty => 0x00,
             Self::Constant(_) => 0x01,
@@ -78,9 +84,31 @@ impl SymbolicVarF {
             Self::IsTransition => 0x08,
             Self::PublicValue(_) => 0x09,
             Self::GlobalCumulativeSum(_) => 0x0A,
+            Self::InvZeroifier => 0x0B,
         }
     }
 
+    /// The variable with tag `variant` and payload `data`, the inverse of [`Self::variant`] and
+    /// [`Self::data`]. Returns `None` for an unknown tag.
+    pub fn decode(variant: u8, data: u32) -> Option<Self> {
+        let var = match variant {
+            0x00 => Self::Empty,
+            0x01 => Self::Constant(data),
+            0x02 => Self::PreprocessedLocal(data),
+            0x03 => Self::PreprocessedNext(data),
+            0x04 => Self::MainLocal(data),
+            0x05 => Self::MainNext(data),
+            0x06 => Self::IsFirstRow,
+            0x07 => Self::IsLastRow,
+            0x08 => Self::IsTransition,
+            0x09 => Self::PublicValue(data),
+            0x0A => Self::GlobalCumulativeSum(data),
+            0x0B => Self::InvZeroifier,
+            _ => return None,
+        };
+        Some(var)
+    }
+
     pub fn data(&self) -> u32 {
         match self {
             Self::Empty => 0,
@@ -94,6 +122,7 @@ impl SymbolicVarF {
             Self::IsTransition => 0,
             Self::PublicValue(idx) => *idx,
             Self::GlobalCumulativeSum(idx) => *idx,
+            Self::InvZeroifier => 0,
         }
     }
 }
@@ -102,9 +131,7 @@ impl From<SymbolicVarF> for SymbolicExprF {
     // #[instrument(skip_all, level = "trace", name = "From<SymbolicVarF> for SymbolicExprF")]
     fn from(val: SymbolicVarF) -> Self {
         let output = SymbolicExprF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::f_assign_v(output, val));
-        drop(code);
+        TraceContext::push(Instruction32::f_assign_v(output, val));
         output
     }
 }
@@ -115,9 +142,7 @@ impl Add<F> for SymbolicVarF {
     // #[instrument(skip_all, level = "trace", name = "Add<F> for SymbolicVarF")]
     fn add(self, rhs: F) -> Self::Output {
         let output = SymbolicExprF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::f_add_vc(output, self, rhs));
-        drop(code);
+        TraceContext::push(Instruction32::f_add_vc(output, self, rhs));
         output
     }
 }
@@ -128,9 +153,7 @@ impl Add<SymbolicVarF> for SymbolicVarF {
     // #[instrument(skip_all, level = "trace", name = "Add<SymbolicVarF> for SymbolicVarF")]
     fn add(self, rhs: SymbolicVarF) -> Self::Output {
         let output = SymbolicExprF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::f_add_vv(output, self, rhs));
-        drop(code);
+        TraceContext::push(Instruction32::f_add_vv(output, self, rhs));
         output
     }
 }
@@ -141,9 +164,7 @@ impl Add<SymbolicExprF> for SymbolicVarF {
     // #[instrument(skip_all, level = "trace", name = "Add<SymbolicExprF> for SymbolicVarF")]
     fn add(self, rhs: SymbolicExprF) -> Self::Output {
         let output = SymbolicExprF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::f_add_ve(output, self, rhs));
-        drop(code);
+        TraceContext::push(Instruction32::f_add_ve(output, self, rhs));
         output
     }
 }
@@ -154,9 +175,7 @@ impl Sub<F> for SymbolicVarF {
     // #[instrument(skip_all, level = "trace", name = "Sub<F> for SymbolicVarF")]
     fn sub(self, rhs: F) -> Self::Output {
         let output = SymbolicExprF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::f_sub_vc(output, self, rhs));
-        drop(code);
+        TraceContext::push(Instruction32::f_sub_vc(output, self, rhs));
         output
     }
 }
@@ -167,9 +186,7 @@ impl Sub<SymbolicVarF> for SymbolicVarF {
     // #[instrument(skip_all, level = "trace", name = "Sub<SymbolicVarF> for SymbolicVarF")]
     fn sub(self, rhs: SymbolicVarF) -> Self::Output {
         let output = SymbolicExprF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::f_sub_vv(output, self, rhs));
-        drop(code);
+        TraceContext::push(Instruction32::f_sub_vv(output, self, rhs));
         output
     }
 }
@@ -180,9 +197,7 @@ impl Sub<SymbolicExprF> for SymbolicVarF {
     // #[instrument(skip_all, level = "trace", name = "Sub<SymbolicExprF> for SymbolicVarF")]
     fn sub(self, rhs: SymbolicExprF) -> Self::Output {
         let output = SymbolicExprF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::f_sub_ve(output, self, rhs));
-        drop(code);
+        TraceContext::push(Instruction32::f_sub_ve(output, self, rhs));
         output
     }
 }
@@ -193,9 +208,7 @@ impl Mul<F> for SymbolicVarF {
     // #[instrument(skip_all, level = "trace", name = "Mul<F> for SymbolicVarF")]
     fn mul(self, rhs: F) -> Self::Output {
         let output = SymbolicExprF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::f_mul_vc(output, self, rhs));
-        drop(code);
+        TraceContext::push(Instruction32::f_mul_vc(output, self, rhs));
         output
     }
 }
@@ -206,9 +219,7 @@ impl Mul<SymbolicVarF> for SymbolicVarF {
     // #[instrument(skip_all, level = "trace", name = "Mul<SymbolicVarF> for SymbolicVarF")]
     fn mul(self, rhs: SymbolicVarF) -> Self::Output {
         let output = SymbolicExprF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::f_mul_vv(output, self, rhs));
-        drop(code);
+        TraceContext::push(Instruction32::f_mul_vv(output, self, rhs));
         output
     }
 }
@@ -219,9 +230,7 @@ impl Mul<SymbolicExprF> for SymbolicVarF {
     // #[instrument(skip_all, level = "trace", name = "Mul<SymbolicExprF> for SymbolicVarF")]
     fn mul(self, rhs: SymbolicExprF) -> Self::Output {
         let output = SymbolicExprF::alloc();
-        let mut code = CUDA_P3_EVAL_CODE.lock().unwrap();
-        code.push(Instruction32::f_mul_ve(output, self, rhs));
-        drop(code);
+        TraceContext::push(Instruction32::f_mul_ve(output, self, rhs));
         output
     }
 }
@@ -0,0 +1,243 @@
+use std::{
+    cell::RefCell,
+    collections::{hash_map::Entry, HashMap},
+    hash::Hash,
+    marker::PhantomData,
+};
+
+use crate::{error::CodegenErrorKind, instruction::Instruction32, EF, F};
+
+thread_local! {
+    static TRACE_CONTEXT: RefCell<TraceContext> = RefCell::new(TraceContext::default());
+}
+
+/// The state recorded while symbolically tracing a chip.
+///
+/// Every thread owns its own context, so chips traced on different threads never observe each
+/// other's code, constants or expression counters.
+#[derive(Debug, Default, Clone)]
+pub struct TraceContext {
+    pub code: Vec<Instruction32>,
+    f_constants: Vec<F>,
+    ef_constants: Vec<EF>,
+    pub expr_f_ctr: u32,
+    pub expr_ef_ctr: u32,
+    /// The first failure hit while tracing, if any.
+    pub error: Option<CodegenErrorKind>,
+    f_index: PoolIndex<F>,
+    ef_index: PoolIndex<EF>,
+}
+
+/// How well a constant pool deduplicated the constants of a trace.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PoolStats {
+    /// The number of distinct constants in the pool.
+    pub distinct: usize,
+    /// The number of lookups that found their constant already in the pool.
+    pub hits: usize,
+}
+
+/// The statistics of both constant pools of a traced chip.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ConstantStats {
+    pub f: PoolStats,
+    pub ef: PoolStats,
+}
+
+/// The position of every constant in a constant pool, so interning does not depend on the size
+/// of the pool.
+#[derive(Debug, Clone)]
+pub(crate) struct PoolIndex<T> {
+    positions: HashMap<T, u32>,
+    hits: usize,
+}
+
+impl<T> Default for PoolIndex<T> {
+    fn default() -> Self {
+        Self { positions: HashMap::new(), hits: 0 }
+    }
+}
+
+impl<T: Copy + Eq + Hash> PoolIndex<T> {
+    /// Indexes the constants already in `pool`. Duplicates resolve to their first position.
+    pub(crate) fn new(pool: &[T]) -> Self {
+        let mut positions = HashMap::with_capacity(pool.len());
+        for (i, &c) in pool.iter().enumerate() {
+            positions.entry(c).or_insert(i as u32);
+        }
+        Self { positions, hits: 0 }
+    }
+
+    /// Returns the position of `c` in `pool`, appending it if it is new. `pool` must only grow
+    /// through this index.
+    pub(crate) fn intern(&mut self, pool: &mut Vec<T>, c: T) -> u32 {
+        match self.positions.entry(c) {
+            Entry::Occupied(entry) => {
+                self.hits += 1;
+                *entry.get()
+            }
+            Entry::Vacant(entry) => {
+                pool.push(c);
+                *entry.insert((pool.len() - 1) as u32)
+            }
+        }
+    }
+}
+
+impl TraceContext {
+    /// Runs `f` with mutable access to the current thread's trace context.
+    pub fn with<R>(f: impl FnOnce(&mut TraceContext) -> R) -> R {
+        TRACE_CONTEXT.with(|ctx| f(&mut ctx.borrow_mut()))
+    }
+
+    /// Appends an instruction to the current thread's trace.
+    pub fn push(instr: Instruction32) {
+        Self::with(|ctx| ctx.code.push(instr));
+    }
+
+    /// Takes the current thread's trace, leaving an empty context in its place.
+    pub fn take() -> TraceContext {
+        TRACE_CONTEXT.with(|ctx| ctx.take())
+    }
+
+    /// Records a failure on the current thread's trace. Only the first failure is kept.
+    pub fn fail(kind: CodegenErrorKind) {
+        Self::with(|ctx| {
+            ctx.error.get_or_insert(kind);
+        });
+    }
+
+    /// Discards everything recorded on the current thread.
+    pub fn reset() {
+        Self::take();
+    }
+
+    pub fn alloc_f(&mut self) -> u32 {
+        let id = self.expr_f_ctr;
+        self.expr_f_ctr += 1;
+        id
+    }
+
+    pub fn alloc_ef(&mut self) -> u32 {
+        let id = self.expr_ef_ctr;
+        self.expr_ef_ctr += 1;
+        id
+    }
+
+    /// The F constants recorded so far, in the order they were first seen.
+    pub fn f_constants(&self) -> &[F] {
+        &self.f_constants
+    }
+
+    /// The EF constants recorded so far, in the order they were first seen.
+    pub fn ef_constants(&self) -> &[EF] {
+        &self.ef_constants
+    }
+
+    /// Consumes the trace, returning its code and both constant pools.
+    pub fn into_parts(self) -> (Vec<Instruction32>, Vec<F>, Vec<EF>) {
+        (self.code, self.f_constants, self.ef_constants)
+    }
+
+    /// The position of `c` in the F constant pool, appending it if it is new.
+    pub fn f_constant(&mut self, c: F) -> u32 {
+        self.f_index.intern(&mut self.f_constants, c)
+    }
+
+    /// The position of `c` in the EF constant pool, appending it if it is new.
+    pub fn ef_constant(&mut self, c: EF) -> u32 {
+        self.ef_index.intern(&mut self.ef_constants, c)
+    }
+
+    /// How well both constant pools have deduplicated so far.
+    pub fn constant_stats(&self) -> ConstantStats {
+        ConstantStats {
+            f: PoolStats { distinct: self.f_constants.len(), hits: self.f_index.hits },
+            ef: PoolStats { distinct: self.ef_constants.len(), hits: self.ef_index.hits },
+        }
+    }
+}
+
+/// Scopes a trace to the current thread.
+///
+/// Starting a session discards anything left behind on the thread, and dropping it discards
+/// anything that was not taken with [`TraceSession::finish`], including while unwinding from a
+/// panic in a chip's `eval`. Every piece of state, including both constant pools, starts empty.
+pub struct TraceSession {
+    // The session guards the current thread's context, so it must not move to another thread.
+    _thread: PhantomData<*const ()>,
+}
+
+impl TraceSession {
+    pub fn start() -> Self {
+        TraceContext::reset();
+        Self { _thread: PhantomData }
+    }
+
+    /// Ends the session and returns everything it recorded.
+    pub fn finish(self) -> TraceContext {
+        TraceContext::take()
+    }
+}
+
+impl Drop for TraceSession {
+    fn drop(&mut self) {
+        TraceContext::reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic;
+
+    use p3_field::AbstractField;
+
+    use crate::{symbolic_var_f::SymbolicVarF, EF, F};
+
+    use super::{ConstantStats, PoolStats, TraceContext, TraceSession};
+
+    #[test]
+    fn test_session_discards_state_on_panic() {
+        let result = panic::catch_unwind(|| {
+            let _session = TraceSession::start();
+            let _ = SymbolicVarF::main_local(0) + F::from_canonical_u32(7);
+            panic!("chip eval failed");
+        });
+        assert!(result.is_err());
+
+        let trace = TraceContext::take();
+        assert_eq!(trace.constant_stats(), ConstantStats::default());
+        assert_eq!((trace.expr_f_ctr, trace.expr_ef_ctr, &trace.error), (0, 0, &None));
+        let (code, f_constants, ef_constants) = trace.into_parts();
+        assert!(code.is_empty() && f_constants.is_empty() && ef_constants.is_empty());
+    }
+
+    #[test]
+    fn test_session_starts_empty() {
+        TraceContext::with(|ctx| ctx.f_constant(F::two()));
+        let session = TraceSession::start();
+        let _ = SymbolicVarF::main_local(0) + F::from_canonical_u32(7);
+        let trace = session.finish();
+        assert_eq!(trace.f_constants(), [F::from_canonical_u32(7)]);
+        assert!(TraceContext::take().f_constants().is_empty());
+    }
+
+    #[test]
+    fn test_constant_pools_keep_insertion_order() {
+        let mut ctx = TraceContext::default();
+        let values = [5, 3, 5, 9, 3, 5].map(F::from_canonical_u32);
+        let indices = values.map(|c| ctx.f_constant(c));
+        assert_eq!(indices, [0, 1, 0, 2, 1, 0]);
+        assert_eq!(ctx.f_constants(), [5, 3, 9].map(F::from_canonical_u32));
+        assert_eq!(ctx.ef_constant(EF::two()), 0);
+        assert_eq!(ctx.ef_constant(EF::one()), 1);
+        assert_eq!(ctx.ef_constant(EF::two()), 0);
+        assert_eq!(
+            ctx.constant_stats(),
+            ConstantStats {
+                f: PoolStats { distinct: 3, hits: 3 },
+                ef: PoolStats { distinct: 2, hits: 1 },
+            }
+        );
+    }
+}